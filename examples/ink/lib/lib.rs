@@ -2,6 +2,7 @@
 
 #[ink::contract]
 mod whisper_token {
+    use ink::prelude::{string::String, vec::Vec};
     use ink::storage::Mapping;
 
     /// ERC-20 Token for Polkadot/Substrate using ink!
@@ -13,6 +14,31 @@ mod whisper_token {
         balances: Mapping<AccountId, Balance>,
         /// Mapping from (owner, spender) to allowance
         allowances: Mapping<(AccountId, AccountId), Balance>,
+        /// Account allowed to mint new tokens
+        owner: AccountId,
+        /// Display name shown by wallets
+        name: String,
+        /// Ticker symbol shown by wallets
+        symbol: String,
+        /// Number of decimals a balance is displayed with
+        decimals: u8,
+        /// Whether transfers and approvals are frozen by the owner
+        paused: bool,
+        /// Next `permit` nonce expected for each owner
+        nonces: Mapping<AccountId, u64>,
+        /// Id of the latest snapshot (0 = none taken yet)
+        snapshot_id: u64,
+        /// Total supply recorded by each snapshot
+        snapshot_supplies: Mapping<u64, Balance>,
+        /// Balance an account held at a snapshot, written by its first
+        /// balance change after that snapshot
+        snapshot_balances: Mapping<(AccountId, u64), Balance>,
+        /// Latest snapshot id each account has an entry for in `snapshot_balances`
+        last_checkpoint: Mapping<AccountId, u64>,
+        /// Number of `snapshot_balances` entries each account has
+        checkpoint_counts: Mapping<AccountId, u64>,
+        /// Snapshot id of an account's n-th `snapshot_balances` entry, ascending in n
+        checkpoint_ids: Mapping<(AccountId, u64), u64>,
     }
 
     /// Event emitted when tokens are transferred
@@ -25,6 +51,19 @@ mod whisper_token {
         value: Balance,
     }
 
+    /// Event emitted alongside `Transfer` by `transfer_with_memo`
+    #[ink(event)]
+    pub struct TransferWithMemo {
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+        value: Balance,
+        /// Caller-chosen reference, e.g. a WhisperChain message PDA or hash
+        #[ink(topic)]
+        memo: [u8; 32],
+    }
+
     /// Event emitted when an approval occurs
     #[ink(event)]
     pub struct Approval {
@@ -35,6 +74,27 @@ mod whisper_token {
         value: Balance,
     }
 
+    /// Event emitted when the owner freezes the token
+    #[ink(event)]
+    pub struct Paused {
+        #[ink(topic)]
+        by: AccountId,
+    }
+
+    /// Event emitted when the owner lifts the freeze
+    #[ink(event)]
+    pub struct Unpaused {
+        #[ink(topic)]
+        by: AccountId,
+    }
+
+    /// Event emitted when the owner takes a balance snapshot
+    #[ink(event)]
+    pub struct Snapshot {
+        id: u64,
+        total_supply: Balance,
+    }
+
     /// Errors that can occur upon calling this contract
     #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
@@ -43,15 +103,42 @@ mod whisper_token {
         InsufficientBalance,
         /// Insufficient allowance for transfer
         InsufficientAllowance,
+        /// Caller is not the contract owner
+        NotOwner,
+        /// Operation would overflow the total supply, a balance or an allowance
+        Overflow,
+        /// Token is paused by the owner
+        Paused,
+        /// Permit deadline has passed
+        PermitExpired,
+        /// Permit signature was not made by the owner
+        InvalidSignature,
     }
 
     /// Type alias for the contract's result type
     pub type Result<T> = core::result::Result<T, Error>;
 
     impl WhisperToken {
-        /// Creates a new ERC-20 contract with the specified initial supply
+        /// Creates a new ERC-20 contract with the specified initial supply,
+        /// owned by the caller, using the default WhisperToken metadata
         #[ink(constructor)]
         pub fn new(total_supply: Balance) -> Self {
+            Self::new_with_metadata(
+                total_supply,
+                String::from("WhisperToken"),
+                String::from("WHISPER"),
+                18,
+            )
+        }
+
+        /// Creates a new ERC-20 contract with the given supply and wallet metadata
+        #[ink(constructor)]
+        pub fn new_with_metadata(
+            total_supply: Balance,
+            name: String,
+            symbol: String,
+            decimals: u8,
+        ) -> Self {
             let mut balances = Mapping::default();
             let caller = Self::env().caller();
             balances.insert(caller, &total_supply);
@@ -66,9 +153,39 @@ mod whisper_token {
                 total_supply,
                 balances,
                 allowances: Default::default(),
+                owner: caller,
+                name,
+                symbol,
+                decimals,
+                paused: false,
+                nonces: Default::default(),
+                snapshot_id: 0,
+                snapshot_supplies: Default::default(),
+                snapshot_balances: Default::default(),
+                last_checkpoint: Default::default(),
+                checkpoint_counts: Default::default(),
+                checkpoint_ids: Default::default(),
             }
         }
 
+        /// Returns the token name
+        #[ink(message)]
+        pub fn token_name(&self) -> String {
+            self.name.clone()
+        }
+
+        /// Returns the token symbol
+        #[ink(message)]
+        pub fn token_symbol(&self) -> String {
+            self.symbol.clone()
+        }
+
+        /// Returns the number of decimals used to display balances
+        #[ink(message)]
+        pub fn token_decimals(&self) -> u8 {
+            self.decimals
+        }
+
         /// Returns the total token supply
         #[ink(message)]
         pub fn total_supply(&self) -> Balance {
@@ -87,25 +204,239 @@ mod whisper_token {
             self.allowances.get((owner, spender)).unwrap_or(0)
         }
 
+        /// Returns the nonce the next `permit` signed by `owner` must use
+        #[ink(message)]
+        pub fn nonce_of(&self, owner: AccountId) -> u64 {
+            self.nonces.get(owner).unwrap_or(0)
+        }
+
+        /// Returns the hash `owner` signs to permit `spender` to use `value`
+        ///
+        /// Covers this contract's address and the owner's current nonce, so a
+        /// signature can't be replayed here or against another deployment.
+        #[ink(message)]
+        pub fn permit_hash(
+            &self,
+            owner: AccountId,
+            spender: AccountId,
+            value: Balance,
+            deadline: Timestamp,
+        ) -> [u8; 32] {
+            let payload = (
+                self.env().account_id(),
+                owner,
+                spender,
+                value,
+                self.nonce_of(owner),
+                deadline,
+            );
+            let mut hash = [0u8; 32];
+            ink::env::hash_encoded::<ink::env::hash::Blake2x256, _>(&payload, &mut hash);
+            hash
+        }
+
+        /// Records the current balances and total supply; only the owner may
+        /// snapshot. Returns the new snapshot's id, counting up from 1.
+        ///
+        /// The id comes wrapped in a `Result` rather than bare so that, like
+        /// the other owner-only messages, any other caller gets `NotOwner`.
+        #[ink(message)]
+        pub fn snapshot(&mut self) -> Result<u64> {
+            self.ensure_owner()?;
+            let id = self.snapshot_id.checked_add(1).ok_or(Error::Overflow)?;
+            self.snapshot_id = id;
+            self.snapshot_supplies.insert(id, &self.total_supply);
+
+            self.env().emit_event(Snapshot {
+                id,
+                total_supply: self.total_supply,
+            });
+
+            Ok(id)
+        }
+
+        /// Returns the total supply at `snapshot_id` (0 for an unknown id)
+        #[ink(message)]
+        pub fn total_supply_at(&self, snapshot_id: u64) -> Balance {
+            self.snapshot_supplies.get(snapshot_id).unwrap_or(0)
+        }
+
+        /// Returns the balance `owner` held at `snapshot_id` (0 for an unknown id)
+        ///
+        /// The first checkpoint at or after the snapshot holds the balance
+        /// from before the owner's next change; with none, it hasn't changed.
+        /// The checkpoints are binary searched, one storage read per step.
+        #[ink(message)]
+        pub fn balance_of_at(&self, owner: AccountId, snapshot_id: u64) -> Balance {
+            if snapshot_id == 0 || snapshot_id > self.snapshot_id {
+                return 0;
+            }
+
+            let count = self.checkpoint_counts.get(owner).unwrap_or(0);
+            let (mut low, mut high) = (0, count);
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if self.checkpoint_ids.get((owner, mid)).unwrap_or(0) < snapshot_id {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+
+            if low == count {
+                return self.balance_of(owner);
+            }
+            let id = self.checkpoint_ids.get((owner, low)).unwrap_or(0);
+            self.snapshot_balances.get((owner, id)).unwrap_or(0)
+        }
+
+        /// Returns whether the token is paused
+        #[ink(message)]
+        pub fn is_paused(&self) -> bool {
+            self.paused
+        }
+
+        /// Freezes transfers, approvals and burns; only the owner may pause
+        #[ink(message)]
+        pub fn pause(&mut self) -> Result<()> {
+            let caller = self.ensure_owner()?;
+            self.paused = true;
+            self.env().emit_event(Paused { by: caller });
+            Ok(())
+        }
+
+        /// Lifts a pause; only the owner may unpause
+        #[ink(message)]
+        pub fn unpause(&mut self) -> Result<()> {
+            let caller = self.ensure_owner()?;
+            self.paused = false;
+            self.env().emit_event(Unpaused { by: caller });
+            Ok(())
+        }
+
         /// Transfers `value` amount of tokens from the caller to `to`
         #[ink(message)]
         pub fn transfer(&mut self, to: AccountId, value: Balance) -> Result<()> {
+            self.ensure_not_paused()?;
             let from = self.env().caller();
             self.transfer_from_to(&from, &to, value)
         }
 
+        /// Transfers like `transfer` and tags the payment with a 32-byte `memo`
+        #[ink(message)]
+        pub fn transfer_with_memo(
+            &mut self,
+            to: AccountId,
+            value: Balance,
+            memo: [u8; 32],
+        ) -> Result<()> {
+            self.transfer(to, value)?;
+
+            self.env().emit_event(TransferWithMemo {
+                from: self.env().caller(),
+                to,
+                value,
+                memo,
+            });
+
+            Ok(())
+        }
+
+        /// Transfers from the caller to every recipient, or to none of them
+        ///
+        /// The total is checked against the caller's balance before any
+        /// balance changes, so one overdrawing entry fails the whole batch.
+        #[ink(message)]
+        pub fn batch_transfer(&mut self, recipients: Vec<(AccountId, Balance)>) -> Result<()> {
+            self.ensure_not_paused()?;
+            let from = self.env().caller();
+
+            let total = recipients
+                .iter()
+                .try_fold(0 as Balance, |total, (_, value)| total.checked_add(*value))
+                .ok_or(Error::Overflow)?;
+            if self.balance_of(from) < total {
+                return Err(Error::InsufficientBalance);
+            }
+
+            for (to, value) in recipients {
+                self.transfer_from_to(&from, &to, value)?;
+            }
+
+            Ok(())
+        }
+
         /// Approves `spender` to spend `value` amount of tokens on behalf of caller
         #[ink(message)]
         pub fn approve(&mut self, spender: AccountId, value: Balance) -> Result<()> {
+            self.ensure_not_paused()?;
             let owner = self.env().caller();
-            self.allowances.insert((owner, spender), &value);
+            self.set_allowance(owner, spender, value);
+            Ok(())
+        }
 
-            self.env().emit_event(Approval {
-                owner,
-                spender,
-                value,
-            });
+        /// Raises the caller's allowance for `spender` by `delta`
+        ///
+        /// Unlike `approve`, this adjusts the current value, so a spender
+        /// front-running the change can't use both the old and new allowance.
+        #[ink(message)]
+        pub fn increase_allowance(&mut self, spender: AccountId, delta: Balance) -> Result<()> {
+            self.ensure_not_paused()?;
+            let owner = self.env().caller();
+            let value = self
+                .allowance(owner, spender)
+                .checked_add(delta)
+                .ok_or(Error::Overflow)?;
+            self.set_allowance(owner, spender, value);
+            Ok(())
+        }
 
+        /// Lowers the caller's allowance for `spender` by `delta`
+        #[ink(message)]
+        pub fn decrease_allowance(&mut self, spender: AccountId, delta: Balance) -> Result<()> {
+            self.ensure_not_paused()?;
+            let owner = self.env().caller();
+            let value = self
+                .allowance(owner, spender)
+                .checked_sub(delta)
+                .ok_or(Error::InsufficientAllowance)?;
+            self.set_allowance(owner, spender, value);
+            Ok(())
+        }
+
+        /// Sets `owner`'s allowance for `spender` from an off-chain signature
+        ///
+        /// `signature` is a recoverable ECDSA signature over `permit_hash`;
+        /// `owner` must be the ECDSA account of the signing key, i.e. the
+        /// blake2-256 of its compressed public key. Anyone may submit it.
+        #[ink(message)]
+        pub fn permit(
+            &mut self,
+            owner: AccountId,
+            spender: AccountId,
+            value: Balance,
+            deadline: Timestamp,
+            signature: [u8; 65],
+        ) -> Result<()> {
+            self.ensure_not_paused()?;
+            if self.env().block_timestamp() > deadline {
+                return Err(Error::PermitExpired);
+            }
+
+            let hash = self.permit_hash(owner, spender, value, deadline);
+            let public_key = self
+                .env()
+                .ecdsa_recover(&signature, &hash)
+                .map_err(|_| Error::InvalidSignature)?;
+            let mut signer = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Blake2x256>(&public_key, &mut signer);
+            if AccountId::from(signer) != owner {
+                return Err(Error::InvalidSignature);
+            }
+
+            let nonce = self.nonce_of(owner).checked_add(1).ok_or(Error::Overflow)?;
+            self.nonces.insert(owner, &nonce);
+            self.set_allowance(owner, spender, value);
             Ok(())
         }
 
@@ -117,22 +448,118 @@ mod whisper_token {
             to: AccountId,
             value: Balance,
         ) -> Result<()> {
+            self.ensure_not_paused()?;
             let caller = self.env().caller();
-            let allowance = self.allowance(from, caller);
-
-            if allowance < value {
-                return Err(Error::InsufficientAllowance);
-            }
+            let allowance = self
+                .allowance(from, caller)
+                .checked_sub(value)
+                .ok_or(Error::InsufficientAllowance)?;
 
             self.transfer_from_to(&from, &to, value)?;
 
             // Decrease allowance
-            self.allowances
-                .insert((from, caller), &(allowance - value));
+            self.allowances.insert((from, caller), &allowance);
+
+            Ok(())
+        }
+
+        /// Destroys `value` tokens from the caller, reducing the total supply
+        #[ink(message)]
+        pub fn burn(&mut self, value: Balance) -> Result<()> {
+            self.ensure_not_paused()?;
+            let caller = self.env().caller();
+            let balance = self.balance_of(caller);
+
+            if balance < value {
+                return Err(Error::InsufficientBalance);
+            }
+
+            self.checkpoint(caller);
+            self.balances.insert(caller, &(balance - value));
+            self.total_supply -= value;
+
+            self.env().emit_event(Transfer {
+                from: Some(caller),
+                to: None,
+                value,
+            });
+
+            Ok(())
+        }
+
+        /// Creates `value` new tokens for `to`; only the owner may mint
+        #[ink(message)]
+        pub fn mint(&mut self, to: AccountId, value: Balance) -> Result<()> {
+            self.ensure_owner()?;
+
+            // No balance can exceed the total supply, so this check covers both
+            self.total_supply = self
+                .total_supply
+                .checked_add(value)
+                .ok_or(Error::Overflow)?;
+
+            let to_balance = self.balance_of(to);
+            self.checkpoint(to);
+            self.balances.insert(to, &(to_balance + value));
+
+            self.env().emit_event(Transfer {
+                from: None,
+                to: Some(to),
+                value,
+            });
 
             Ok(())
         }
 
+        /// Internal helper returning the caller if they are the owner
+        fn ensure_owner(&self) -> Result<AccountId> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::NotOwner);
+            }
+            Ok(caller)
+        }
+
+        /// Internal helper rejecting calls while paused
+        fn ensure_not_paused(&self) -> Result<()> {
+            if self.paused {
+                return Err(Error::Paused);
+            }
+            Ok(())
+        }
+
+        /// Internal helper saving `account`'s balance for the latest snapshot
+        /// before the first change to it since that snapshot
+        fn checkpoint(&mut self, account: AccountId) {
+            let id = self.snapshot_id;
+            if id == 0 {
+                return;
+            }
+
+            if self.last_checkpoint.get(account) == Some(id) {
+                return;
+            }
+
+            // An account has at most one checkpoint per snapshot, so the
+            // count stays below `id` and can't overflow
+            let count = self.checkpoint_counts.get(account).unwrap_or(0);
+            self.snapshot_balances.insert((account, id), &self.balance_of(account));
+            self.checkpoint_ids.insert((account, count), &id);
+            self.checkpoint_counts.insert(account, &(count + 1));
+            self.last_checkpoint.insert(account, &id);
+        }
+
+        /// Internal helper storing an allowance and emitting `Approval`
+        fn set_allowance(&mut self, owner: AccountId, spender: AccountId, value: Balance) {
+            self.allowances.insert((owner, spender), &value);
+
+            self.env().emit_event(Approval {
+                owner,
+                spender,
+                value,
+            });
+        }
+
         /// Internal transfer helper
         fn transfer_from_to(
             &mut self,
@@ -140,16 +567,23 @@ mod whisper_token {
             to: &AccountId,
             value: Balance,
         ) -> Result<()> {
-            let from_balance = self.balance_of(*from);
-
-            if from_balance < value {
-                return Err(Error::InsufficientBalance);
-            }
-
-            self.balances.insert(from, &(from_balance - value));
-
-            let to_balance = self.balance_of(*to);
-            self.balances.insert(to, &(to_balance + value));
+            let from_balance = self
+                .balance_of(*from)
+                .checked_sub(value)
+                .ok_or(Error::InsufficientBalance)?;
+
+            // Both sides are checked before either balance is written
+            let to_balance = if from == to {
+                from_balance
+            } else {
+                self.balance_of(*to)
+            };
+            let to_balance = to_balance.checked_add(value).ok_or(Error::Overflow)?;
+
+            self.checkpoint(*from);
+            self.checkpoint(*to);
+            self.balances.insert(from, &from_balance);
+            self.balances.insert(to, &to_balance);
 
             self.env().emit_event(Transfer {
                 from: Some(*from),
@@ -165,12 +599,53 @@ mod whisper_token {
     mod tests {
         use super::*;
 
+        type Event = <WhisperToken as ink::reflect::ContractEventBase>::Type;
+
+        /// Returns the ECDSA account of a fixed test key and a signer for it
+        fn permit_signer() -> (AccountId, impl Fn([u8; 32]) -> [u8; 65]) {
+            use secp256k1::{Message, SecretKey, SECP256K1};
+
+            let secret = SecretKey::from_slice(&[0x42; 32]).unwrap();
+            let public_key = secret.public_key(SECP256K1).serialize();
+            let mut owner = [0u8; 32];
+            ink::env::hash_bytes::<ink::env::hash::Blake2x256>(&public_key, &mut owner);
+
+            let sign = move |hash: [u8; 32]| {
+                let message = Message::from_slice(&hash).unwrap();
+                let (recovery_id, compact) = SECP256K1
+                    .sign_ecdsa_recoverable(&message, &secret)
+                    .serialize_compact();
+                let mut signature = [0u8; 65];
+                signature[..64].copy_from_slice(&compact);
+                signature[64] = recovery_id.to_i32() as u8;
+                signature
+            };
+            (AccountId::from(owner), sign)
+        }
+
         #[ink::test]
         fn new_works() {
             let contract = WhisperToken::new(1000);
             assert_eq!(contract.total_supply(), 1000);
         }
 
+        #[ink::test]
+        fn metadata_works() {
+            let contract = WhisperToken::new_with_metadata(
+                1000,
+                String::from("Test Token"),
+                String::from("TST"),
+                6,
+            );
+            assert_eq!(contract.token_name(), "Test Token");
+            assert_eq!(contract.token_symbol(), "TST");
+            assert_eq!(contract.token_decimals(), 6);
+            assert_eq!(contract.total_supply(), 1000);
+
+            let contract = WhisperToken::new(1000);
+            assert_eq!(contract.token_symbol(), "WHISPER");
+        }
+
         #[ink::test]
         fn balance_works() {
             let contract = WhisperToken::new(100);
@@ -234,5 +709,265 @@ mod whisper_token {
             let result = contract.transfer_from(accounts.alice, accounts.charlie, 25);
             assert_eq!(result, Err(Error::InsufficientAllowance));
         }
+
+        #[ink::test]
+        fn burn_fails_above_balance() {
+            let mut contract = WhisperToken::new(100);
+
+            assert_eq!(contract.burn(101), Err(Error::InsufficientBalance));
+            assert_eq!(contract.total_supply(), 100);
+        }
+
+        #[ink::test]
+        fn burn_reduces_total_supply() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            assert!(contract.burn(30).is_ok());
+            assert_eq!(contract.balance_of(accounts.alice), 70);
+            assert_eq!(contract.total_supply(), 70);
+        }
+
+        #[ink::test]
+        fn mint_works_for_owner_only() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            assert!(contract.mint(accounts.bob, 50).is_ok());
+            assert_eq!(contract.balance_of(accounts.bob), 50);
+            assert_eq!(contract.total_supply(), 150);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+
+            assert_eq!(contract.mint(accounts.bob, 50), Err(Error::NotOwner));
+            assert_eq!(contract.total_supply(), 150);
+        }
+
+        #[ink::test]
+        fn allowance_can_be_adjusted() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            assert!(contract.approve(accounts.bob, 20).is_ok());
+            assert!(contract.increase_allowance(accounts.bob, 15).is_ok());
+            assert!(contract.decrease_allowance(accounts.bob, 5).is_ok());
+            assert_eq!(contract.allowance(accounts.alice, accounts.bob), 30);
+
+            let emitted = ink::env::test::recorded_events().count();
+            assert_eq!(emitted, 4); // initial Transfer plus three Approvals
+        }
+
+        #[ink::test]
+        fn decrease_allowance_fails_below_zero() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            assert!(contract.increase_allowance(accounts.bob, 10).is_ok());
+            assert_eq!(
+                contract.decrease_allowance(accounts.bob, 11),
+                Err(Error::InsufficientAllowance)
+            );
+            assert_eq!(contract.allowance(accounts.alice, accounts.bob), 10);
+        }
+
+        #[ink::test]
+        fn paused_token_rejects_transfers() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            assert!(contract.approve(accounts.bob, 20).is_ok());
+            assert!(contract.pause().is_ok());
+            assert!(contract.is_paused());
+
+            assert_eq!(contract.transfer(accounts.bob, 10), Err(Error::Paused));
+            assert_eq!(contract.approve(accounts.bob, 30), Err(Error::Paused));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                contract.transfer_from(accounts.alice, accounts.bob, 10),
+                Err(Error::Paused)
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert!(contract.unpause().is_ok());
+            assert!(contract.transfer(accounts.bob, 10).is_ok());
+            assert_eq!(contract.balance_of(accounts.bob), 10);
+        }
+
+        #[ink::test]
+        fn only_owner_can_pause() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.pause(), Err(Error::NotOwner));
+            assert_eq!(contract.unpause(), Err(Error::NotOwner));
+            assert!(!contract.is_paused());
+        }
+
+        #[ink::test]
+        fn transfer_rejects_recipient_overflow() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            contract.balances.insert(accounts.bob, &(Balance::MAX - 5));
+
+            assert_eq!(contract.transfer(accounts.bob, 10), Err(Error::Overflow));
+            assert_eq!(contract.balance_of(accounts.alice), 100);
+            assert_eq!(contract.balance_of(accounts.bob), Balance::MAX - 5);
+
+            assert!(contract.transfer(accounts.alice, 100).is_ok());
+            assert_eq!(contract.balance_of(accounts.alice), 100);
+        }
+
+        #[ink::test]
+        fn transfer_with_memo_emits_memo() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            assert!(contract.transfer_with_memo(accounts.bob, 10, [7u8; 32]).is_ok());
+            assert_eq!(contract.balance_of(accounts.bob), 10);
+
+            let event = ink::env::test::recorded_events().last().unwrap();
+            let decoded = <Event as scale::Decode>::decode(&mut &event.data[..]).unwrap();
+            match decoded {
+                Event::TransferWithMemo(TransferWithMemo { from, to, value, memo }) => {
+                    assert_eq!(from, accounts.alice);
+                    assert_eq!(to, accounts.bob);
+                    assert_eq!(value, 10);
+                    assert_eq!(memo, [7u8; 32]);
+                }
+                _ => panic!("expected a TransferWithMemo event"),
+            }
+        }
+
+        #[ink::test]
+        fn batch_transfer_is_all_or_nothing() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            let overdrawn = vec![(accounts.bob, 60), (accounts.charlie, 50)];
+            assert_eq!(contract.batch_transfer(overdrawn), Err(Error::InsufficientBalance));
+            assert_eq!(contract.balance_of(accounts.alice), 100);
+            assert_eq!(contract.balance_of(accounts.bob), 0);
+
+            let batch = vec![(accounts.bob, 60), (accounts.charlie, 40)];
+            assert!(contract.batch_transfer(batch).is_ok());
+            assert_eq!(contract.balance_of(accounts.alice), 0);
+            assert_eq!(contract.balance_of(accounts.bob), 60);
+            assert_eq!(contract.balance_of(accounts.charlie), 40);
+        }
+
+        #[ink::test]
+        fn permit_grants_allowance() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let (owner, sign) = permit_signer();
+            assert!(contract.transfer(owner, 50).is_ok());
+
+            let signature = sign(contract.permit_hash(owner, accounts.bob, 20, 1_000));
+            // Submitted by a relayer rather than the owner
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert!(contract.permit(owner, accounts.bob, 20, 1_000, signature).is_ok());
+            assert_eq!(contract.allowance(owner, accounts.bob), 20);
+            assert_eq!(contract.nonce_of(owner), 1);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert!(contract.transfer_from(owner, accounts.bob, 20).is_ok());
+            assert_eq!(contract.balance_of(accounts.bob), 20);
+        }
+
+        #[ink::test]
+        fn permit_rejects_replay_and_wrong_signer() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let (owner, sign) = permit_signer();
+
+            let signature = sign(contract.permit_hash(owner, accounts.bob, 20, 1_000));
+            assert!(contract.permit(owner, accounts.bob, 20, 1_000, signature).is_ok());
+
+            // The nonce moved on, so the same signature no longer matches
+            assert_eq!(
+                contract.permit(owner, accounts.bob, 20, 1_000, signature),
+                Err(Error::InvalidSignature)
+            );
+
+            let signature = sign(contract.permit_hash(accounts.alice, accounts.bob, 20, 1_000));
+            assert_eq!(
+                contract.permit(accounts.alice, accounts.bob, 20, 1_000, signature),
+                Err(Error::InvalidSignature)
+            );
+            assert_eq!(contract.allowance(accounts.alice, accounts.bob), 0);
+            assert_eq!(contract.nonce_of(owner), 1);
+        }
+
+        #[ink::test]
+        fn permit_rejects_expired_deadline() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let (owner, sign) = permit_signer();
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2_000);
+            let signature = sign(contract.permit_hash(owner, accounts.bob, 20, 1_000));
+            assert_eq!(
+                contract.permit(owner, accounts.bob, 20, 1_000, signature),
+                Err(Error::PermitExpired)
+            );
+            assert_eq!(contract.allowance(owner, accounts.bob), 0);
+            assert_eq!(contract.nonce_of(owner), 0);
+        }
+
+        #[ink::test]
+        fn balance_of_at_returns_pre_transfer_balance() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            assert_eq!(contract.snapshot(), Ok(1));
+            assert!(contract.transfer(accounts.bob, 30).is_ok());
+            assert!(contract.transfer(accounts.bob, 10).is_ok());
+
+            assert_eq!(contract.snapshot(), Ok(2));
+            assert!(contract.mint(accounts.charlie, 50).is_ok());
+
+            assert_eq!(contract.balance_of(accounts.alice), 60);
+            assert_eq!(contract.balance_of(accounts.bob), 40);
+            assert_eq!(contract.balance_of_at(accounts.alice, 1), 100);
+            assert_eq!(contract.balance_of_at(accounts.bob, 1), 0);
+            assert_eq!(contract.balance_of_at(accounts.alice, 2), 60);
+            assert_eq!(contract.balance_of_at(accounts.bob, 2), 40);
+            assert_eq!(contract.balance_of_at(accounts.charlie, 2), 0);
+
+            assert_eq!(contract.total_supply_at(1), 100);
+            assert_eq!(contract.total_supply_at(2), 100);
+            assert_eq!(contract.total_supply(), 150);
+            assert_eq!(contract.balance_of_at(accounts.alice, 3), 0);
+        }
+
+        #[ink::test]
+        fn balance_of_at_searches_many_checkpoints() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            // Alice sends one token after each of five snapshots
+            for id in 1..=5 {
+                assert_eq!(contract.snapshot(), Ok(id));
+                assert!(contract.transfer(accounts.bob, 1).is_ok());
+            }
+            assert_eq!(contract.snapshot(), Ok(6));
+
+            for id in 1..=6u64 {
+                assert_eq!(contract.balance_of_at(accounts.alice, id), 101 - id as Balance);
+                assert_eq!(contract.balance_of_at(accounts.bob, id), id as Balance - 1);
+            }
+            assert_eq!(contract.checkpoint_counts.get(accounts.alice), Some(5));
+        }
+
+        #[ink::test]
+        fn only_owner_can_snapshot() {
+            let mut contract = WhisperToken::new(100);
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(contract.snapshot(), Err(Error::NotOwner));
+            assert_eq!(contract.total_supply_at(1), 0);
+        }
     }
 }
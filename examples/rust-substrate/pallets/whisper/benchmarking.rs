@@ -0,0 +1,167 @@
+//! Benchmarks for the whisper pallet
+
+#![cfg(feature = "runtime-benchmarks")]
+
+use super::*;
+use crate::Pallet as Whisper;
+use frame_benchmarking::v2::*;
+use frame_support::{pallet_prelude::ConstU32, traits::Hooks, weights::Weight, BoundedVec};
+use frame_system::{pallet_prelude::BlockNumberFor, RawOrigin};
+use sp_runtime::traits::Saturating;
+use sp_std::vec;
+
+/// Put `n` full-size messages from distinct senders into `to`'s inbox, all
+/// expiring at block 1
+fn fill_inbox<T: Config>(to: &T::AccountId, n: u32) {
+    for id in 0..n {
+        let message = MessageData {
+            sender: account("sender", id, 0),
+            content: BoundedVec::<u8, ConstU32<256>>::truncate_from(vec![0u8; 256]),
+            ephemeral_public_key: [0u8; 32],
+            timestamp: 0,
+            expires_at: 1,
+        };
+        Messages::<T>::insert(to, id, message);
+    }
+    NextMessageId::<T>::insert(to, n);
+    InboxCount::<T>::insert(to, n);
+}
+
+/// Put `n` full-size copies of messages to distinct recipients into
+/// `from`'s outbox after whatever ids it already used, all expiring at block 1
+fn fill_outbox<T: Config>(from: &T::AccountId, n: u32) {
+    let first = NextMessageId::<T>::get(from);
+    for offset in 0..n {
+        let message = SentMessageData {
+            recipient: account("recipient", offset, 0),
+            content: BoundedVec::<u8, ConstU32<256>>::truncate_from(vec![0u8; 256]),
+            ephemeral_public_key: [0u8; 32],
+            timestamp: 0,
+            expires_at: 1,
+        };
+        Outbox::<T>::insert(from, first + offset, message);
+    }
+    NextMessageId::<T>::insert(from, first + n);
+    OutboxCount::<T>::insert(from, n);
+}
+
+/// First block at which `on_idle` prunes, with messages stored at block 0 expired
+fn first_pruning_block<T: Config>() -> BlockNumberFor<T> {
+    T::MaxMessageAge::get().saturating_add(1u32.into())
+}
+
+#[benchmarks]
+mod benchmarks {
+    use super::*;
+
+    #[benchmark]
+    fn send_message(n: Linear<0, { T::MaxMessagesPerAccount::get() - 1 }>) {
+        let caller: T::AccountId = whitelisted_caller();
+        let to: T::AccountId = account("recipient", 0, 0);
+        Keys::<T>::insert(&to, [1u8; 32]);
+        fill_inbox::<T>(&to, n);
+
+        #[extrinsic_call]
+        send_message(RawOrigin::Signed(caller), to.clone(), vec![0u8; 256], [2u8; 32], 0);
+
+        assert_eq!(InboxCount::<T>::get(&to), n + 1);
+    }
+
+    #[benchmark]
+    fn delete_message(n: Linear<1, { T::MaxMessagesPerAccount::get() }>) {
+        let caller: T::AccountId = whitelisted_caller();
+        fill_inbox::<T>(&caller, n);
+
+        #[extrinsic_call]
+        delete_message(RawOrigin::Signed(caller.clone()), 0);
+
+        assert_eq!(InboxCount::<T>::get(&caller), n - 1);
+    }
+
+    #[benchmark]
+    fn register_key() {
+        let caller: T::AccountId = whitelisted_caller();
+
+        #[extrinsic_call]
+        register_key(RawOrigin::Signed(caller.clone()), [1u8; 32]);
+
+        assert_eq!(Keys::<T>::get(&caller), Some([1u8; 32]));
+    }
+
+    #[benchmark]
+    fn reap_expired(n: Linear<0, { 2 * T::MaxMessagesPerAccount::get() }>) {
+        let caller: T::AccountId = whitelisted_caller();
+        let owner: T::AccountId = account("owner", 0, 0);
+        let in_inbox = n.min(T::MaxMessagesPerAccount::get());
+        fill_inbox::<T>(&owner, in_inbox);
+        fill_outbox::<T>(&owner, n - in_inbox);
+        frame_system::Pallet::<T>::set_block_number(1u32.into());
+
+        #[extrinsic_call]
+        reap_expired(RawOrigin::Signed(caller), owner.clone());
+
+        assert_eq!(InboxCount::<T>::get(&owner), 0);
+        assert_eq!(OutboxCount::<T>::get(&owner), 0);
+    }
+
+    #[benchmark]
+    fn clear_inbox(n: Linear<0, { T::MaxClearPerCall::get() }>) {
+        let caller: T::AccountId = whitelisted_caller();
+        fill_inbox::<T>(&caller, n);
+
+        #[extrinsic_call]
+        clear_inbox(RawOrigin::Signed(caller.clone()));
+
+        assert_eq!(InboxCount::<T>::get(&caller), 0);
+    }
+
+    #[benchmark]
+    fn on_idle_base() {
+        // Nothing stored, so the sweep only reads and writes the cursor
+        let now = first_pruning_block::<T>();
+
+        #[block]
+        {
+            Whisper::<T>::on_idle(now, Weight::MAX);
+        }
+
+        assert_eq!(PruneCursor::<T>::get(), Some(SweepPosition::Inbox(None)));
+    }
+
+    #[benchmark]
+    fn on_idle_inspect() {
+        // One message too new to prune
+        let owner: T::AccountId = account("owner", 0, 0);
+        fill_inbox::<T>(&owner, 1);
+        Messages::<T>::mutate(&owner, 0, |message| {
+            if let Some(message) = message {
+                message.timestamp = u64::MAX;
+            }
+        });
+        let now = first_pruning_block::<T>();
+
+        #[block]
+        {
+            Whisper::<T>::on_idle(now, Weight::MAX);
+        }
+
+        assert_eq!(InboxCount::<T>::get(&owner), 1);
+    }
+
+    #[benchmark]
+    fn on_idle_prune() {
+        // One message stored at block 0, past its age
+        let owner: T::AccountId = account("owner", 0, 0);
+        fill_inbox::<T>(&owner, 1);
+        let now = first_pruning_block::<T>();
+
+        #[block]
+        {
+            Whisper::<T>::on_idle(now, Weight::MAX);
+        }
+
+        assert_eq!(InboxCount::<T>::get(&owner), 0);
+    }
+
+    impl_benchmark_test_suite!(Whisper, crate::mock::new_test_ext(), crate::mock::Test);
+}
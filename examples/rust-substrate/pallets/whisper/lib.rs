@@ -4,49 +4,219 @@
 /// Decentralized messaging on Substrate-based chains
 pub use pallet::*;
 
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+
+pub mod runtime_api;
+pub mod weights;
+pub use weights::WeightInfo;
+
+/// Hook notified after a message has been stored in the recipient's inbox.
+///
+/// Runtimes can use this to trigger an offchain worker (e.g. for push
+/// notifications) or to feed a downstream pallet. `()` is a no-op.
+pub trait OnMessageSent<AccountId> {
+    /// Called with the sender, the recipient and the id of the new message in their inbox.
+    fn on_message_sent(from: &AccountId, to: &AccountId, id: u32);
+}
+
+impl<AccountId> OnMessageSent<AccountId> for () {
+    fn on_message_sent(_from: &AccountId, _to: &AccountId, _id: u32) {}
+}
+
 #[frame_support::pallet]
 pub mod pallet {
     use frame_support::{dispatch::DispatchResult, pallet_prelude::*};
     use frame_system::pallet_prelude::*;
+    use sp_runtime::{
+        traits::{Saturating, TrailingZeroInput, Zero},
+        ArithmeticError,
+    };
     use sp_std::vec::Vec;
 
+    use crate::{OnMessageSent, WeightInfo};
+
     #[pallet::pallet]
     pub struct Pallet<T>(_);
 
     #[pallet::config]
     pub trait Config: frame_system::Config {
         type RuntimeEvent: From<Event<Self>> + IsType<<Self as frame_system::Config>::RuntimeEvent>;
+
+        /// Handler notified after every successful `send_message`
+        type OnMessageSent: OnMessageSent<Self::AccountId>;
+
+        /// Weights of this pallet's calls, see `weights::SubstrateWeight`
+        type WeightInfo: WeightInfo;
+
+        /// Blocks a sender must wait between messages to the same recipient (0 = unlimited)
+        #[pallet::constant]
+        type MinSendInterval: Get<BlockNumberFor<Self>>;
+
+        /// Blocks after which `on_idle` deletes a message regardless of its owner (0 = keep forever)
+        #[pallet::constant]
+        type MaxMessageAge: Get<BlockNumberFor<Self>>;
+
+        /// Most messages an inbox or an outbox can hold, so neither grows without bound
+        #[pallet::constant]
+        type MaxMessagesPerAccount: Get<u32>;
+
+        /// Most messages one `clear_inbox` call removes, bounding its weight
+        #[pallet::constant]
+        type MaxClearPerCall: Get<u32>;
     }
 
     #[pallet::storage]
     #[pallet::getter(fn messages)]
-    pub type Messages<T: Config> = StorageMap<
+    pub type Messages<T: Config> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        T::AccountId,
+        Twox64Concat,
+        u32,
+        MessageData<T::AccountId>,
+        OptionQuery,
+    >;
+
+    /// Id the next message to or from each account will get. Inbox and
+    /// outbox share the sequence, so an id belongs to exactly one of them.
+    /// Ids are never reused, so deleting a message doesn't shift the ones
+    /// after it
+    #[pallet::storage]
+    #[pallet::getter(fn next_message_id)]
+    pub type NextMessageId<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, u32, ValueQuery>;
+
+    /// Sender's copy of each message they sent, so they can reconstruct
+    /// the conversation; deleted, pruned and reaped independently of the
+    /// recipient's copy
+    #[pallet::storage]
+    #[pallet::getter(fn outbox)]
+    pub type Outbox<T: Config> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        T::AccountId,
+        Twox64Concat,
+        u32,
+        SentMessageData<T::AccountId>,
+        OptionQuery,
+    >;
+
+    /// Number of messages currently in each account's inbox
+    #[pallet::storage]
+    #[pallet::getter(fn inbox_count)]
+    pub type InboxCount<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, u32, ValueQuery>;
+
+    /// Number of messages currently in each account's outbox
+    #[pallet::storage]
+    #[pallet::getter(fn outbox_count)]
+    pub type OutboxCount<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, u32, ValueQuery>;
+
+    /// X25519 public key each account has registered for Diffie-Hellman
+    /// with senders' ephemeral keys; accounts without one can't be messaged
+    #[pallet::storage]
+    #[pallet::getter(fn keys)]
+    pub type Keys<T: Config> =
+        StorageMap<_, Blake2_128Concat, T::AccountId, [u8; 32], OptionQuery>;
+
+    /// Where `on_idle`'s sweep stopped, so the next block's pruning resumes
+    /// there instead of rescanning the start of the maps (None = sweep not
+    /// started)
+    #[pallet::storage]
+    pub type PruneCursor<T: Config> = StorageValue<_, SweepPosition<T::AccountId>, OptionQuery>;
+
+    /// Block of each sender's last message to a recipient, for rate limiting.
+    /// Not written while `MinSendInterval` is 0; `on_idle` removes entries
+    /// once the interval has passed, since they no longer limit anything
+    #[pallet::storage]
+    pub type LastSend<T: Config> = StorageDoubleMap<
         _,
         Blake2_128Concat,
         T::AccountId,
-        Vec<MessageData<T::AccountId>>,
-        ValueQuery,
+        Blake2_128Concat,
+        T::AccountId,
+        BlockNumberFor<T>,
+        OptionQuery,
     >;
 
     #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, TypeInfo, MaxEncodedLen)]
     #[scale_info(skip_type_params(T))]
     pub struct MessageData<AccountId> {
         pub sender: AccountId,
+        /// Ciphertext under the secret shared by `ephemeral_public_key` and the recipient's key
         pub content: BoundedVec<u8, ConstU32<256>>,
+        /// Sender's one-off X25519 key, needed by the recipient to decrypt
+        pub ephemeral_public_key: [u8; 32],
         pub timestamp: u64,
+        /// Block from which anyone may reap the message with `reap_expired` (0 = never)
+        pub expires_at: u64,
+    }
+
+    /// Position of `on_idle`'s sweep, which goes through every inbox, then
+    /// every outbox, then `LastSend` before starting over
+    #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, TypeInfo, MaxEncodedLen)]
+    pub enum SweepPosition<AccountId> {
+        /// In `Messages`, after this key (None = from the start)
+        Inbox(Option<(AccountId, u32)>),
+        /// In `Outbox`, after this key (None = from the start)
+        Outbox(Option<(AccountId, u32)>),
+        /// In `LastSend`, after this sender and recipient (None = from the start)
+        LastSend(Option<(AccountId, AccountId)>),
+    }
+
+    /// A message as kept in its sender's `Outbox`
+    #[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug, TypeInfo, MaxEncodedLen)]
+    #[scale_info(skip_type_params(T))]
+    pub struct SentMessageData<AccountId> {
+        pub recipient: AccountId,
+        /// The same ciphertext the recipient got, readable by the sender only
+        /// if they kept the ephemeral secret key
+        pub content: BoundedVec<u8, ConstU32<256>>,
+        pub ephemeral_public_key: [u8; 32],
+        pub timestamp: u64,
+        /// Copied from the recipient's message; the copies are reaped separately
+        pub expires_at: u64,
     }
 
     #[pallet::event]
     #[pallet::generate_deposit(pub(super) fn deposit_event)]
     pub enum Event<T: Config> {
+        /// Carries a hash of the content so events don't duplicate the ciphertext
         MessageSent {
             from: T::AccountId,
             to: T::AccountId,
-            content: Vec<u8>,
+            content_hash: [u8; 32],
         },
         MessageDeleted {
             account: T::AccountId,
-            index: u32,
+            id: u32,
+        },
+        /// Inbox or outbox copy pruned by `on_idle` for exceeding
+        /// `MaxMessageAge`, or reaped by `reap_expired` once past its own
+        /// `expires_at`
+        MessageExpired {
+            account: T::AccountId,
+            id: u32,
+        },
+        /// Account registered or replaced its public key
+        KeyRegistered {
+            account: T::AccountId,
+            public_key: [u8; 32],
+        },
+        /// `count` messages removed by `clear_inbox`; `partial` means some
+        /// remain and the account has to call again
+        InboxCleared {
+            account: T::AccountId,
+            count: u32,
+            partial: bool,
         },
     }
 
@@ -55,65 +225,411 @@ pub mod pallet {
         MessageTooLong,
         MessageNotFound,
         Unauthorized,
+        /// Sent again within `MinSendInterval` blocks of the last message to this recipient
+        RateLimited,
+        /// Recipient's inbox already holds `MaxMessagesPerAccount` messages
+        InboxFull,
+        /// Sender's outbox already holds `MaxMessagesPerAccount` messages;
+        /// delete some of the copies to send again
+        OutboxFull,
+        /// Recipient hasn't registered a public key, so nothing sent to them could be decrypted
+        RecipientHasNoKey,
+        /// Sender and recipient are the same account
+        CannotMessageSelf,
+        /// Recipient is the all-zero account, which nobody holds the key to
+        InvalidRecipient,
+        /// Nonzero `expires_at` at or before the current block, so the
+        /// message would be reapable as soon as it landed
+        ExpiryInPast,
+    }
+
+    #[pallet::hooks]
+    impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+        /// Enforces the chain-wide retention ceiling and drops stale
+        /// `LastSend` entries with leftover block weight
+        fn on_idle(now: BlockNumberFor<T>, remaining_weight: Weight) -> Weight {
+            let max_age = T::MaxMessageAge::get();
+            let prunes_messages = !max_age.is_zero() && now > max_age;
+            if !prunes_messages && T::MinSendInterval::get().is_zero() {
+                return Weight::zero();
+            }
+            let cutoff: u64 = now.saturating_sub(max_age).saturated_into();
+
+            let mut used = T::WeightInfo::on_idle_base();
+            if used.any_gt(remaining_weight) {
+                return Weight::zero();
+            }
+
+            let mut cursor = PruneCursor::<T>::get().unwrap_or(SweepPosition::Inbox(None));
+            // Nothing is old enough yet, so only `LastSend` needs sweeping
+            if !prunes_messages && !matches!(cursor, SweepPosition::LastSend(_)) {
+                cursor = SweepPosition::LastSend(None);
+            }
+            if let SweepPosition::Inbox(after) = &mut cursor {
+                let (spent, finished) = Self::prune::<Messages<T>, InboxCount<T>, _>(
+                    cutoff,
+                    after,
+                    remaining_weight.saturating_sub(used),
+                    |message: &MessageData<T::AccountId>| message.timestamp,
+                );
+                used = used.saturating_add(spent);
+                if finished {
+                    cursor = SweepPosition::Outbox(None);
+                }
+            }
+            if let SweepPosition::Outbox(after) = &mut cursor {
+                let (spent, finished) = Self::prune::<Outbox<T>, OutboxCount<T>, _>(
+                    cutoff,
+                    after,
+                    remaining_weight.saturating_sub(used),
+                    |message: &SentMessageData<T::AccountId>| message.timestamp,
+                );
+                used = used.saturating_add(spent);
+                if finished {
+                    cursor = SweepPosition::LastSend(None);
+                }
+            }
+            if let SweepPosition::LastSend(after) = &mut cursor {
+                let (spent, finished) =
+                    Self::prune_last_send(now, after, remaining_weight.saturating_sub(used));
+                used = used.saturating_add(spent);
+                if finished {
+                    cursor = SweepPosition::Inbox(None);
+                }
+            }
+            PruneCursor::<T>::put(cursor);
+
+            used
+        }
     }
 
     #[pallet::call]
     impl<T: Config> Pallet<T> {
-        /// Send an encrypted message
-        #[pallet::weight(10_000)]
+        /// Send a message encrypted to the recipient's registered key.
+        ///
+        /// A nonzero `expires_at` makes the message reapable from that block on,
+        /// and must be after the current block.
+        #[pallet::weight(T::WeightInfo::send_message(T::MaxMessagesPerAccount::get()))]
         #[pallet::call_index(0)]
         pub fn send_message(
             origin: OriginFor<T>,
             to: T::AccountId,
             content: Vec<u8>,
+            ephemeral_public_key: [u8; 32],
+            expires_at: u64,
         ) -> DispatchResult {
             let sender = ensure_signed(origin)?;
 
+            ensure!(to != sender, Error::<T>::CannotMessageSelf);
+            ensure!(to != Self::zero_account(), Error::<T>::InvalidRecipient);
             ensure!(content.len() <= 256, Error::<T>::MessageTooLong);
+            ensure!(Keys::<T>::contains_key(&to), Error::<T>::RecipientHasNoKey);
 
-            let bounded_content = BoundedVec::<u8, ConstU32<256>>::try_from(content.clone())
+            let content_hash = sp_io::hashing::blake2_256(&content);
+            let bounded_content = BoundedVec::<u8, ConstU32<256>>::try_from(content)
                 .map_err(|_| Error::<T>::MessageTooLong)?;
 
+            let count = InboxCount::<T>::get(&to);
+            ensure!(count < T::MaxMessagesPerAccount::get(), Error::<T>::InboxFull);
+            let outbox_count = OutboxCount::<T>::get(&sender);
+            ensure!(
+                outbox_count < T::MaxMessagesPerAccount::get(),
+                Error::<T>::OutboxFull
+            );
+
+            let now = <frame_system::Pallet<T>>::block_number();
+            ensure!(
+                expires_at == 0 || expires_at > now.saturated_into::<u64>(),
+                Error::<T>::ExpiryInPast
+            );
+            let interval = T::MinSendInterval::get();
+            if !interval.is_zero() {
+                if let Some(last) = LastSend::<T>::get(&sender, &to) {
+                    ensure!(now.saturating_sub(last) >= interval, Error::<T>::RateLimited);
+                }
+                LastSend::<T>::insert(&sender, &to, now);
+            }
+
+            let sent = SentMessageData {
+                recipient: to.clone(),
+                content: bounded_content.clone(),
+                ephemeral_public_key,
+                timestamp: now.saturated_into(),
+                expires_at,
+            };
             let message = MessageData {
                 sender: sender.clone(),
                 content: bounded_content,
-                timestamp: <frame_system::Pallet<T>>::block_number().saturated_into(),
+                ephemeral_public_key,
+                timestamp: now.saturated_into(),
+                expires_at,
             };
 
-            Messages::<T>::mutate(&to, |messages| {
-                messages.push(message);
-            });
+            let id = NextMessageId::<T>::get(&to);
+            let next_id = id.checked_add(1).ok_or(ArithmeticError::Overflow)?;
+            let outbox_id = NextMessageId::<T>::get(&sender);
+            let next_outbox_id = outbox_id.checked_add(1).ok_or(ArithmeticError::Overflow)?;
+
+            NextMessageId::<T>::insert(&to, next_id);
+            InboxCount::<T>::insert(&to, count + 1);
+            Messages::<T>::insert(&to, id, message);
+            NextMessageId::<T>::insert(&sender, next_outbox_id);
+            OutboxCount::<T>::insert(&sender, outbox_count + 1);
+            Outbox::<T>::insert(&sender, outbox_id, sent);
+
+            T::OnMessageSent::on_message_sent(&sender, &to, id);
 
             Self::deposit_event(Event::MessageSent {
                 from: sender,
                 to,
-                content,
+                content_hash,
             });
 
             Ok(())
         }
 
-        /// Delete a message
-        #[pallet::weight(10_000)]
+        /// Delete a message from the caller's inbox or outbox by its id
+        #[pallet::weight(T::WeightInfo::delete_message(T::MaxMessagesPerAccount::get()))]
         #[pallet::call_index(1)]
         pub fn delete_message(
             origin: OriginFor<T>,
-            index: u32,
+            id: u32,
         ) -> DispatchResult {
             let account = ensure_signed(origin)?;
 
-            Messages::<T>::try_mutate(&account, |messages| -> DispatchResult {
+            if Messages::<T>::contains_key(&account, id) {
+                Messages::<T>::remove(&account, id);
+                InboxCount::<T>::mutate(&account, |count| *count = count.saturating_sub(1));
+            } else {
                 ensure!(
-                    (index as usize) < messages.len(),
+                    Outbox::<T>::contains_key(&account, id),
                     Error::<T>::MessageNotFound
                 );
+                Outbox::<T>::remove(&account, id);
+                OutboxCount::<T>::mutate(&account, |count| *count = count.saturating_sub(1));
+            }
+
+            Self::deposit_event(Event::MessageDeleted { account, id });
+
+            Ok(())
+        }
 
-                messages.remove(index as usize);
+        /// Register the caller's X25519 public key, replacing any earlier one.
+        ///
+        /// Messages already in the inbox stay readable only with the key they
+        /// were sent to.
+        #[pallet::weight(T::WeightInfo::register_key())]
+        #[pallet::call_index(2)]
+        pub fn register_key(origin: OriginFor<T>, public_key: [u8; 32]) -> DispatchResult {
+            let account = ensure_signed(origin)?;
+
+            Keys::<T>::insert(&account, public_key);
+
+            Self::deposit_event(Event::KeyRegistered { account, public_key });
+
+            Ok(())
+        }
+
+        /// Remove every message in `account`'s inbox and outbox whose
+        /// `expires_at` has been reached.
+        ///
+        /// Permissionless, so senders' self-destruct deadlines hold even if the
+        /// recipient never comes back.
+        #[pallet::weight(T::WeightInfo::reap_expired(
+            T::MaxMessagesPerAccount::get().saturating_mul(2)
+        ))]
+        #[pallet::call_index(3)]
+        pub fn reap_expired(origin: OriginFor<T>, account: T::AccountId) -> DispatchResult {
+            ensure_signed(origin)?;
+
+            let now: u64 = <frame_system::Pallet<T>>::block_number().saturated_into();
+            let is_expired = |expires_at: u64| expires_at != 0 && expires_at <= now;
+            let expired: Vec<u32> = Messages::<T>::iter_prefix(&account)
+                .filter(|(_, message)| is_expired(message.expires_at))
+                .map(|(id, _)| id)
+                .collect();
+            let expired_sent: Vec<u32> = Outbox::<T>::iter_prefix(&account)
+                .filter(|(_, message)| is_expired(message.expires_at))
+                .map(|(id, _)| id)
+                .collect();
+
+            // Removals happen after iterating so the maps aren't mutated mid-iteration
+            for id in expired {
+                Messages::<T>::remove(&account, id);
+                InboxCount::<T>::mutate(&account, |count| *count = count.saturating_sub(1));
+                Self::deposit_event(Event::MessageExpired { account: account.clone(), id });
+            }
+            for id in expired_sent {
+                Outbox::<T>::remove(&account, id);
+                OutboxCount::<T>::mutate(&account, |count| *count = count.saturating_sub(1));
+                Self::deposit_event(Event::MessageExpired { account: account.clone(), id });
+            }
+
+            Ok(())
+        }
+
+        /// Delete up to `MaxClearPerCall` messages from the caller's inbox.
+        ///
+        /// Messages are removed in storage order rather than by id. Repeat the
+        /// call while `InboxCleared` reports `partial` to empty the inbox.
+        #[pallet::weight(T::WeightInfo::clear_inbox(T::MaxClearPerCall::get()))]
+        #[pallet::call_index(4)]
+        pub fn clear_inbox(origin: OriginFor<T>) -> DispatchResult {
+            let account = ensure_signed(origin)?;
 
-                Self::deposit_event(Event::MessageDeleted { account: account.clone(), index });
+            let ids: Vec<u32> = Messages::<T>::iter_prefix(&account)
+                .map(|(id, _)| id)
+                .take(T::MaxClearPerCall::get() as usize)
+                .collect();
+
+            // Removals happen after iterating so the map isn't mutated mid-iteration
+            for &id in &ids {
+                Messages::<T>::remove(&account, id);
+            }
+            let count = ids.len() as u32;
+            let remaining = InboxCount::<T>::mutate(&account, |inbox_count| {
+                *inbox_count = inbox_count.saturating_sub(count);
+                *inbox_count
+            });
+
+            Self::deposit_event(Event::InboxCleared {
+                account,
+                count,
+                partial: remaining > 0,
+            });
+
+            Ok(())
+        }
+    }
+
+    impl<T: Config> Pallet<T> {
+        /// Up to `limit` messages of `account`'s inbox after the one with id
+        /// `after` (None = from the start), in storage order.
+        ///
+        /// Backs `WhisperApi::inbox`. Ids are hashed into their keys, so the
+        /// order isn't by id. The message at `after` may have been deleted
+        /// since; the page still picks up where it would have been. Reads at
+        /// most `limit` entries.
+        pub fn get_inbox_page(
+            account: &T::AccountId,
+            after: Option<u32>,
+            limit: u32,
+        ) -> Vec<(u32, MessageData<T::AccountId>)> {
+            let messages = match after {
+                Some(id) => Messages::<T>::iter_prefix_from(
+                    account,
+                    Messages::<T>::hashed_key_for(account, id),
+                ),
+                None => Messages::<T>::iter_prefix(account),
+            };
+            messages.take(limit as usize).collect()
+        }
+
+        /// Remove `Map` entries stored before `cutoff`, continuing after
+        /// `cursor` and leaving it at the last entry inspected, and decrement
+        /// each owner's `Count`. Sweeps both the inbox and the outbox, with
+        /// `timestamp` reading an entry's store block.
+        ///
+        /// Returns the weight used and whether the end of the map was reached
+        /// within `budget`.
+        fn prune<Map, Count, V>(
+            cutoff: u64,
+            cursor: &mut Option<(T::AccountId, u32)>,
+            budget: Weight,
+            timestamp: impl Fn(&V) -> u64,
+        ) -> (Weight, bool)
+        where
+            V: codec::FullCodec,
+            Map: frame_support::storage::IterableStorageDoubleMap<T::AccountId, u32, V>,
+            Count: frame_support::storage::StorageMap<T::AccountId, u32, Query = u32>,
+        {
+            // Before each read, keep back enough to also prune what it finds
+            let inspect = T::WeightInfo::on_idle_inspect();
+            let prune = T::WeightInfo::on_idle_prune();
+            let worst_case = inspect.saturating_add(prune);
+            let mut used = Weight::zero();
+            let mut expired = Vec::new();
+
+            let mut entries = match cursor {
+                Some((account, id)) => Map::iter_from(Map::hashed_key_for(&*account, *id)),
+                None => Map::iter(),
+            };
+            let finished = loop {
+                if used.saturating_add(worst_case).any_gt(budget) {
+                    break false;
+                }
+                let Some((account, id, entry)) = entries.next() else {
+                    break true;
+                };
+                used = used.saturating_add(inspect);
+
+                if timestamp(&entry) < cutoff {
+                    used = used.saturating_add(prune);
+                    expired.push((account.clone(), id));
+                }
+                *cursor = Some((account, id));
+            };
+
+            // Removals happen after iterating so the map isn't mutated mid-iteration
+            for (account, id) in expired {
+                Map::remove(&account, id);
+                Count::mutate(&account, |count| *count = count.saturating_sub(1));
+                Self::deposit_event(Event::MessageExpired { account, id });
+            }
+
+            (used, finished)
+        }
+
+        /// Remove `LastSend` entries at least `MinSendInterval` blocks old,
+        /// continuing after `cursor` like `prune`
+        fn prune_last_send(
+            now: BlockNumberFor<T>,
+            cursor: &mut Option<(T::AccountId, T::AccountId)>,
+            budget: Weight,
+        ) -> (Weight, bool) {
+            // An entry is smaller than a message and has no count or event,
+            // so the message costs bound it
+            let inspect = T::WeightInfo::on_idle_inspect();
+            let prune = T::WeightInfo::on_idle_prune();
+            let worst_case = inspect.saturating_add(prune);
+            let interval = T::MinSendInterval::get();
+            let mut used = Weight::zero();
+            let mut stale = Vec::new();
+
+            let mut entries = match cursor {
+                Some((sender, recipient)) => {
+                    LastSend::<T>::iter_from(LastSend::<T>::hashed_key_for(&*sender, &*recipient))
+                }
+                None => LastSend::<T>::iter(),
+            };
+            let finished = loop {
+                if used.saturating_add(worst_case).any_gt(budget) {
+                    break false;
+                }
+                let Some((sender, recipient, last)) = entries.next() else {
+                    break true;
+                };
+                used = used.saturating_add(inspect);
+
+                if now.saturating_sub(last) >= interval {
+                    used = used.saturating_add(prune);
+                    stale.push((sender.clone(), recipient.clone()));
+                }
+                *cursor = Some((sender, recipient));
+            };
+
+            // Removals happen after iterating so the map isn't mutated mid-iteration
+            for (sender, recipient) in stale {
+                LastSend::<T>::remove(&sender, &recipient);
+            }
+
+            (used, finished)
+        }
 
-                Ok(())
-            })
+        /// Account id decoded from all-zero bytes, usually a client's unset default
+        fn zero_account() -> T::AccountId {
+            T::AccountId::decode(&mut TrailingZeroInput::zeroes())
+                .expect("infinite input is enough to decode any account id; qed")
         }
     }
 }
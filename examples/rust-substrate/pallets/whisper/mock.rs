@@ -0,0 +1,117 @@
+use crate as pallet_whisper;
+use frame_support::{
+    derive_impl,
+    traits::{ConstU32, ConstU64, Get},
+    weights::constants::RocksDbWeight,
+};
+use sp_runtime::{BuildStorage, DispatchResult};
+use std::cell::RefCell;
+
+type Block = frame_system::mocking::MockBlock<Test>;
+
+frame_support::construct_runtime!(
+    pub enum Test {
+        System: frame_system,
+        Whisper: pallet_whisper,
+    }
+);
+
+#[derive_impl(frame_system::config_preludes::TestDefaultConfig)]
+impl frame_system::Config for Test {
+    type Block = Block;
+    // Nonzero so `on_idle` actually runs out of weight in tests
+    type DbWeight = RocksDbWeight;
+}
+
+thread_local! {
+    static SENT_NOTIFICATIONS: RefCell<Vec<(u64, u64, u32)>> = RefCell::new(Vec::new());
+    static MIN_SEND_INTERVAL: RefCell<u64> = RefCell::new(3);
+}
+
+/// `MinSendInterval` of the mock runtime: 3 blocks unless a test sets another
+pub struct MinSendInterval;
+
+impl MinSendInterval {
+    pub fn set(blocks: u64) {
+        MIN_SEND_INTERVAL.with(|interval| *interval.borrow_mut() = blocks);
+    }
+}
+
+impl Get<u64> for MinSendInterval {
+    fn get() -> u64 {
+        MIN_SEND_INTERVAL.with(|interval| *interval.borrow())
+    }
+}
+
+/// Records every hook invocation so tests can assert on it
+pub struct RecordingHook;
+
+impl pallet_whisper::OnMessageSent<u64> for RecordingHook {
+    fn on_message_sent(from: &u64, to: &u64, id: u32) {
+        SENT_NOTIFICATIONS.with(|sent| sent.borrow_mut().push((*from, *to, id)));
+    }
+}
+
+pub fn sent_notifications() -> Vec<(u64, u64, u32)> {
+    SENT_NOTIFICATIONS.with(|sent| sent.borrow().clone())
+}
+
+/// Accounts `new_test_ext` registers a public key for; any other account has none
+pub const REGISTERED_ACCOUNTS: [u64; 5] = [1, 2, 3, 4, 5];
+
+/// Ephemeral key the tests send messages under
+pub const EPHEMERAL_KEY: [u8; 32] = [9u8; 32];
+
+/// Send `content` from `from` to `to` under `EPHEMERAL_KEY`, with no expiry
+pub fn send(from: u64, to: u64, content: &[u8]) -> DispatchResult {
+    Whisper::send_message(
+        RuntimeOrigin::signed(from),
+        to,
+        content.to_vec(),
+        EPHEMERAL_KEY,
+        0,
+    )
+}
+
+/// Ids and contents of `account`'s inbox, in id order
+pub fn inbox(account: u64) -> Vec<(u32, Vec<u8>)> {
+    let mut messages: Vec<_> = pallet_whisper::Messages::<Test>::iter_prefix(account)
+        .map(|(id, message)| (id, message.content.to_vec()))
+        .collect();
+    messages.sort_by_key(|(id, _)| *id);
+    messages
+}
+
+/// Outbox ids, recipients and contents of `account`'s sent messages, in id order
+pub fn outbox(account: u64) -> Vec<(u32, u64, Vec<u8>)> {
+    let mut messages: Vec<_> = pallet_whisper::Outbox::<Test>::iter_prefix(account)
+        .map(|(id, message)| (id, message.recipient, message.content.to_vec()))
+        .collect();
+    messages.sort_by_key(|(id, _, _)| *id);
+    messages
+}
+
+impl pallet_whisper::Config for Test {
+    type RuntimeEvent = RuntimeEvent;
+    type OnMessageSent = RecordingHook;
+    type WeightInfo = ();
+    type MinSendInterval = MinSendInterval;
+    type MaxMessageAge = ConstU64<10>;
+    type MaxMessagesPerAccount = ConstU32<3>;
+    type MaxClearPerCall = ConstU32<2>;
+}
+
+pub fn new_test_ext() -> sp_io::TestExternalities {
+    let storage = frame_system::GenesisConfig::<Test>::default()
+        .build_storage()
+        .unwrap();
+
+    let mut ext = sp_io::TestExternalities::new(storage);
+    ext.execute_with(|| {
+        System::set_block_number(1);
+        for account in REGISTERED_ACCOUNTS {
+            pallet_whisper::Keys::<Test>::insert(account, [account as u8; 32]);
+        }
+    });
+    ext
+}
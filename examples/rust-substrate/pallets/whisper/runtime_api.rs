@@ -0,0 +1,25 @@
+//! Runtime API for reading inboxes without decoding the whole storage prefix.
+//!
+//! Runtimes expose it with `impl_runtime_apis!`, forwarding to
+//! `Pallet::get_inbox_page` and `Pallet::inbox_count`.
+
+use codec::Codec;
+use sp_std::vec::Vec;
+
+use crate::MessageData;
+
+sp_api::decl_runtime_apis! {
+    pub trait WhisperApi<AccountId>
+    where
+        AccountId: Codec,
+    {
+        /// Up to `limit` messages of `account`'s inbox after the one with id
+        /// `after` (None = from the start), in storage order rather than id order.
+        ///
+        /// Pass the last returned id as the next `after` to page through the inbox.
+        fn inbox(account: AccountId, after: Option<u32>, limit: u32) -> Vec<(u32, MessageData<AccountId>)>;
+
+        /// Number of messages currently in `account`'s inbox
+        fn inbox_len(account: AccountId) -> u32;
+    }
+}
@@ -0,0 +1,618 @@
+use crate::{mock::*, Error, WeightInfo};
+use frame_support::{
+    assert_noop, assert_ok,
+    pallet_prelude::Encode,
+    traits::Hooks,
+    weights::Weight,
+};
+
+type Weights = <Test as crate::Config>::WeightInfo;
+
+#[test]
+fn send_message_notifies_hook() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"hello"));
+        assert_ok!(send(3, 2, b"hey"));
+
+        assert_eq!(sent_notifications(), vec![(1, 2, 0), (3, 2, 1)]);
+    });
+}
+
+#[test]
+fn failed_send_does_not_notify_hook() {
+    new_test_ext().execute_with(|| {
+        assert_noop!(send(1, 2, &[0u8; 257]), Error::<Test>::MessageTooLong);
+
+        assert!(sent_notifications().is_empty());
+    });
+}
+
+#[test]
+fn repeat_send_to_same_recipient_is_rate_limited() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"hello"));
+
+        System::set_block_number(3);
+        assert_noop!(send(1, 2, b"again"), Error::<Test>::RateLimited);
+
+        // Other recipients and other senders are tracked separately
+        assert_ok!(send(1, 3, b"hi"));
+        assert_ok!(send(3, 2, b"hey"));
+
+        System::set_block_number(4);
+        assert_ok!(send(1, 2, b"again"));
+        assert_eq!(inbox(2).len(), 3);
+    });
+}
+
+#[test]
+fn zero_send_interval_records_no_last_send() {
+    new_test_ext().execute_with(|| {
+        MinSendInterval::set(0);
+
+        assert_ok!(send(1, 2, b"hello"));
+        assert_ok!(send(1, 2, b"again"));
+
+        assert_eq!(inbox(2).len(), 2);
+        assert_eq!(crate::LastSend::<Test>::iter().count(), 0);
+    });
+}
+
+#[test]
+fn on_idle_removes_last_send_once_the_interval_has_passed() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"hello"));
+
+        // Still rate limiting at block 3, so kept
+        System::set_block_number(3);
+        Whisper::on_idle(3, Weight::MAX);
+        assert_eq!(crate::LastSend::<Test>::get(1, 2), Some(1));
+
+        System::set_block_number(4);
+        Whisper::on_idle(4, Weight::MAX);
+        assert_eq!(crate::LastSend::<Test>::get(1, 2), None);
+        assert_ok!(send(1, 2, b"again"));
+    });
+}
+
+#[test]
+fn on_idle_prunes_messages_past_max_age() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"old"));
+        System::set_block_number(8);
+        assert_ok!(send(1, 2, b"new"));
+
+        // Exactly MaxMessageAge blocks old is still kept
+        System::set_block_number(11);
+        Whisper::on_idle(11, Weight::MAX);
+        assert_eq!(inbox(2).len(), 2);
+
+        System::set_block_number(12);
+        Whisper::on_idle(12, Weight::MAX);
+
+        assert_eq!(inbox(2), vec![(1, b"new".to_vec())]);
+        System::assert_has_event(crate::Event::MessageExpired { account: 2, id: 0 }.into());
+    });
+}
+
+#[test]
+fn on_idle_without_weight_prunes_nothing() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"old"));
+
+        System::set_block_number(20);
+        assert_eq!(Whisper::on_idle(20, Weight::zero()), Weight::zero());
+        assert_eq!(inbox(2).len(), 1);
+    });
+}
+
+#[test]
+fn on_idle_resumes_where_the_last_block_stopped() {
+    new_test_ext().execute_with(|| {
+        for (from, to) in [(1, 2), (1, 3), (3, 4), (3, 5)] {
+            assert_ok!(send(from, to, b"old"));
+        }
+        System::set_block_number(8);
+        for (from, to) in [(2, 1), (2, 3), (4, 5), (5, 4)] {
+            assert_ok!(send(from, to, b"new"));
+        }
+
+        // Enough for the cursor and two copies per block, so however the
+        // maps are ordered, eight blocks must cover all sixteen copies
+        let per_copy = Weights::on_idle_inspect().saturating_add(Weights::on_idle_prune());
+        let budget = Weights::on_idle_base().saturating_add(per_copy.saturating_mul(2));
+        for now in 12..20 {
+            System::set_block_number(now);
+            Whisper::on_idle(now, budget);
+        }
+
+        let received = |account| inbox(account).into_iter().map(|(_, content)| content).collect::<Vec<_>>();
+        let sent = |account| outbox(account).into_iter().map(|(_, to, _)| to).collect::<Vec<_>>();
+        for account in [1, 3, 4, 5] {
+            assert_eq!(received(account), vec![b"new".to_vec()]);
+        }
+        assert!(received(2).is_empty());
+        assert!(sent(1).is_empty());
+        assert!(sent(3).is_empty());
+        assert_eq!(sent(2), vec![1, 3]);
+        assert_eq!(sent(4), vec![5]);
+        assert_eq!(sent(5), vec![4]);
+    });
+}
+
+#[test]
+fn on_idle_stays_within_the_remaining_weight() {
+    new_test_ext().execute_with(|| {
+        for (from, to) in [(1, 2), (3, 4), (4, 5)] {
+            assert_ok!(send(from, to, b"old"));
+        }
+        let copies = || (1..=5).map(|account| inbox(account).len() + outbox(account).len()).sum::<usize>();
+
+        // One copy's inspect and prune fit, a second inspect doesn't
+        let one_copy = Weights::on_idle_base()
+            .saturating_add(Weights::on_idle_inspect())
+            .saturating_add(Weights::on_idle_prune());
+        let budget = one_copy.saturating_add(Weights::on_idle_inspect());
+
+        System::set_block_number(12);
+        assert_eq!(Whisper::on_idle(12, budget), one_copy);
+        assert_eq!(copies(), 5);
+    });
+}
+
+#[test]
+fn on_idle_prunes_outbox_copies_past_max_age() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"old"));
+        System::set_block_number(8);
+        assert_ok!(send(1, 3, b"new"));
+
+        System::set_block_number(12);
+        Whisper::on_idle(12, Weight::MAX);
+
+        assert_eq!(outbox(1), vec![(1, 3, b"new".to_vec())]);
+        assert_eq!(Whisper::outbox_count(1), 1);
+        System::assert_has_event(crate::Event::MessageExpired { account: 1, id: 0 }.into());
+    });
+}
+
+#[test]
+fn deleting_a_message_keeps_later_ids_stable() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"first"));
+        assert_ok!(send(3, 2, b"second"));
+
+        assert_ok!(Whisper::delete_message(RuntimeOrigin::signed(2), 0));
+        System::assert_has_event(crate::Event::MessageDeleted { account: 2, id: 0 }.into());
+
+        assert_eq!(inbox(2), vec![(1, b"second".to_vec())]);
+        assert_eq!(Whisper::messages(2, 1).unwrap().sender, 3);
+        assert_noop!(
+            Whisper::delete_message(RuntimeOrigin::signed(2), 0),
+            Error::<Test>::MessageNotFound
+        );
+
+        // New messages never reuse a deleted id
+        System::set_block_number(5);
+        assert_ok!(send(1, 2, b"third"));
+        assert_eq!(
+            inbox(2),
+            vec![(1, b"second".to_vec()), (2, b"third".to_vec())]
+        );
+    });
+}
+
+#[test]
+fn full_inbox_rejects_sends_until_a_message_is_deleted() {
+    new_test_ext().execute_with(|| {
+        for sender in [1, 3, 4] {
+            assert_ok!(send(sender, 2, b"hi"));
+        }
+        assert_eq!(Whisper::inbox_count(2), 3);
+
+        assert_noop!(send(5, 2, b"hi"), Error::<Test>::InboxFull);
+
+        assert_ok!(Whisper::delete_message(RuntimeOrigin::signed(2), 1));
+        assert_eq!(Whisper::inbox_count(2), 2);
+        assert_ok!(send(5, 2, b"hi"));
+        assert_eq!(Whisper::inbox_count(2), 3);
+    });
+}
+
+#[test]
+fn full_outbox_rejects_sends_until_a_copy_is_deleted() {
+    new_test_ext().execute_with(|| {
+        for to in [2, 3, 4] {
+            assert_ok!(send(1, to, b"hi"));
+        }
+        assert_eq!(Whisper::outbox_count(1), 3);
+
+        assert_noop!(send(1, 5, b"hi"), Error::<Test>::OutboxFull);
+
+        assert_ok!(Whisper::delete_message(RuntimeOrigin::signed(1), 0));
+        assert_eq!(Whisper::outbox_count(1), 2);
+        assert_ok!(send(1, 5, b"hi"));
+        assert_eq!(Whisper::outbox_count(1), 3);
+    });
+}
+
+#[test]
+fn message_sent_event_carries_only_the_content_hash() {
+    new_test_ext().execute_with(|| {
+        let content = b"opaque ciphertext bytes".to_vec();
+        assert_ok!(send(1, 2, &content));
+
+        let stored = Whisper::messages(2, 0).unwrap();
+        let expected = crate::Event::MessageSent {
+            from: 1,
+            to: 2,
+            content_hash: sp_io::hashing::blake2_256(&stored.content),
+        };
+        System::assert_last_event(expected.clone().into());
+
+        let encoded = expected.encode();
+        assert!(!encoded
+            .windows(content.len())
+            .any(|window| window == content.as_slice()));
+    });
+}
+
+#[test]
+fn inbox_pages_are_bounded_by_what_remains() {
+    new_test_ext().execute_with(|| {
+        for sender in [1, 3, 4] {
+            assert_ok!(send(sender, 2, &[sender as u8]));
+        }
+        let page = |after, limit| -> Vec<u32> {
+            Whisper::get_inbox_page(&2, after, limit)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect()
+        };
+
+        let first = page(None, 2);
+        assert_eq!(first.len(), 2);
+        let rest = page(Some(first[1]), 2);
+        assert_eq!(rest.len(), 1);
+        assert!(page(Some(rest[0]), 10).is_empty());
+
+        let mut all = [first, rest].concat();
+        all.sort();
+        assert_eq!(all, vec![0, 1, 2]);
+        assert_eq!(page(None, 10).len(), 3);
+        assert!(page(None, 0).is_empty());
+        assert!(Whisper::get_inbox_page(&5, None, 10).is_empty());
+    });
+}
+
+#[test]
+fn inbox_pages_skip_deleted_ids() {
+    new_test_ext().execute_with(|| {
+        for sender in [1, 3, 4] {
+            assert_ok!(send(sender, 2, b"hi"));
+        }
+        assert_ok!(Whisper::delete_message(RuntimeOrigin::signed(2), 1));
+
+        let first = Whisper::get_inbox_page(&2, None, 1)[0].0;
+        assert_ne!(first, 1);
+
+        // Paging resumes correctly even after the last id seen is deleted
+        assert_ok!(Whisper::delete_message(RuntimeOrigin::signed(2), first));
+        let rest: Vec<u32> = Whisper::get_inbox_page(&2, Some(first), 10)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(rest.len(), 1);
+        assert!(![1, first].contains(&rest[0]));
+        assert_eq!(Whisper::inbox_count(2), 1);
+    });
+}
+
+#[test]
+fn register_key_stores_and_replaces_the_callers_key() {
+    new_test_ext().execute_with(|| {
+        assert_eq!(Whisper::keys(7), None);
+
+        assert_ok!(Whisper::register_key(RuntimeOrigin::signed(7), [1u8; 32]));
+        assert_eq!(Whisper::keys(7), Some([1u8; 32]));
+        System::assert_last_event(
+            crate::Event::KeyRegistered {
+                account: 7,
+                public_key: [1u8; 32],
+            }
+            .into(),
+        );
+
+        assert_ok!(Whisper::register_key(RuntimeOrigin::signed(7), [2u8; 32]));
+        assert_eq!(Whisper::keys(7), Some([2u8; 32]));
+    });
+}
+
+#[test]
+fn send_to_recipient_without_a_key_is_rejected() {
+    new_test_ext().execute_with(|| {
+        assert_noop!(send(1, 7, b"hi"), Error::<Test>::RecipientHasNoKey);
+        assert!(sent_notifications().is_empty());
+
+        assert_ok!(Whisper::register_key(RuntimeOrigin::signed(7), [1u8; 32]));
+        assert_ok!(send(1, 7, b"hi"));
+    });
+}
+
+#[test]
+fn send_stores_the_ephemeral_key_with_the_message() {
+    new_test_ext().execute_with(|| {
+        let origin = RuntimeOrigin::signed;
+        assert_ok!(Whisper::send_message(
+            origin(1),
+            2,
+            b"hi".to_vec(),
+            [4u8; 32],
+            0
+        ));
+        assert_ok!(Whisper::send_message(
+            origin(3),
+            2,
+            b"hey".to_vec(),
+            [5u8; 32],
+            0
+        ));
+
+        assert_eq!(
+            Whisper::messages(2, 0).unwrap().ephemeral_public_key,
+            [4u8; 32]
+        );
+        assert_eq!(
+            Whisper::messages(2, 1).unwrap().ephemeral_public_key,
+            [5u8; 32]
+        );
+    });
+}
+
+#[test]
+fn reap_expired_removes_only_messages_past_their_expiry() {
+    new_test_ext().execute_with(|| {
+        let origin = RuntimeOrigin::signed;
+        assert_ok!(Whisper::send_message(
+            origin(1),
+            2,
+            b"soon".to_vec(),
+            EPHEMERAL_KEY,
+            5
+        ));
+        assert_ok!(Whisper::send_message(
+            origin(3),
+            2,
+            b"later".to_vec(),
+            EPHEMERAL_KEY,
+            9
+        ));
+        assert_ok!(send(4, 2, b"forever"));
+
+        System::set_block_number(4);
+        assert_ok!(Whisper::reap_expired(origin(1), 2));
+        assert_eq!(inbox(2).len(), 3);
+
+        // Expiry is inclusive of its block
+        System::set_block_number(5);
+        assert_ok!(Whisper::reap_expired(origin(1), 2));
+        assert_eq!(
+            inbox(2),
+            vec![(1, b"later".to_vec()), (2, b"forever".to_vec())]
+        );
+        assert_eq!(Whisper::inbox_count(2), 2);
+        System::assert_has_event(crate::Event::MessageExpired { account: 2, id: 0 }.into());
+    });
+}
+
+#[test]
+fn send_with_expiry_in_the_past_is_rejected() {
+    new_test_ext().execute_with(|| {
+        System::set_block_number(5);
+        let send_expiring = |from, expires_at| {
+            Whisper::send_message(
+                RuntimeOrigin::signed(from),
+                2,
+                b"bye".to_vec(),
+                EPHEMERAL_KEY,
+                expires_at,
+            )
+        };
+
+        assert_noop!(send_expiring(1, 4), Error::<Test>::ExpiryInPast);
+        assert_noop!(send_expiring(1, 5), Error::<Test>::ExpiryInPast);
+
+        assert_ok!(send_expiring(1, 6));
+        assert_ok!(send_expiring(3, 0));
+        assert_eq!(inbox(2).len(), 2);
+    });
+}
+
+#[test]
+fn reap_expired_removes_expired_outbox_copies() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(Whisper::send_message(
+            RuntimeOrigin::signed(1),
+            2,
+            b"soon".to_vec(),
+            EPHEMERAL_KEY,
+            5
+        ));
+        assert_ok!(send(1, 3, b"forever"));
+
+        System::set_block_number(5);
+        assert_ok!(Whisper::reap_expired(RuntimeOrigin::signed(9), 1));
+
+        assert_eq!(outbox(1), vec![(1, 3, b"forever".to_vec())]);
+        assert_eq!(Whisper::outbox_count(1), 1);
+        // The recipient's copy is reaped with their own account
+        assert_eq!(inbox(2).len(), 1);
+        assert_ok!(Whisper::reap_expired(RuntimeOrigin::signed(9), 2));
+        assert!(inbox(2).is_empty());
+    });
+}
+
+#[test]
+fn anyone_can_reap_an_inbox() {
+    new_test_ext().execute_with(|| {
+        let origin = RuntimeOrigin::signed;
+        assert_ok!(Whisper::send_message(
+            origin(1),
+            2,
+            b"bye".to_vec(),
+            EPHEMERAL_KEY,
+            2
+        ));
+
+        System::set_block_number(2);
+        // Neither the sender nor the recipient, and without a registered key
+        assert_ok!(Whisper::reap_expired(origin(9), 2));
+
+        assert!(inbox(2).is_empty());
+        assert_eq!(Whisper::inbox_count(2), 0);
+    });
+}
+
+#[test]
+fn send_to_self_is_rejected() {
+    new_test_ext().execute_with(|| {
+        assert_noop!(
+            send(2, 2, b"note to self"),
+            Error::<Test>::CannotMessageSelf
+        );
+        assert!(inbox(2).is_empty());
+    });
+}
+
+#[test]
+fn send_to_zero_account_is_rejected() {
+    new_test_ext().execute_with(|| {
+        // Even once someone has registered a key for it
+        assert_ok!(Whisper::register_key(RuntimeOrigin::signed(0), [1u8; 32]));
+        assert_noop!(send(1, 0, b"hi"), Error::<Test>::InvalidRecipient);
+        assert!(inbox(0).is_empty());
+    });
+}
+
+#[test]
+fn send_to_distinct_recipient_still_succeeds() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"hi"));
+        assert_eq!(inbox(2), vec![(0, b"hi".to_vec())]);
+    });
+}
+
+#[test]
+fn clear_inbox_removes_a_small_inbox_in_one_call() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"one"));
+        assert_ok!(send(3, 2, b"two"));
+        assert_ok!(send(1, 4, b"not yours"));
+
+        assert_ok!(Whisper::clear_inbox(RuntimeOrigin::signed(2)));
+
+        assert!(inbox(2).is_empty());
+        assert_eq!(Whisper::inbox_count(2), 0);
+        assert_eq!(inbox(4).len(), 1);
+        System::assert_last_event(
+            crate::Event::InboxCleared {
+                account: 2,
+                count: 2,
+                partial: false,
+            }
+            .into(),
+        );
+    });
+}
+
+#[test]
+fn clear_inbox_is_capped_per_call() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"one"));
+        assert_ok!(send(3, 2, b"two"));
+        assert_ok!(send(4, 2, b"three"));
+
+        // MaxClearPerCall is 2 in the mock
+        assert_ok!(Whisper::clear_inbox(RuntimeOrigin::signed(2)));
+        assert_eq!(inbox(2).len(), 1);
+        assert_eq!(Whisper::inbox_count(2), 1);
+        System::assert_last_event(
+            crate::Event::InboxCleared {
+                account: 2,
+                count: 2,
+                partial: true,
+            }
+            .into(),
+        );
+
+        assert_ok!(Whisper::clear_inbox(RuntimeOrigin::signed(2)));
+        assert!(inbox(2).is_empty());
+        System::assert_last_event(
+            crate::Event::InboxCleared {
+                account: 2,
+                count: 1,
+                partial: false,
+            }
+            .into(),
+        );
+
+        // Ids keep counting up after a clear
+        assert_ok!(send(5, 2, b"fresh"));
+        assert_eq!(inbox(2), vec![(3, b"fresh".to_vec())]);
+    });
+}
+
+#[test]
+fn sent_message_lands_in_sender_outbox_and_recipient_inbox() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"hello"));
+
+        assert_eq!(inbox(2), vec![(0, b"hello".to_vec())]);
+        assert_eq!(outbox(1), vec![(0, 2, b"hello".to_vec())]);
+        assert_eq!(
+            Whisper::outbox(1, 0).unwrap().ephemeral_public_key,
+            EPHEMERAL_KEY
+        );
+        assert!(inbox(1).is_empty());
+        assert!(outbox(2).is_empty());
+    });
+}
+
+#[test]
+fn inbox_and_outbox_copies_are_deleted_independently() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"hello"));
+
+        assert_ok!(Whisper::delete_message(RuntimeOrigin::signed(2), 0));
+        assert!(inbox(2).is_empty());
+        assert_eq!(outbox(1).len(), 1);
+
+        assert_ok!(send(3, 2, b"hey"));
+        assert_ok!(Whisper::delete_message(RuntimeOrigin::signed(3), 0));
+        assert!(outbox(3).is_empty());
+        assert_eq!(inbox(2), vec![(1, b"hey".to_vec())]);
+        assert_eq!(Whisper::inbox_count(2), 1);
+        System::assert_last_event(crate::Event::MessageDeleted { account: 3, id: 0 }.into());
+    });
+}
+
+#[test]
+fn inbox_and_outbox_share_one_id_sequence() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(send(1, 2, b"ping"));
+        assert_ok!(send(2, 1, b"pong"));
+        assert_ok!(send(3, 2, b"hey"));
+
+        // Account 2 received id 0, sent id 1 and received id 2
+        assert_eq!(inbox(2), vec![(0, b"ping".to_vec()), (2, b"hey".to_vec())]);
+        assert_eq!(outbox(2), vec![(1, 1, b"pong".to_vec())]);
+
+        assert_ok!(Whisper::delete_message(RuntimeOrigin::signed(2), 1));
+        assert!(outbox(2).is_empty());
+        assert_eq!(inbox(2).len(), 2);
+        assert_noop!(
+            Whisper::delete_message(RuntimeOrigin::signed(2), 1),
+            Error::<Test>::MessageNotFound
+        );
+    });
+}
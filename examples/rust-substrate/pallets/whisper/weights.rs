@@ -0,0 +1,153 @@
+//! Placeholder weights for the whisper pallet
+//!
+//! These are NOT benchmark results. The database accesses are counted from
+//! the code, but the execution times are hand estimates. Replace this file
+//! with the output of `frame-benchmarking-cli` run against `benchmarking.rs`
+//! (build with `--features runtime-benchmarks`) on reference hardware before
+//! using the pallet on a production chain. The `on_idle_*` benchmarks each
+//! time the whole hook, so subtract `on_idle_base` from `on_idle_inspect`
+//! and `on_idle_inspect` from `on_idle_prune` when filling those in.
+
+use core::marker::PhantomData;
+use frame_support::{
+    traits::Get,
+    weights::{constants::RocksDbWeight, Weight},
+};
+
+/// Weight functions needed for the whisper pallet
+pub trait WeightInfo {
+    fn send_message(n: u32) -> Weight;
+    fn delete_message(n: u32) -> Weight;
+    fn register_key() -> Weight;
+    fn reap_expired(n: u32) -> Weight;
+    fn clear_inbox(n: u32) -> Weight;
+    fn on_idle_base() -> Weight;
+    fn on_idle_inspect() -> Weight;
+    fn on_idle_prune() -> Weight;
+}
+
+/// Placeholder weights for the whisper pallet using the runtime's database weights
+pub struct SubstrateWeight<T>(PhantomData<T>);
+impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
+    /// Reads `Keys`, both counts, `LastSend` and both `NextMessageId`s,
+    /// then writes all of them but `Keys` plus one `Messages` and one
+    /// `Outbox` entry. `n` is the recipient's inbox size.
+    fn send_message(n: u32) -> Weight {
+        Weight::from_parts(30_000_000, 7_200)
+            // Messages are keyed individually, so a fuller inbox costs next to nothing
+            .saturating_add(Weight::from_parts(1_000, 0).saturating_mul(n.into()))
+            .saturating_add(T::DbWeight::get().reads(6_u64))
+            .saturating_add(T::DbWeight::get().writes(7_u64))
+    }
+
+    /// Checks `Messages` and `Outbox`, then removes the entry and updates
+    /// its count. `n` is the caller's inbox size.
+    fn delete_message(n: u32) -> Weight {
+        Weight::from_parts(18_000_000, 3_600)
+            .saturating_add(Weight::from_parts(1_000, 0).saturating_mul(n.into()))
+            .saturating_add(T::DbWeight::get().reads(3_u64))
+            .saturating_add(T::DbWeight::get().writes(2_u64))
+    }
+
+    /// Writes one `Keys` entry
+    fn register_key() -> Weight {
+        Weight::from_parts(12_000_000, 0)
+            .saturating_add(T::DbWeight::get().writes(1_u64))
+    }
+
+    /// Reads and removes `n` inbox and outbox entries and updates both
+    /// counts, `n` being at most twice `MaxMessagesPerAccount`
+    fn reap_expired(n: u32) -> Weight {
+        Weight::from_parts(15_000_000, 3_600)
+            .saturating_add(Weight::from_parts(6_000_000, 0).saturating_mul(n.into()))
+            .saturating_add(T::DbWeight::get().reads(2_u64))
+            .saturating_add(T::DbWeight::get().writes(2_u64))
+            .saturating_add(T::DbWeight::get().reads_writes(1, 1).saturating_mul(n.into()))
+    }
+
+    /// Reads and removes `n` inbox entries, at most `MaxClearPerCall`, and
+    /// updates the count
+    fn clear_inbox(n: u32) -> Weight {
+        Weight::from_parts(15_000_000, 3_600)
+            .saturating_add(Weight::from_parts(5_000_000, 0).saturating_mul(n.into()))
+            .saturating_add(T::DbWeight::get().reads(1_u64))
+            .saturating_add(T::DbWeight::get().writes(1_u64))
+            .saturating_add(T::DbWeight::get().reads_writes(1, 1).saturating_mul(n.into()))
+    }
+
+    /// Reads and writes `PruneCursor`, charged once per `on_idle`
+    fn on_idle_base() -> Weight {
+        Weight::from_parts(5_000_000, 1_500)
+            .saturating_add(T::DbWeight::get().reads(1_u64))
+            .saturating_add(T::DbWeight::get().writes(1_u64))
+    }
+
+    /// Reads one `Messages` or `Outbox` entry to check its age
+    fn on_idle_inspect() -> Weight {
+        Weight::from_parts(4_000_000, 3_600)
+            .saturating_add(T::DbWeight::get().reads(1_u64))
+    }
+
+    /// Removes one inspected entry, updates its count and deposits
+    /// `MessageExpired`
+    fn on_idle_prune() -> Weight {
+        Weight::from_parts(10_000_000, 0)
+            .saturating_add(T::DbWeight::get().reads(1_u64))
+            .saturating_add(T::DbWeight::get().writes(2_u64))
+    }
+}
+
+// The same placeholders for tests and runtimes without their own weights
+impl WeightInfo for () {
+    fn send_message(n: u32) -> Weight {
+        Weight::from_parts(30_000_000, 7_200)
+            .saturating_add(Weight::from_parts(1_000, 0).saturating_mul(n.into()))
+            .saturating_add(RocksDbWeight::get().reads(6_u64))
+            .saturating_add(RocksDbWeight::get().writes(7_u64))
+    }
+
+    fn delete_message(n: u32) -> Weight {
+        Weight::from_parts(18_000_000, 3_600)
+            .saturating_add(Weight::from_parts(1_000, 0).saturating_mul(n.into()))
+            .saturating_add(RocksDbWeight::get().reads(3_u64))
+            .saturating_add(RocksDbWeight::get().writes(2_u64))
+    }
+
+    fn register_key() -> Weight {
+        Weight::from_parts(12_000_000, 0)
+            .saturating_add(RocksDbWeight::get().writes(1_u64))
+    }
+
+    fn reap_expired(n: u32) -> Weight {
+        Weight::from_parts(15_000_000, 3_600)
+            .saturating_add(Weight::from_parts(6_000_000, 0).saturating_mul(n.into()))
+            .saturating_add(RocksDbWeight::get().reads(2_u64))
+            .saturating_add(RocksDbWeight::get().writes(2_u64))
+            .saturating_add(RocksDbWeight::get().reads_writes(1, 1).saturating_mul(n.into()))
+    }
+
+    fn clear_inbox(n: u32) -> Weight {
+        Weight::from_parts(15_000_000, 3_600)
+            .saturating_add(Weight::from_parts(5_000_000, 0).saturating_mul(n.into()))
+            .saturating_add(RocksDbWeight::get().reads(1_u64))
+            .saturating_add(RocksDbWeight::get().writes(1_u64))
+            .saturating_add(RocksDbWeight::get().reads_writes(1, 1).saturating_mul(n.into()))
+    }
+
+    fn on_idle_base() -> Weight {
+        Weight::from_parts(5_000_000, 1_500)
+            .saturating_add(RocksDbWeight::get().reads(1_u64))
+            .saturating_add(RocksDbWeight::get().writes(1_u64))
+    }
+
+    fn on_idle_inspect() -> Weight {
+        Weight::from_parts(4_000_000, 3_600)
+            .saturating_add(RocksDbWeight::get().reads(1_u64))
+    }
+
+    fn on_idle_prune() -> Weight {
+        Weight::from_parts(10_000_000, 0)
+            .saturating_add(RocksDbWeight::get().reads(1_u64))
+            .saturating_add(RocksDbWeight::get().writes(2_u64))
+    }
+}
@@ -0,0 +1,767 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::time::Duration;
+
+use solana_program::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program_error::ProgramError,
+    native_token::lamports_to_sol,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program, sysvar,
+};
+
+use crate::error::WhisperChainError;
+use crate::instruction::WhisperChainInstruction;
+use crate::processor::SendMessageArgs;
+use crate::state::{Chat, ChatConfig, ChatInit, Message, MessageHeader, MAX_MESSAGE_SIZE};
+
+/// Chat created by InitializeChat: `[b"chat", initializer]`
+pub fn chat_pda(program_id: &Pubkey, initializer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"chat", initializer.as_ref()], program_id)
+}
+
+/// Chat created by InitializeChatsBatch: `[b"chat", initializer, counterpart]`
+pub fn batch_chat_pda(program_id: &Pubkey, initializer: &Pubkey, counterpart: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"chat", initializer.as_ref(), counterpart.as_ref()],
+        program_id,
+    )
+}
+
+/// Chat created by InitializeChatPair: `[b"chat_pair", lower, higher]`,
+/// the same whichever of `a` and `b` is passed first
+pub fn pair_chat_pda(program_id: &Pubkey, a: &Pubkey, b: &Pubkey) -> (Pubkey, u8) {
+    let (lower, higher) = Chat::sorted_pair(a, b);
+    Pubkey::find_program_address(&[b"chat_pair", lower.as_ref(), higher.as_ref()], program_id)
+}
+
+/// Program-wide config: `[b"config"]`
+pub fn config_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"config"], program_id)
+}
+
+/// Program-wide counters: `[b"stats"]`
+pub fn stats_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"stats"], program_id)
+}
+
+/// Message at `index` in `chat`: `[b"message", chat, index]`
+pub fn message_pda(program_id: &Pubkey, chat: &Pubkey, index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"message", chat.as_ref(), &index.to_le_bytes()], program_id)
+}
+
+/// Dead letter left by `sender` for an uninitialized chat: `[b"dead_letter", sender, chat]`
+pub fn dead_letter_pda(program_id: &Pubkey, sender: &Pubkey, chat: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"dead_letter", sender.as_ref(), chat.as_ref()], program_id)
+}
+
+/// Every PDA a client needs to set up one chat, each with its bump.
+///
+/// Deriving them together saves integrators a string of separate
+/// `find_program_address` calls when opening a conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChatAddresses {
+    pub chat: (Pubkey, u8),
+    /// Message index 0, written by whoever sends first
+    pub first_message: (Pubkey, u8),
+    /// Where the initializer's early sends land while the chat is uninitialized
+    pub initializer_dead_letter: (Pubkey, u8),
+    /// Where the counterpart's early sends land while the chat is uninitialized
+    pub counterpart_dead_letter: (Pubkey, u8),
+}
+
+impl ChatAddresses {
+    /// Addresses for the chat InitializeChatsBatch opens between the pair
+    pub fn derive(program_id: &Pubkey, initializer: &Pubkey, counterpart: &Pubkey) -> Self {
+        let chat = batch_chat_pda(program_id, initializer, counterpart);
+        Self::for_chat(program_id, chat, initializer, counterpart)
+    }
+
+    /// Addresses around an already known chat, e.g. one from `chat_pda`
+    pub fn for_chat(
+        program_id: &Pubkey,
+        chat: (Pubkey, u8),
+        initializer: &Pubkey,
+        counterpart: &Pubkey,
+    ) -> Self {
+        Self {
+            chat,
+            first_message: message_pda(program_id, &chat.0, 0),
+            initializer_dead_letter: dead_letter_pda(program_id, initializer, &chat.0),
+            counterpart_dead_letter: dead_letter_pda(program_id, counterpart, &chat.0),
+        }
+    }
+}
+
+fn instruction(
+    program_id: &Pubkey,
+    accounts: Vec<AccountMeta>,
+    data: WhisperChainInstruction,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: data.try_to_vec().expect("instruction data serializes"),
+    }
+}
+
+pub fn initialize_chat_ix(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    public_key: [u8; 32],
+    config: ChatConfig,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*initializer, true),
+            AccountMeta::new(chat_pda(program_id, initializer).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(config_pda(program_id).0, false),
+        ],
+        WhisperChainInstruction::InitializeChat { public_key, config },
+    )
+}
+
+pub fn initialize_chats_batch_ix(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    entries: Vec<ChatInit>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*initializer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(config_pda(program_id).0, false),
+    ];
+    accounts.extend(entries.iter().map(|entry| {
+        AccountMeta::new(batch_chat_pda(program_id, initializer, &entry.counterpart).0, false)
+    }));
+
+    instruction(
+        program_id,
+        accounts,
+        WhisperChainInstruction::InitializeChatsBatch { entries },
+    )
+}
+
+pub fn initialize_chat_pair_ix(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    other: &Pubkey,
+    public_key: [u8; 32],
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*initializer, true),
+            AccountMeta::new(pair_chat_pda(program_id, initializer, other).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(config_pda(program_id).0, false),
+        ],
+        WhisperChainInstruction::InitializeChatPair {
+            other: *other,
+            public_key,
+        },
+    )
+}
+
+/// `message_index` must be the chat's current `message_count`
+pub fn send_message_ix(
+    program_id: &Pubkey,
+    sender: &Pubkey,
+    chat: &Pubkey,
+    message_index: u64,
+    args: SendMessageArgs,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*sender, true),
+            AccountMeta::new(*chat, false),
+            AccountMeta::new(message_pda(program_id, chat, message_index).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(config_pda(program_id).0, false),
+        ],
+        WhisperChainInstruction::SendMessage {
+            encrypted_data: args.encrypted_data,
+            ephemeral_public_key: args.ephemeral_public_key,
+            expires_at: args.expires_at,
+            visible_at: args.visible_at,
+            client_timestamp: args.client_timestamp,
+            client_nonce: args.client_nonce,
+            reply_to: args.reply_to,
+            show_sender: args.show_sender,
+        },
+    )
+}
+
+/// `live_indices` are the indices of every message account still open;
+/// close any that don't fit in the transaction with `close_messages_ix` first
+pub fn delete_chat_ix(
+    program_id: &Pubkey,
+    participant: &Pubkey,
+    chat: &Pubkey,
+    participants: (&Pubkey, &Pubkey),
+    live_indices: &[u64],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*participant, true),
+        AccountMeta::new(*chat, false),
+        AccountMeta::new(*participants.0, false),
+        AccountMeta::new(*participants.1, false),
+    ];
+    accounts.extend(
+        live_indices
+            .iter()
+            .map(|&index| AccountMeta::new(message_pda(program_id, chat, index).0, false)),
+    );
+
+    instruction(program_id, accounts, WhisperChainInstruction::DeleteChat)
+}
+
+/// Close the messages at `indices` in a chat between `participants`,
+/// refunding each to its sender
+pub fn close_messages_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    chat: &Pubkey,
+    participants: (&Pubkey, &Pubkey),
+    indices: &[u64],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*chat, false),
+        AccountMeta::new(*participants.0, false),
+        AccountMeta::new(*participants.1, false),
+    ];
+    accounts.extend(
+        indices
+            .iter()
+            .map(|&index| AccountMeta::new(message_pda(program_id, chat, index).0, false)),
+    );
+
+    instruction(program_id, accounts, WhisperChainInstruction::CloseMessages)
+}
+
+/// The rent refund always goes to the message's sender
+pub fn delete_message_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    chat: &Pubkey,
+    index: u64,
+    sender: &Pubkey,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(message_pda(program_id, chat, index).0, false),
+            AccountMeta::new(*chat, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new(*sender, false),
+        ],
+        WhisperChainInstruction::DeleteMessage,
+    )
+}
+
+/// Conservative compute-unit limit for a single WhisperChain instruction.
+///
+/// Prepend a `ComputeBudgetInstruction::set_compute_unit_limit` with this
+/// value (summed across instructions) so larger operations don't hit the
+/// default budget. The numbers leave headroom over measured usage.
+pub fn recommended_cu(instruction: &WhisperChainInstruction) -> u32 {
+    match instruction {
+        WhisperChainInstruction::InitializeChat { .. } => 30_000,
+        WhisperChainInstruction::SendMessage { encrypted_data, .. } => {
+            // Serialization cost grows with the ciphertext
+            35_000 + 10 * encrypted_data.len() as u32
+        }
+        // Covers closing a handful of trailing message accounts
+        WhisperChainInstruction::DeleteChat => 40_000,
+        WhisperChainInstruction::DeleteMessage => 15_000,
+        WhisperChainInstruction::InitializeChatsBatch { entries } => {
+            5_000 + 30_000 * entries.len() as u32
+        }
+        WhisperChainInstruction::EditMessage { new_encrypted_data, .. } => {
+            // Growing the account adds a rent top-up transfer
+            25_000 + 10 * new_encrypted_data.len() as u32
+        }
+        WhisperChainInstruction::MarkRead { .. } => 5_000,
+        // Per message: a PDA check, a hash and an event
+        WhisperChainInstruction::ArchiveToCompressed => 60_000,
+        WhisperChainInstruction::RestoreFromCompressed { proof, .. } => {
+            30_000 + 1_000 * proof.len() as u32
+        }
+        WhisperChainInstruction::RotateKey { .. } => 5_000,
+        WhisperChainInstruction::RevokeEphemeralKey { .. } => 5_000,
+        // Hashing the ciphertext plus instruction introspection
+        WhisperChainInstruction::AckMessage { .. } => 15_000,
+        WhisperChainInstruction::ClearDeadLetter => 10_000,
+        WhisperChainInstruction::ResyncChat => 40_000,
+        WhisperChainInstruction::SetBlocked { .. } => 5_000,
+        WhisperChainInstruction::RekeyAndResend { new_encrypted_data, .. } => {
+            // SendMessage plus the rotation and shrinking the old message
+            50_000 + 10 * new_encrypted_data.len() as u32
+        }
+        WhisperChainInstruction::ForwardMessage { new_encrypted_data, .. } => {
+            // SendMessage plus decoding the source chat and message
+            40_000 + 10 * new_encrypted_data.len() as u32
+        }
+        // Rewrites the whole message to flip one byte
+        WhisperChainInstruction::SetPinned { .. } => 10_000,
+        WhisperChainInstruction::React { .. } => 10_000,
+        WhisperChainInstruction::GetChatInfo => 5_000,
+        // DeleteChat plus hashing the digest
+        WhisperChainInstruction::ArchiveChat => 45_000,
+        WhisperChainInstruction::SendMessageBatch { messages } => messages
+            .iter()
+            .map(|entry| 35_000 + 10 * entry.encrypted_data.len() as u32)
+            .sum(),
+        WhisperChainInstruction::TransferParticipant { .. } => 10_000,
+        WhisperChainInstruction::MarkDelivered { .. } => 5_000,
+        WhisperChainInstruction::Ping => 1_000,
+        WhisperChainInstruction::InitConfig => 15_000,
+        WhisperChainInstruction::SetPaused { .. } => 5_000,
+        WhisperChainInstruction::SoftDeleteMessage => 15_000,
+        WhisperChainInstruction::InitializeChatPair { .. } => 30_000,
+        WhisperChainInstruction::SetFee { .. } => 5_000,
+        // Same per-message work as ArchiveToCompressed, minus the hashing
+        WhisperChainInstruction::CloseMessages => 50_000,
+    }
+}
+
+/// Decode a message account's metadata without touching the ciphertext.
+///
+/// Only the header bytes are read, so list views can skip allocating (or
+/// even fetching, with a data slice) the encrypted payload.
+pub fn read_message_header(account_data: &[u8]) -> Result<MessageHeader, ProgramError> {
+    Ok(MessageHeader::deserialize(&mut &account_data[..])?)
+}
+
+/// A problem found by `validate_conversation`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsistencyError {
+    /// The message points at a different chat account
+    WrongChat { index: u64, chat: Pubkey },
+    /// The index was never assigned by the chat
+    IndexOutOfBounds { index: u64, message_count: u64 },
+    /// More than one fetched message claims this index
+    DuplicateIndex { index: u64 },
+}
+
+/// Check fetched messages against their chat header.
+///
+/// Catches gaps or tampering on the RPC side: messages from another chat,
+/// indices the chat never handed out, and the same index returned twice.
+/// Returns every problem found rather than stopping at the first.
+pub fn validate_conversation(
+    chat: &Chat,
+    chat_pubkey: &Pubkey,
+    messages: &[Message],
+) -> Result<(), Vec<ConsistencyError>> {
+    let mut errors = Vec::new();
+
+    for (i, message) in messages.iter().enumerate() {
+        if message.chat != *chat_pubkey {
+            errors.push(ConsistencyError::WrongChat {
+                index: message.index,
+                chat: message.chat,
+            });
+        }
+
+        if message.index >= chat.message_count {
+            errors.push(ConsistencyError::IndexOutOfBounds {
+                index: message.index,
+                message_count: chat.message_count,
+            });
+        }
+
+        // Report each duplicated index once, at its second occurrence
+        let earlier = messages[..i].iter().filter(|m| m.index == message.index).count();
+        if earlier == 1 {
+            errors.push(ConsistencyError::DuplicateIndex { index: message.index });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Ciphertext sizes for every live message of `chat`.
+///
+/// Sizes the caller already knows (from headers or a local cache) are used
+/// as given; any live messages beyond them count as `MAX_MESSAGE_SIZE`, so
+/// passing an empty slice yields an upper bound without fetching anything.
+fn live_message_sizes<'a>(chat: &Chat, message_sizes: &'a [usize]) -> impl Iterator<Item = usize> + 'a {
+    let unknown = (chat.live_message_count as usize).saturating_sub(message_sizes.len());
+    message_sizes
+        .iter()
+        .copied()
+        .chain(std::iter::repeat_n(MAX_MESSAGE_SIZE, unknown))
+}
+
+/// Account bytes held by a chat and its live messages, ignoring replies' extra 8 bytes
+pub fn total_bytes(chat: &Chat, message_sizes: &[usize]) -> usize {
+    Chat::LEN
+        + live_message_sizes(chat, message_sizes)
+            .map(|size| Message::space(size, false, false))
+            .sum::<usize>()
+}
+
+/// Lamports locked as rent by a chat, split for display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RentEstimate {
+    /// Rent of the chat account itself
+    pub chat_lamports: u64,
+    /// Rent of all live message accounts
+    pub message_lamports: u64,
+    /// Number of message accounts counted
+    pub message_count: usize,
+    /// Account bytes covered, as returned by `total_bytes`
+    pub total_bytes: usize,
+}
+
+impl RentEstimate {
+    pub fn total_lamports(&self) -> u64 {
+        self.chat_lamports.saturating_add(self.message_lamports)
+    }
+}
+
+impl std::fmt::Display for RentEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} SOL locked ({} SOL chat, {} SOL across {} messages, {} bytes)",
+            lamports_to_sol(self.total_lamports()),
+            lamports_to_sol(self.chat_lamports),
+            lamports_to_sol(self.message_lamports),
+            self.message_count,
+            self.total_bytes,
+        )
+    }
+}
+
+/// Estimate the rent locked by a chat and its live messages.
+///
+/// Rent is charged per account, so each message is priced on its own rather
+/// than from `total_bytes` alone. See `total_bytes` for how `message_sizes`
+/// is completed.
+pub fn estimated_rent(rent: &Rent, chat: &Chat, message_sizes: &[usize]) -> RentEstimate {
+    let (message_count, message_lamports) = live_message_sizes(chat, message_sizes)
+        .fold((0, 0u64), |(count, lamports), size| {
+            let message_rent = rent.minimum_balance(Message::space(size, false, false));
+            (count + 1, lamports.saturating_add(message_rent))
+        });
+
+    RentEstimate {
+        chat_lamports: rent.minimum_balance(Chat::LEN),
+        message_lamports,
+        message_count,
+        total_bytes: total_bytes(chat, message_sizes),
+    }
+}
+
+/// Whether a SendMessage failed because another send took the index first.
+///
+/// A stale index derives a different PDA than the chat expects, and a lost
+/// race finds the PDA already funded; both are fixed by refetching the chat.
+pub fn is_index_conflict(error: &InstructionError) -> bool {
+    match error {
+        InstructionError::InvalidAccountData => true,
+        InstructionError::Custom(code) => *code == WhisperChainError::AlreadyInitialized as u32,
+        _ => false,
+    }
+}
+
+/// Hands out message indices for one chat when several devices send to it.
+///
+/// Indices are reserved optimistically from the last fetched
+/// `message_count`, so a device can have several sends in flight. When one
+/// is rejected with an index conflict, the caller refetches the chat and
+/// passes it to `on_conflict`, then waits the returned backoff before
+/// retrying. The backoff grows with each consecutive conflict and is
+/// jittered by a per-device seed so racing devices drift apart.
+#[derive(Debug, Clone)]
+pub struct SequenceManager {
+    next_index: u64,
+    conflicts: u32,
+    seed: u64,
+}
+
+impl SequenceManager {
+    /// Backoff after the first conflict, before jitter
+    pub const BASE_BACKOFF: Duration = Duration::from_millis(200);
+
+    /// Upper bound on the backoff, before jitter
+    pub const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+    /// Start from a freshly fetched chat; `seed` should differ per device
+    pub fn new(chat: &Chat, seed: u64) -> Self {
+        Self {
+            next_index: chat.message_count,
+            conflicts: 0,
+            // xorshift never leaves zero
+            seed: seed | 1,
+        }
+    }
+
+    /// Reserve the index for the next send
+    pub fn next_send_index(&mut self) -> u64 {
+        let index = self.next_index;
+        // The program rejects a send at u64::MAX long before this could wrap
+        self.next_index = self.next_index.saturating_add(1);
+        index
+    }
+
+    /// A send landed, so the next conflict starts from the base backoff again
+    pub fn on_confirmed(&mut self) {
+        self.conflicts = 0;
+    }
+
+    /// Drop outstanding reservations after a conflict and return how long to wait
+    pub fn on_conflict(&mut self, refetched: &Chat) -> Duration {
+        self.next_index = refetched.message_count;
+        self.conflicts = self.conflicts.saturating_add(1);
+
+        let backoff = Self::BASE_BACKOFF
+            .saturating_mul(1 << (self.conflicts - 1).min(16))
+            .min(Self::MAX_BACKOFF);
+
+        // xorshift keeps the jitter deterministic per device without a RNG dependency
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        let jitter = self.seed % (backoff.as_millis() as u64 + 1);
+
+        backoff + Duration::from_millis(jitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(chat: Pubkey, index: u64) -> Message {
+        let mut message = Message::try_from_slice(&vec![0u8; Message::space(0, false, false)]).unwrap();
+        message.is_initialized = true;
+        message.chat = chat;
+        message.index = index;
+        message
+    }
+
+    fn chat_with_count(message_count: u64) -> Chat {
+        let mut chat = Chat::try_from_slice(&[0u8; Chat::LEN]).unwrap();
+        chat.is_initialized = true;
+        chat.message_count = message_count;
+        chat
+    }
+
+    #[test]
+    fn test_consistent_conversation() {
+        let key = Pubkey::new_unique();
+        let messages = [message(key, 0), message(key, 2)];
+        assert_eq!(validate_conversation(&chat_with_count(3), &key, &messages), Ok(()));
+    }
+
+    #[test]
+    fn test_mismatched_chat_and_out_of_bounds() {
+        let key = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let messages = [message(other, 0), message(key, 3)];
+
+        let errors = validate_conversation(&chat_with_count(3), &key, &messages).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                ConsistencyError::WrongChat { index: 0, chat: other },
+                ConsistencyError::IndexOutOfBounds { index: 3, message_count: 3 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_index() {
+        let key = Pubkey::new_unique();
+        let messages = [message(key, 1), message(key, 0), message(key, 1), message(key, 1)];
+
+        let errors = validate_conversation(&chat_with_count(2), &key, &messages).unwrap_err();
+        assert_eq!(errors, vec![ConsistencyError::DuplicateIndex { index: 1 }]);
+    }
+
+    #[test]
+    fn test_header_matches_full_decode() {
+        let mut full = message(Pubkey::new_unique(), 4);
+        full.sender = Pubkey::new_unique();
+        full.timestamp = 1_700_000_000;
+        full.reply_to = Some(2);
+        full.forwarded_from = Some(Pubkey::new_unique());
+        full.ephemeral_public_key = [7u8; 32];
+        full.encrypted_data = vec![9u8; 300];
+        let data = full.try_to_vec().unwrap();
+
+        let header = read_message_header(&data).unwrap();
+        assert_eq!(header.chat, full.chat);
+        assert_eq!(header.sender, full.sender);
+        assert_eq!(header.index, 4);
+        assert_eq!(header.timestamp, full.timestamp);
+        assert_eq!(header.reply_to, Some(2));
+        assert_eq!(header.forwarded_from, full.forwarded_from);
+        assert_eq!(header.ephemeral_public_key, full.ephemeral_public_key);
+        assert_eq!(header.encrypted_data_len, 300);
+
+        // The ciphertext itself is never read, so a truncated slice decodes
+        let header_len = data.len() - full.encrypted_data.len();
+        assert_eq!(read_message_header(&data[..header_len]).unwrap(), header);
+        assert!(read_message_header(&data[..header_len - 1]).is_err());
+    }
+
+    #[test]
+    fn test_send_message_accounts() {
+        let program_id = Pubkey::new_unique();
+        let sender = Pubkey::new_unique();
+        let (chat, _) = chat_pda(&program_id, &sender);
+        let args = SendMessageArgs {
+            encrypted_data: vec![1u8; 16],
+            ephemeral_public_key: [7u8; 32],
+            expires_at: 0,
+            visible_at: 0,
+            client_timestamp: 0,
+            client_nonce: [0u8; 16],
+            reply_to: None,
+            show_sender: true,
+        };
+
+        let ix = send_message_ix(&program_id, &sender, &chat, 3, args);
+        let keys: Vec<_> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(
+            keys,
+            vec![
+                sender,
+                chat,
+                message_pda(&program_id, &chat, 3).0,
+                system_program::id(),
+                sysvar::clock::id(),
+                config_pda(&program_id).0,
+            ]
+        );
+        assert!(ix.accounts[0].is_signer && ix.accounts[2].is_writable);
+        assert!(matches!(
+            WhisperChainInstruction::try_from_slice(&ix.data).unwrap(),
+            WhisperChainInstruction::SendMessage { .. }
+        ));
+    }
+
+    #[test]
+    fn test_estimated_rent_matches_minimum_balances() {
+        let rent = Rent::default();
+        let mut chat = chat_with_count(3);
+        chat.live_message_count = 3;
+
+        let estimate = estimated_rent(&rent, &chat, &[16, 128, 512]);
+        let expected_messages = rent.minimum_balance(Message::space(16, false, false))
+            + rent.minimum_balance(Message::space(128, false, false))
+            + rent.minimum_balance(Message::space(512, false, false));
+        assert_eq!(estimate.chat_lamports, rent.minimum_balance(Chat::LEN));
+        assert_eq!(estimate.message_lamports, expected_messages);
+        assert_eq!(estimate.total_lamports(), rent.minimum_balance(Chat::LEN) + expected_messages);
+        assert_eq!(estimate.message_count, 3);
+        assert_eq!(
+            estimate.total_bytes,
+            Chat::LEN + Message::space(16, false, false) + Message::space(128, false, false) + Message::space(512, false, false)
+        );
+    }
+
+    #[test]
+    fn test_estimated_rent_bounds_unknown_sizes() {
+        let rent = Rent::default();
+        let mut chat = chat_with_count(5);
+        chat.live_message_count = 2;
+
+        let bound = estimated_rent(&rent, &chat, &[]);
+        assert_eq!(bound.message_count, 2);
+        assert_eq!(
+            bound.message_lamports,
+            2 * rent.minimum_balance(Message::space(MAX_MESSAGE_SIZE, false, false))
+        );
+        assert!(estimated_rent(&rent, &chat, &[16]).total_lamports() < bound.total_lamports());
+        assert_eq!(estimated_rent(&rent, &chat_with_count(0), &[]).message_lamports, 0);
+    }
+
+    #[test]
+    fn test_index_conflicts() {
+        assert!(is_index_conflict(&InstructionError::InvalidAccountData));
+        assert!(is_index_conflict(&InstructionError::Custom(
+            WhisperChainError::AlreadyInitialized as u32
+        )));
+        assert!(!is_index_conflict(&InstructionError::Custom(
+            WhisperChainError::NotAuthorized as u32
+        )));
+        assert!(!is_index_conflict(&InstructionError::MissingRequiredSignature));
+    }
+
+    #[test]
+    fn test_sequence_reserves_and_resets() {
+        let mut manager = SequenceManager::new(&chat_with_count(4), 1);
+        assert_eq!(manager.next_send_index(), 4);
+        assert_eq!(manager.next_send_index(), 5);
+
+        let first = manager.on_conflict(&chat_with_count(7));
+        assert_eq!(manager.next_send_index(), 7);
+        assert!(first >= SequenceManager::BASE_BACKOFF);
+        assert!(first <= 2 * SequenceManager::BASE_BACKOFF);
+
+        for _ in 0..20 {
+            let backoff = manager.on_conflict(&chat_with_count(7));
+            assert!(backoff <= 2 * SequenceManager::MAX_BACKOFF);
+        }
+    }
+
+    #[test]
+    fn test_racing_sequence_managers_both_make_progress() {
+        // The chat accepts a send only at its current message_count
+        let mut chat = chat_with_count(0);
+        let mut devices = [
+            (SequenceManager::new(&chat, 0x9e37_79b9_7f4a_7c15), Duration::ZERO, 0u32),
+            (SequenceManager::new(&chat, 0x2545_f491_4f6c_dd1d), Duration::ZERO, 0u32),
+        ];
+        let mut conflicts = 0;
+
+        while devices.iter().any(|(_, _, sent)| *sent < 5) {
+            let now = devices
+                .iter()
+                .filter(|(_, _, sent)| *sent < 5)
+                .map(|(_, ready_at, _)| *ready_at)
+                .min()
+                .unwrap();
+
+            // Every device ready at the same instant submits against the same chat state
+            let indices: Vec<_> = devices
+                .iter_mut()
+                .map(|(manager, ready_at, sent)| {
+                    (*ready_at == now && *sent < 5).then(|| manager.next_send_index())
+                })
+                .collect();
+            let accepted = chat.message_count;
+
+            for ((manager, ready_at, sent), index) in devices.iter_mut().zip(indices) {
+                let Some(index) = index else { continue };
+                if index == accepted && chat.message_count == accepted {
+                    chat.message_count += 1;
+                    manager.on_confirmed();
+                    *sent += 1;
+                    *ready_at = now + Duration::from_millis(1);
+                } else {
+                    conflicts += 1;
+                    *ready_at = now + manager.on_conflict(&chat);
+                }
+            }
+            assert!(conflicts < 50, "managers kept colliding");
+        }
+
+        assert_eq!(chat.message_count, 10);
+    }
+}
@@ -0,0 +1,123 @@
+use solana_program::{ed25519_program, instruction::Instruction, pubkey::Pubkey};
+
+/// Curve25519 field prime 2^255 - 19, little-endian
+const FIELD_PRIME: [u8; 32] = [
+    0xed, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f,
+];
+
+/// Canonical u-coordinates of the points whose order divides the cofactor.
+/// A DH exchange with any of them yields a shared secret an attacker can guess.
+const SMALL_ORDER_POINTS: [[u8; 32]; 5] = [
+    // 0 (order 2)
+    [0x00; 32],
+    // 1 (order 4)
+    [
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ],
+    // Order 8
+    [
+        0xe0, 0xeb, 0x7a, 0x7c, 0x3b, 0x41, 0xb8, 0xae, 0x16, 0x56, 0xe3, 0xfa, 0xf1, 0x9f, 0xc4, 0x6a,
+        0xda, 0x09, 0x8d, 0xeb, 0x9c, 0x32, 0xb1, 0xfd, 0x86, 0x62, 0x05, 0x16, 0x5f, 0x49, 0xb8, 0x00,
+    ],
+    // Order 8
+    [
+        0x5f, 0x9c, 0x95, 0xbc, 0xa3, 0x50, 0x8c, 0x24, 0xb1, 0xd0, 0xb1, 0x55, 0x9c, 0x83, 0xef, 0x5b,
+        0x04, 0x44, 0x5c, 0xc4, 0x58, 0x1c, 0x8e, 0x86, 0xd8, 0x22, 0x4e, 0xdd, 0xd0, 0x9f, 0x11, 0x57,
+    ],
+    // p - 1
+    [
+        0xec, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f,
+    ],
+];
+
+/// Whether `key` is in reduced form, i.e. a little-endian integer below p
+fn is_canonical(key: &[u8; 32]) -> bool {
+    key.iter().rev().lt(FIELD_PRIME.iter().rev())
+}
+
+/// Whether `key` is a canonical curve25519 u-coordinate that is safe for DH
+pub fn is_valid_public_key(key: &[u8; 32]) -> bool {
+    is_canonical(key) && !SMALL_ORDER_POINTS.contains(key)
+}
+
+/// Offsets in ed25519 program data: count and padding, then seven u16 fields
+const ED25519_OFFSETS_START: usize = 2;
+const ED25519_OFFSETS_END: usize = ED25519_OFFSETS_START + 14;
+
+/// Whether `ix` is an ed25519 program instruction verifying exactly
+/// `signature` by `pubkey` over `message`, with all data inline.
+///
+/// The runtime rejects the transaction if the signature is invalid, so a
+/// match means the signature was checked.
+pub fn is_ed25519_verification(
+    ix: &Instruction,
+    pubkey: &Pubkey,
+    message: &[u8],
+    signature: &[u8; 64],
+) -> bool {
+    if ix.program_id != ed25519_program::id() || ix.data.len() < ED25519_OFFSETS_END {
+        return false;
+    }
+
+    // A single signature
+    if ix.data[0] != 1 {
+        return false;
+    }
+
+    let field = |i: usize| {
+        let at = ED25519_OFFSETS_START + 2 * i;
+        u16::from_le_bytes([ix.data[at], ix.data[at + 1]]) as usize
+    };
+    let (signature_offset, public_key_offset) = (field(0), field(2));
+    let (message_offset, message_size) = (field(4), field(5));
+
+    // Data referenced from other instructions could say anything
+    let inline = u16::MAX as usize;
+    if field(1) != inline || field(3) != inline || field(6) != inline {
+        return false;
+    }
+
+    let slice = |offset: usize, len: usize| ix.data.get(offset..offset.checked_add(len)?);
+    slice(signature_offset, 64) == Some(&signature[..])
+        && slice(public_key_offset, 32) == Some(pubkey.as_ref())
+        && slice(message_offset, message_size) == Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_with(first: u8, fill: u8, last: u8) -> [u8; 32] {
+        let mut key = [fill; 32];
+        key[0] = first;
+        key[31] = last;
+        key
+    }
+
+    #[test]
+    fn test_small_order_points_are_rejected() {
+        for point in SMALL_ORDER_POINTS {
+            assert!(!is_valid_public_key(&point));
+        }
+    }
+
+    #[test]
+    fn test_non_canonical_encodings_are_rejected() {
+        // p, p + 1 and anything with the top bit set
+        assert!(!is_valid_public_key(&FIELD_PRIME));
+        assert!(!is_valid_public_key(&key_with(0xee, 0xff, 0x7f)));
+        assert!(!is_valid_public_key(&key_with(0x09, 0x00, 0x80)));
+        assert!(!is_valid_public_key(&[0xff; 32]));
+    }
+
+    #[test]
+    fn test_ordinary_points_are_accepted() {
+        // The X25519 base point, u = 9
+        assert!(is_valid_public_key(&key_with(0x09, 0x00, 0x00)));
+        assert!(is_valid_public_key(&[7u8; 32]));
+        assert!(is_valid_public_key(&key_with(0xeb, 0xff, 0x7f)));
+    }
+}
@@ -1,7 +1,13 @@
-use solana_program::program_error::ProgramError;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
 use thiserror::Error;
 
-#[derive(Error, Debug, Copy, Clone)]
+#[derive(Error, Debug, Copy, Clone, PartialEq, FromPrimitive)]
 pub enum WhisperChainError {
     #[error("Invalid instruction")]
     InvalidInstruction,
@@ -26,6 +32,51 @@ pub enum WhisperChainError {
 
     #[error("Data too large")]
     DataTooLarge,
+
+    #[error("Invalid expiration")]
+    InvalidExpiration,
+
+    #[error("Message size not allowed by chat")]
+    InvalidPadding,
+
+    #[error("Account growth exceeds the per-instruction realloc limit")]
+    ReallocLimitExceeded,
+
+    #[error("Invalid Merkle proof")]
+    InvalidMerkleProof,
+
+    #[error("Message quota exceeded")]
+    QuotaExceeded,
+
+    #[error("Unsupported account version")]
+    UnsupportedVersion,
+
+    #[error("Sending too fast")]
+    RateLimited,
+
+    #[error("Key rotated too recently")]
+    RotationTooFrequent,
+
+    #[error("Chat already has the maximum number of pinned messages")]
+    PinLimitReached,
+
+    #[error("Nonce already used by a recent message")]
+    DuplicateNonce,
+
+    #[error("Program is paused")]
+    ProgramPaused,
+
+    #[error("Insufficient funds for the send fee and rent")]
+    InsufficientFunds,
+
+    #[error("Unknown instruction discriminant")]
+    UnknownInstruction,
+
+    #[error("Instruction data does not match its discriminant")]
+    MalformedInstruction,
+
+    #[error("Message is not visible yet")]
+    NotYetVisible,
 }
 
 impl From<WhisperChainError> for ProgramError {
@@ -33,3 +84,38 @@ impl From<WhisperChainError> for ProgramError {
         ProgramError::Custom(e as u32)
     }
 }
+
+impl<T> DecodeError<T> for WhisperChainError {
+    fn type_of() -> &'static str {
+        "WhisperChainError"
+    }
+}
+
+impl PrintProgramError for WhisperChainError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        msg!("Error: {}", self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_codes_decode_to_variants() {
+        let mut code = 0;
+        while let Some(error) = WhisperChainError::from_u32(code) {
+            let program_error: ProgramError = error.into();
+            assert_eq!(program_error, ProgramError::Custom(code));
+            assert_eq!(
+                <WhisperChainError as DecodeError<WhisperChainError>>::decode_custom_error_to_enum(code),
+                Some(error)
+            );
+            code += 1;
+        }
+        assert!(code > 0);
+    }
+}
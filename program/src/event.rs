@@ -0,0 +1,248 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{entrypoint::ProgramResult, log::sol_log_data, pubkey::Pubkey};
+
+use crate::state::Message;
+
+/// Structured event logged with `sol_log_data` for off-chain indexers.
+///
+/// Each payload is prefixed with an 8-byte discriminator,
+/// `sha256("event:<Name>")[..8]`, so clients can demux them.
+pub trait Event: BorshSerialize {
+    const DISCRIMINATOR: [u8; 8];
+
+    /// Discriminator followed by the Borsh-encoded event
+    fn to_log_data(&self) -> Result<Vec<u8>, std::io::Error> {
+        let mut data = Self::DISCRIMINATOR.to_vec();
+        self.serialize(&mut data)?;
+        Ok(data)
+    }
+
+    fn emit(&self) -> ProgramResult {
+        sol_log_data(&[&self.to_log_data()?]);
+        Ok(())
+    }
+}
+
+/// A chat account was created
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ChatInitializedEvent {
+    pub chat: Pubkey,
+    pub participant1: Pubkey,
+    /// Default until participant2 joins, unless named at init
+    pub participant2: Pubkey,
+    pub created_at: i64,
+}
+
+impl Event for ChatInitializedEvent {
+    const DISCRIMINATOR: [u8; 8] = [250, 140, 100, 13, 242, 91, 68, 49];
+}
+
+/// A message account was created
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct MessageSentEvent {
+    pub chat: Pubkey,
+    /// The sender's commitment rather than their key for unattributed messages
+    pub sender: Pubkey,
+    pub index: u64,
+    pub timestamp: i64,
+    pub expires_at: i64,
+}
+
+impl Event for MessageSentEvent {
+    const DISCRIMINATOR: [u8; 8] = [116, 70, 224, 76, 128, 28, 110, 55];
+}
+
+/// Participant2 joined an open chat by sending its first message.
+///
+/// Logged alongside that message's MessageSentEvent, only when the chat's
+/// participant2 goes from unset to the sender.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ParticipantJoinedEvent {
+    pub chat: Pubkey,
+    pub participant: Pubkey,
+    /// Key the participant joined with, now `participant2_public_key`
+    pub public_key: [u8; 32],
+}
+
+impl Event for ParticipantJoinedEvent {
+    const DISCRIMINATOR: [u8; 8] = [48, 182, 206, 15, 56, 181, 24, 253];
+}
+
+/// A message account was closed
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct MessageDeletedEvent {
+    pub chat: Pubkey,
+    pub message: Pubkey,
+    pub index: u64,
+    /// Signer that triggered the delete
+    pub deleted_by: Pubkey,
+}
+
+impl Event for MessageDeletedEvent {
+    const DISCRIMINATOR: [u8; 8] = [100, 213, 172, 129, 17, 208, 177, 23];
+}
+
+/// A chat account was closed
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ChatDeletedEvent {
+    pub chat: Pubkey,
+    /// Participant that triggered the delete
+    pub deleted_by: Pubkey,
+}
+
+impl Event for ChatDeletedEvent {
+    const DISCRIMINATOR: [u8; 8] = [26, 148, 12, 67, 134, 213, 148, 133];
+}
+
+/// A message account was folded into its chat's archive root.
+///
+/// Carries the whole message so indexers can keep the copy needed for a
+/// later RestoreFromCompressed.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct MessageArchivedEvent {
+    pub chat: Pubkey,
+    pub message: Message,
+}
+
+impl Event for MessageArchivedEvent {
+    const DISCRIMINATOR: [u8; 8] = [168, 150, 111, 65, 237, 250, 82, 145];
+}
+
+/// A chat account was closed by ArchiveChat, leaving its state digest behind
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ChatArchivedEvent {
+    pub chat: Pubkey,
+    /// `Chat::state_digest` just before the close
+    pub digest: [u8; 32],
+}
+
+impl Event for ChatArchivedEvent {
+    const DISCRIMINATOR: [u8; 8] = [77, 91, 80, 91, 82, 129, 87, 133];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::hash::hash;
+
+    fn assert_roundtrip<E: Event + BorshDeserialize + PartialEq + std::fmt::Debug>(
+        name: &str,
+        event: E,
+    ) {
+        let expected = hash(format!("event:{}", name).as_bytes()).to_bytes();
+        assert_eq!(E::DISCRIMINATOR, expected[..8]);
+
+        let data = event.to_log_data().unwrap();
+        assert_eq!(data[..8], E::DISCRIMINATOR);
+        assert_eq!(E::try_from_slice(&data[8..]).unwrap(), event);
+    }
+
+    #[test]
+    fn test_chat_initialized_event() {
+        assert_roundtrip(
+            "ChatInitialized",
+            ChatInitializedEvent {
+                chat: Pubkey::new_unique(),
+                participant1: Pubkey::new_unique(),
+                participant2: Pubkey::default(),
+                created_at: 1_700_000_000,
+            },
+        );
+    }
+
+    #[test]
+    fn test_message_sent_event() {
+        assert_roundtrip(
+            "MessageSent",
+            MessageSentEvent {
+                chat: Pubkey::new_unique(),
+                sender: Pubkey::new_unique(),
+                index: 42,
+                timestamp: 1_700_000_000,
+                expires_at: 1_700_003_600,
+            },
+        );
+    }
+
+    #[test]
+    fn test_participant_joined_event() {
+        assert_roundtrip(
+            "ParticipantJoined",
+            ParticipantJoinedEvent {
+                chat: Pubkey::new_unique(),
+                participant: Pubkey::new_unique(),
+                public_key: [9u8; 32],
+            },
+        );
+    }
+
+    #[test]
+    fn test_message_deleted_event() {
+        assert_roundtrip(
+            "MessageDeleted",
+            MessageDeletedEvent {
+                chat: Pubkey::new_unique(),
+                message: Pubkey::new_unique(),
+                index: 7,
+                deleted_by: Pubkey::new_unique(),
+            },
+        );
+    }
+
+    #[test]
+    fn test_chat_deleted_event() {
+        assert_roundtrip(
+            "ChatDeleted",
+            ChatDeletedEvent {
+                chat: Pubkey::new_unique(),
+                deleted_by: Pubkey::new_unique(),
+            },
+        );
+    }
+
+    #[test]
+    fn test_chat_archived_event() {
+        assert_roundtrip(
+            "ChatArchived",
+            ChatArchivedEvent {
+                chat: Pubkey::new_unique(),
+                digest: [5u8; 32],
+            },
+        );
+    }
+
+    #[test]
+    fn test_message_archived_event() {
+        assert_roundtrip(
+            "MessageArchived",
+            MessageArchivedEvent {
+                chat: Pubkey::new_unique(),
+                message: Message {
+                    version: Message::VERSION,
+                    is_initialized: true,
+                    chat: Pubkey::new_unique(),
+                    sender: Pubkey::new_unique(),
+                    show_sender: true,
+                    index: 3,
+                    timestamp: 1_700_000_000,
+                    client_timestamp: 0,
+                    client_nonce: [0u8; 16],
+                    clock_drift_flag: false,
+                    expires_at: 0,
+                    visible_at: 0,
+                    edited_at: 0,
+                    deleted: false,
+                    ack_signature: [0u8; 64],
+                    pinned: false,
+                    reactions: [0, 2, 0, 0, 0, 0, 0, 1],
+                    participant1_last_reaction_nonce: 9,
+                    participant2_last_reaction_nonce: 0,
+                    reply_to: Some(1),
+                    forwarded_from: None,
+                    ephemeral_public_key: [7u8; 32],
+                    encrypted_data: vec![1, 2, 3],
+                },
+            },
+        );
+    }
+}
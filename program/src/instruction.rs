@@ -1,50 +1,579 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::pubkey::Pubkey;
+
+use crate::error::WhisperChainError;
+use crate::state::{BatchEntry, ChatConfig, ChatInit, Message};
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub enum WhisperChainInstruction {
     /// Initialize a new chat between two participants
     ///
+    /// Participant2 joins by sending the first message. Until then the
+    /// initializer can only send if `config.allow_self_chat` is set.
+    ///
     /// Accounts expected:
     /// 0. `[writable, signer]` Chat initializer (payer)
     /// 1. `[writable]` Chat account (PDA)
     /// 2. `[]` System program
+    /// 3. `[]` Config account (PDA, may be uninitialized)
+    /// 4. `[writable]` Optional stats account (PDA), counts the new chat
     InitializeChat {
         /// Public key for Diffie-Hellman exchange (32 bytes)
         public_key: [u8; 32],
+        /// Settings for the new chat
+        config: ChatConfig,
     },
 
     /// Send an encrypted message to a chat
     ///
+    /// By default a chat that isn't initialized is rejected. Passing the
+    /// optional dead-letter account opts in to storing the message there
+    /// instead when the chat is allocated but still zeroed.
+    ///
+    /// On success the created account is set as return data (a Borsh
+    /// `SentMessage`); a dead-lettered send returns nothing.
+    ///
     /// Accounts expected:
     /// 0. `[writable, signer]` Message sender (payer)
     /// 1. `[writable]` Chat account
     /// 2. `[writable]` Message account (PDA)
     /// 3. `[]` System program
     /// 4. `[]` Clock sysvar
+    /// 5. `[]` Config account (PDA, may be uninitialized)
+    /// 6. `[writable]` Optional dead-letter account (PDA of sender and chat)
+    /// 7. `[writable]` Optional stats account (PDA), counts the new message
+    /// 8. `[writable]` Treasury from the config, required while a send fee is set
+    ///
+    /// The optional accounts are told apart by address, so any of them may
+    /// be passed alone from index 6.
     SendMessage {
         /// Encrypted message data (max 512 bytes)
         encrypted_data: Vec<u8>,
         /// Ephemeral public key for this message (32 bytes)
         ephemeral_public_key: [u8; 32],
-        /// Message timestamp
-        timestamp: i64,
-        /// Optional expiration timestamp (0 = never expires)
+        /// Optional expiration timestamp (0 = never expires, otherwise must be in the future)
         expires_at: i64,
+        /// Optional time the message becomes visible to clients (0 = immediately)
+        visible_at: i64,
+        /// Send time according to the client's clock (0 = not reported)
+        client_timestamp: i64,
+        /// Random value identifying this send, so a retry of it is rejected
+        /// as a duplicate (zero = no deduplication)
+        client_nonce: [u8; 16],
+        /// Index of an earlier message in the chat this one replies to
+        reply_to: Option<u64>,
+        /// Attribute the message to the sender (false stores a commitment instead
+        /// of their key and asks clients not to display it; the sender stays
+        /// visible as the transaction signer)
+        show_sender: bool,
     },
 
     /// Delete a chat and all associated data
     ///
+    /// Every live message account must be closed so none is left stranded:
+    /// those still open are passed here and, as with CloseMessages, each
+    /// one's rent goes back to its sender. The chat's rent goes to the
+    /// signer. Chats with more live messages than fit in one transaction
+    /// are emptied with CloseMessages first.
+    ///
     /// Accounts expected:
     /// 0. `[writable, signer]` Chat participant
     /// 1. `[writable]` Chat account
+    /// 2. `[writable]` Participant 1 (rent refund)
+    /// 3. `[writable]` Participant 2 (rent refund)
+    /// 4. `[writable]` Message account (PDA) for each message still live
     DeleteChat,
 
     /// Delete a specific message (self-destruct)
     ///
+    /// Live messages can only be deleted by their sender; once expired,
+    /// either chat participant may reap them.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Message sender, or a chat participant if expired
+    /// 1. `[writable]` Message account
+    /// 2. `[writable]` Chat account
+    /// 3. `[]` Clock sysvar
+    /// 4. `[writable]` Rent refund destination (must be the message sender)
+    DeleteMessage,
+
+    /// Initialize several chats with known counterparts at once
+    ///
+    /// Each chat is a PDA derived from the initializer and the counterpart,
+    /// and starts with the default `ChatConfig`.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Chat initializer (payer)
+    /// 1. `[]` System program
+    /// 2. `[]` Config account (PDA, may be uninitialized)
+    /// 3. `[writable]` Chat account (PDA) for each entry, in the same order as `entries`
+    /// 4. `[writable]` Optional stats account (PDA) after the chat accounts, counts every new chat
+    InitializeChatsBatch {
+        /// Chats to create (max 8)
+        entries: Vec<ChatInit>,
+    },
+
+    /// Replace the ciphertext of an existing message in place
+    ///
+    /// The message keeps its index, timestamp and chat. The account is
+    /// resized to fit the new data; the sender tops up or gets back rent.
+    ///
     /// Accounts expected:
     /// 0. `[writable, signer]` Message sender
     /// 1. `[writable]` Message account
     /// 2. `[]` Chat account
+    /// 3. `[]` System program
+    EditMessage {
+        /// New encrypted message data (max 512 bytes)
+        new_encrypted_data: Vec<u8>,
+        /// Ephemeral public key the new data was encrypted with
+        new_ephemeral_public_key: [u8; 32],
+    },
+
+    /// Advance the signer's read cursor
+    ///
+    /// Cursors only move forward. The counterpart's cursor tells a sender
+    /// which of their messages have been seen. The signer's delivery cursor
+    /// is advanced too if it was behind.
+    ///
+    /// In a chat with `config.strict_visibility`, the message accounts for
+    /// every index from the current cursor through `up_to_index` must be
+    /// passed, and the read fails with `NotYetVisible` if any of them is
+    /// still scheduled. Deleted messages are passed as their closed PDAs.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Chat participant
+    /// 1. `[writable]` Chat account
+    /// 2. `[]` Strict chats only: message account (PDA) for each newly read index
+    MarkRead {
+        /// Index of the newest message read (must be below the message count)
+        up_to_index: u64,
+    },
+
+    /// Fold old message accounts into a Merkle root stored on the chat
+    ///
+    /// Each message is emitted as a `MessageArchived` event and its account
+    /// closed, with rent going back to the sender. Leaves are the raw account
+    /// bytes in the order given. A chat can only be archived once.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Chat participant
+    /// 1. `[writable]` Chat account
+    /// 2. `[writable]` Participant 1 (rent refund)
+    /// 3. `[writable]` Participant 2 (rent refund)
+    /// 4. `[writable]` Message account (PDA) for each message to archive
+    ArchiveToCompressed,
+
+    /// Recreate an archived message account from its Merkle proof
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Chat participant (payer)
+    /// 1. `[writable]` Chat account
+    /// 2. `[writable]` Message account (PDA)
+    /// 3. `[]` System program
+    RestoreFromCompressed {
+        /// The message exactly as it was archived
+        message: Box<Message>,
+        /// Sibling hashes from the leaf up to the chat's root
+        proof: Vec<[u8; 32]>,
+    },
+
+    /// Replace the signer's static DH public key, e.g. after a device compromise
+    ///
+    /// Increments the chat's `key_version`. Rotations closer together than
+    /// the chat's `min_rotation_interval_secs` are rejected.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Chat participant
+    /// 1. `[writable]` Chat account
+    RotateKey {
+        /// New public key for DH exchange (a safe curve25519 point)
+        new_public_key: [u8; 32],
+    },
+
+    /// Mark a leaked ephemeral key as revoked on the chat
+    ///
+    /// Clients treat messages encrypted with a revoked key as compromised
+    /// and may delete them. Only the last `MAX_REVOKED_KEYS` keys are kept.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Chat participant
+    /// 1. `[writable]` Chat account
+    RevokeEphemeralKey {
+        /// The compromised ephemeral public key
+        key: [u8; 32],
+    },
+
+    /// Store the recipient's signed acknowledgement of a message
+    ///
+    /// The recipient signs `Message::ack_payload` with their wallet key and
+    /// the transaction must carry an ed25519 program instruction verifying
+    /// that signature immediately before this one. Editing a message clears
+    /// its ack.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Recipient (the participant who didn't send the message)
+    /// 1. `[]` Chat account
+    /// 2. `[writable]` Message account
+    /// 3. `[]` Instructions sysvar
+    AckMessage {
+        /// Index of the acknowledged message
+        index: u64,
+        /// Ed25519 signature over the message's ack payload
+        signature: [u8; 64],
+    },
+
+    /// Close the sender's dead letter for a chat and reclaim its rent
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Sender of the dead letter
+    /// 1. `[writable]` Dead-letter account
+    ClearDeadLetter,
+
+    /// Recompute the chat's counters from its live message accounts
+    ///
+    /// Repairs a header that fell behind the messages actually on chain:
+    /// `live_message_count` is raised to the number of accounts passed and
+    /// `message_count` to the highest index plus one. Counters are never
+    /// lowered, since a partial set would otherwise reuse live indices or
+    /// strand messages on DeleteChat. Messages are passed in ascending
+    /// index order.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Chat participant
+    /// 1. `[writable]` Chat account
+    /// 2. `[]` Message account (PDA) for each live message
+    ResyncChat,
+
+    /// Block or unblock the other participant of a chat
+    ///
+    /// While blocked, sends from the target fail with `NotAuthorized`.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Chat participant
+    /// 1. `[writable]` Chat account
+    SetBlocked {
+        /// The other participant
+        target: Pubkey,
+        /// Whether their messages are refused from now on
+        blocked: bool,
+    },
+
+    /// Rotate the signer's key and resend one of their messages under it
+    ///
+    /// Combines RotateKey, SoftDeleteMessage and SendMessage in one
+    /// instruction, so there is no window where a message is only readable
+    /// with the retired key: the old message is tombstoned as the new key
+    /// takes effect, and the re-encrypted copy is sent at the next index
+    /// with the original's expiry, schedule, reply and attribution. The same
+    /// checks as all three apply and either everything happens or nothing.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Chat participant who sent the message
+    /// 1. `[writable]` Chat account
+    /// 2. `[writable]` Message account to tombstone
+    /// 3. `[]` System program
+    /// 4. `[writable]` New message account (PDA at the chat's message_count)
+    /// 5. `[]` Clock sysvar
+    /// 6. `[]` Config account (PDA, may be uninitialized)
+    /// 7. `[writable]` Treasury from the config, required while a send fee is set
+    RekeyAndResend {
+        /// New DH public key for the signer
+        new_public_key: [u8; 32],
+        /// Message data re-encrypted under the new key (max 512 bytes)
+        new_encrypted_data: Vec<u8>,
+        /// Ephemeral public key the new data was encrypted with
+        new_ephemeral_public_key: [u8; 32],
+    },
+
+    /// Forward a message into another chat as a new message
+    ///
+    /// The signer must be a participant of both chats. The ciphertext is
+    /// supplied again, re-encrypted for the destination chat, and the new
+    /// message records the source account in `forwarded_from`. Otherwise
+    /// the same checks as SendMessage apply to the destination.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Participant of both chats
+    /// 1. `[]` Source chat account
+    /// 2. `[]` Source message account
+    /// 3. `[writable]` Destination chat account
+    /// 4. `[writable]` Destination message account (PDA at the chat's message_count)
+    /// 5. `[]` System program
+    /// 6. `[]` Clock sysvar
+    /// 7. `[]` Config account (PDA, may be uninitialized)
+    /// 8. `[writable]` Treasury from the config, required while a send fee is set
+    ForwardMessage {
+        /// Message data encrypted for the destination chat (max 512 bytes)
+        new_encrypted_data: Vec<u8>,
+        /// Ephemeral public key the new data was encrypted with
+        new_ephemeral_public_key: [u8; 32],
+    },
+
+    /// Pin or unpin a message so clients can list it without a full scan
+    ///
+    /// Either participant may pin any message in the chat, up to
+    /// MAX_PINNED_MESSAGES at a time. Setting the current state is a no-op.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Chat participant
+    /// 1. `[writable]` Chat account
+    /// 2. `[writable]` Message account
+    SetPinned {
+        pinned: bool,
+    },
+
+    /// Add or remove a reaction on a message
+    ///
+    /// Reactions are anonymous counters, one per palette slot, so there is
+    /// no per-reactor account to pay for. Removing from a zero count fails.
+    /// A React repeating the participant's last nonce on the message is a
+    /// retry and succeeds without changing the counters.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Chat participant
+    /// 1. `[]` Chat account
+    /// 2. `[writable]` Message account
+    React {
+        /// Palette slot, below REACTION_SLOTS
+        slot: u8,
+        /// Increment the slot if true, decrement it if false
+        add: bool,
+        /// Random value identifying this reaction, so a retry of it is
+        /// ignored (zero = no deduplication)
+        nonce: u64,
+    },
+
+    /// Write the chat's `ChatInfo` as return data, for other programs to
+    /// read with `get_return_data` after a CPI
+    ///
+    /// Accounts expected:
+    /// 0. `[]` Chat account
+    GetChatInfo,
+
+    /// DeleteChat that first records `Chat::state_digest`
+    ///
+    /// The digest is set as return data and logged as a ChatArchived event,
+    /// so clients and bridges can keep proof the chat existed. Accounts and
+    /// checks are the same as DeleteChat.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Chat participant
+    /// 1. `[writable]` Chat account
+    /// 2. `[writable]` Participant 1 (rent refund)
+    /// 3. `[writable]` Participant 2 (rent refund)
+    /// 4. `[writable]` Message account (PDA) for each message still live
+    ArchiveChat,
+
+    /// Send several messages to one chat in a single instruction
+    ///
+    /// Each entry goes through the same checks as SendMessage and takes the
+    /// next index in turn, so the message accounts are the PDAs for
+    /// consecutive values starting at the chat's current `message_count`.
+    /// The whole batch counts as one send against `min_interval_secs`, while
+    /// quotas count every entry. The return data is a Borsh `BatchSent` with
+    /// the chat's `message_count` after the batch and the number written.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Message sender (payer)
+    /// 1. `[writable]` Chat account
+    /// 2. `[]` System program
     /// 3. `[]` Clock sysvar
-    DeleteMessage,
+    /// 4. `[]` Config account (PDA, may be uninitialized)
+    /// 5. `[writable]` Message account (PDA) for each entry, in the same order as `messages`
+    /// 6. `[writable]` Treasury from the config after the message accounts,
+    ///    required while a send fee is set (charged per entry)
+    SendMessageBatch {
+        /// Messages to send (max 8)
+        messages: Vec<BatchEntry>,
+    },
+
+    /// Hand the participant2 slot to another wallet, keeping the conversation
+    ///
+    /// Only participant2 can transfer: chats are PDAs of participant1 and
+    /// moving that slot would need a new address. Chats opened by
+    /// InitializeChatsBatch are also keyed by participant2, so they can't be
+    /// transferred at all. Increments the chat's `key_version`.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Current participant2
+    /// 1. `[writable]` Chat account (PDA of participant1 only)
+    TransferParticipant {
+        /// Wallet taking over the slot
+        new_pubkey: Pubkey,
+        /// New participant's public key for DH exchange (32 bytes)
+        new_public_key: [u8; 32],
+    },
+
+    /// Advance the signer's delivery cursor once their client has
+    /// downloaded messages, before they are necessarily read
+    ///
+    /// Cursors only move forward and never fall behind the read cursor.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Chat participant
+    /// 1. `[writable]` Chat account
+    MarkDelivered {
+        /// Index of the newest message delivered (must be below the message count)
+        up_to_index: u64,
+    },
+
+    /// Health check: logs the program version and sets it as UTF-8 return
+    /// data, touching no accounts
+    ///
+    /// Accounts expected: none
+    Ping,
+
+    /// Create the program-wide config with the signer as its admin
+    ///
+    /// The config is a singleton, so this succeeds only once, and only the
+    /// program's upgrade authority may create it.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Upgrade authority, becomes the admin (payer)
+    /// 1. `[writable]` Config account (PDA)
+    /// 2. `[]` System program
+    /// 3. `[]` ProgramData account of this program (upgradeable loader)
+    InitConfig,
+
+    /// Pause or resume the program for incident response
+    ///
+    /// While paused, instructions that create chats or messages fail with
+    /// `ProgramPaused`; they all require the config account. Deletes and
+    /// everything else keep working, so users can still reclaim rent.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Config admin
+    /// 1. `[writable]` Config account
+    SetPaused {
+        /// Refuse new chats and messages from now on
+        paused: bool,
+    },
+
+    /// Wipe a message's ciphertext but keep its account as a tombstone
+    ///
+    /// Unlike DeleteMessage this leaves no hole in the chat's message
+    /// sequence: the account keeps its index and timestamp and gets the
+    /// `deleted` flag. It shrinks to an empty payload, refunding most rent
+    /// to the sender.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Message sender
+    /// 1. `[writable]` Message account
+    /// 2. `[]` Chat account
+    /// 3. `[]` System program
+    SoftDeleteMessage,
+
+    /// Initialize the chat between the signer and `other` at an address
+    /// either of them can derive
+    ///
+    /// The chat PDA is `[b"chat_pair", lower, higher]` over the two keys in
+    /// sorted order, so each pair gets exactly one chat. Both participants
+    /// are stored at init; `other` sets their key with their first message.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` Chat initializer (payer)
+    /// 1. `[writable]` Chat account (PDA of the sorted pair)
+    /// 2. `[]` System program
+    /// 3. `[]` Config account (PDA, may be uninitialized)
+    /// 4. `[writable]` Optional stats account (PDA), counts the new chat
+    InitializeChatPair {
+        /// The other participant
+        other: Pubkey,
+        /// Initializer's public key for Diffie-Hellman exchange (32 bytes)
+        public_key: [u8; 32],
+    },
+
+    /// Set the protocol fee every new message pays to a treasury
+    ///
+    /// The fee is moved from the sender with a system transfer before the
+    /// message account is created, dead-lettered sends included. It is read
+    /// from the config account every send requires, so it can't be skipped
+    /// by leaving the config out. A treasury holding less than the
+    /// rent-exempt minimum can't receive fees, so fund it first.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Config admin
+    /// 1. `[writable]` Config account
+    SetFee {
+        /// Lamports charged per message (0 = no fee)
+        fee_lamports: u64,
+        /// Account fees are paid to; required when `fee_lamports` is nonzero
+        treasury: Pubkey,
+    },
+
+    /// Close some of a chat's message accounts, e.g. to empty a chat that
+    /// has too many live messages for a single DeleteChat
+    ///
+    /// Either participant may close any message; rent goes back to each
+    /// message's sender. The chat's `live_message_count` and `pinned_count`
+    /// drop by what was closed.
+    ///
+    /// Accounts expected:
+    /// 0. `[signer]` Chat participant
+    /// 1. `[writable]` Chat account
+    /// 2. `[writable]` Participant 1 (rent refund)
+    /// 3. `[writable]` Participant 2 (rent refund)
+    /// 4. `[writable]` Message account (PDA) for each message to close
+    CloseMessages,
+}
+
+impl WhisperChainInstruction {
+    /// Number of variants, so valid data starts with a byte below this
+    pub const VARIANT_COUNT: u8 = 31;
+
+    /// Decode instruction data, telling an empty payload, an unknown
+    /// discriminant and a body that doesn't deserialize apart
+    pub fn unpack(data: &[u8]) -> Result<Self, WhisperChainError> {
+        let (&discriminant, _) = data
+            .split_first()
+            .ok_or(WhisperChainError::InvalidInstruction)?;
+        if discriminant >= Self::VARIANT_COUNT {
+            return Err(WhisperChainError::UnknownInstruction);
+        }
+        Self::try_from_slice(data).map_err(|_| WhisperChainError::MalformedInstruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variant_count_matches_last_variant() {
+        let last = WhisperChainInstruction::CloseMessages;
+        let data = last.try_to_vec().unwrap();
+        assert_eq!(data[0], WhisperChainInstruction::VARIANT_COUNT - 1);
+    }
+
+    #[test]
+    fn test_unpack_empty_data() {
+        assert_eq!(
+            WhisperChainInstruction::unpack(&[]).unwrap_err(),
+            WhisperChainError::InvalidInstruction
+        );
+    }
+
+    #[test]
+    fn test_unpack_unknown_discriminant() {
+        let data = [WhisperChainInstruction::VARIANT_COUNT, 0, 0];
+        assert_eq!(
+            WhisperChainInstruction::unpack(&data).unwrap_err(),
+            WhisperChainError::UnknownInstruction
+        );
+    }
+
+    #[test]
+    fn test_unpack_truncated_body() {
+        let data = WhisperChainInstruction::MarkRead { up_to_index: 7 }.try_to_vec().unwrap();
+        assert!(matches!(
+            WhisperChainInstruction::unpack(&data),
+            Ok(WhisperChainInstruction::MarkRead { up_to_index: 7 })
+        ));
+
+        assert_eq!(
+            WhisperChainInstruction::unpack(&data[..data.len() - 1]).unwrap_err(),
+            WhisperChainError::MalformedInstruction
+        );
+    }
 }
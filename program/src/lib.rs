@@ -1,25 +1,29 @@
 use solana_program::{
-    account_info::{next_account_info, AccountInfo},
+    account_info::AccountInfo,
     entrypoint,
     entrypoint::ProgramResult,
     msg,
-    program_error::ProgramError,
+    program_error::{PrintProgramError, ProgramError},
     pubkey::Pubkey,
-    rent::Rent,
-    sysvar::Sysvar,
-    program::invoke,
-    system_instruction,
-    clock::Clock,
 };
-use borsh::{BorshDeserialize, BorshSerialize};
 
+pub mod crypto;
 pub mod error;
+pub mod event;
 pub mod instruction;
+pub mod merkle;
 pub mod state;
 pub mod processor;
 
+#[cfg(feature = "client")]
+pub mod client;
+
+use error::WhisperChainError;
 use instruction::WhisperChainInstruction;
-use processor::Processor;
+use processor::{Processor, SendMessageArgs};
+
+/// Version of the deployed program, returned by Ping
+pub const PROGRAM_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 entrypoint!(process_instruction);
 
@@ -28,23 +32,40 @@ pub fn process_instruction(
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    let instruction = WhisperChainInstruction::try_from_slice(instruction_data)
-        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    let instruction = WhisperChainInstruction::unpack(instruction_data).map_err(|error| {
+        error.print::<WhisperChainError>();
+        ProgramError::from(error)
+    })?;
 
-    match instruction {
-        WhisperChainInstruction::InitializeChat { public_key } => {
+    let result = match instruction {
+        WhisperChainInstruction::InitializeChat { public_key, config } => {
             msg!("Instruction: InitializeChat");
-            Processor::process_initialize_chat(program_id, accounts, public_key)
+            Processor::process_initialize_chat(program_id, accounts, public_key, config)
         }
-        WhisperChainInstruction::SendMessage { encrypted_data, ephemeral_public_key, timestamp, expires_at } => {
+        WhisperChainInstruction::SendMessage {
+            encrypted_data,
+            ephemeral_public_key,
+            expires_at,
+            visible_at,
+            client_timestamp,
+            client_nonce,
+            reply_to,
+            show_sender,
+        } => {
             msg!("Instruction: SendMessage");
             Processor::process_send_message(
                 program_id,
                 accounts,
-                encrypted_data,
-                ephemeral_public_key,
-                timestamp,
-                expires_at,
+                SendMessageArgs {
+                    encrypted_data,
+                    ephemeral_public_key,
+                    expires_at,
+                    visible_at,
+                    client_timestamp,
+                    client_nonce,
+                    reply_to,
+                    show_sender,
+                },
             )
         }
         WhisperChainInstruction::DeleteChat => {
@@ -55,5 +76,142 @@ pub fn process_instruction(
             msg!("Instruction: DeleteMessage");
             Processor::process_delete_message(program_id, accounts)
         }
+        WhisperChainInstruction::InitializeChatsBatch { entries } => {
+            msg!("Instruction: InitializeChatsBatch");
+            Processor::process_initialize_chats_batch(program_id, accounts, entries)
+        }
+        WhisperChainInstruction::EditMessage { new_encrypted_data, new_ephemeral_public_key } => {
+            msg!("Instruction: EditMessage");
+            Processor::process_edit_message(
+                program_id,
+                accounts,
+                new_encrypted_data,
+                new_ephemeral_public_key,
+            )
+        }
+        WhisperChainInstruction::MarkRead { up_to_index } => {
+            msg!("Instruction: MarkRead");
+            Processor::process_mark_read(program_id, accounts, up_to_index)
+        }
+        WhisperChainInstruction::ArchiveToCompressed => {
+            msg!("Instruction: ArchiveToCompressed");
+            Processor::process_archive_to_compressed(program_id, accounts)
+        }
+        WhisperChainInstruction::RestoreFromCompressed { message, proof } => {
+            msg!("Instruction: RestoreFromCompressed");
+            Processor::process_restore_from_compressed(program_id, accounts, *message, proof)
+        }
+        WhisperChainInstruction::RotateKey { new_public_key } => {
+            msg!("Instruction: RotateKey");
+            Processor::process_rotate_key(program_id, accounts, new_public_key)
+        }
+        WhisperChainInstruction::RevokeEphemeralKey { key } => {
+            msg!("Instruction: RevokeEphemeralKey");
+            Processor::process_revoke_ephemeral_key(program_id, accounts, key)
+        }
+        WhisperChainInstruction::AckMessage { index, signature } => {
+            msg!("Instruction: AckMessage");
+            Processor::process_ack_message(program_id, accounts, index, signature)
+        }
+        WhisperChainInstruction::ClearDeadLetter => {
+            msg!("Instruction: ClearDeadLetter");
+            Processor::process_clear_dead_letter(program_id, accounts)
+        }
+        WhisperChainInstruction::ResyncChat => {
+            msg!("Instruction: ResyncChat");
+            Processor::process_resync_chat(program_id, accounts)
+        }
+        WhisperChainInstruction::SetBlocked { target, blocked } => {
+            msg!("Instruction: SetBlocked");
+            Processor::process_set_blocked(program_id, accounts, target, blocked)
+        }
+        WhisperChainInstruction::RekeyAndResend {
+            new_public_key,
+            new_encrypted_data,
+            new_ephemeral_public_key,
+        } => {
+            msg!("Instruction: RekeyAndResend");
+            Processor::process_rekey_and_resend(
+                program_id,
+                accounts,
+                new_public_key,
+                new_encrypted_data,
+                new_ephemeral_public_key,
+            )
+        }
+        WhisperChainInstruction::ForwardMessage {
+            new_encrypted_data,
+            new_ephemeral_public_key,
+        } => {
+            msg!("Instruction: ForwardMessage");
+            Processor::process_forward_message(
+                program_id,
+                accounts,
+                new_encrypted_data,
+                new_ephemeral_public_key,
+            )
+        }
+        WhisperChainInstruction::SetPinned { pinned } => {
+            msg!("Instruction: SetPinned");
+            Processor::process_set_pinned(program_id, accounts, pinned)
+        }
+        WhisperChainInstruction::React { slot, add, nonce } => {
+            msg!("Instruction: React");
+            Processor::process_react(program_id, accounts, slot, add, nonce)
+        }
+        WhisperChainInstruction::GetChatInfo => {
+            msg!("Instruction: GetChatInfo");
+            Processor::process_get_chat_info(program_id, accounts)
+        }
+        WhisperChainInstruction::ArchiveChat => {
+            msg!("Instruction: ArchiveChat");
+            Processor::process_archive_chat(program_id, accounts)
+        }
+        WhisperChainInstruction::SendMessageBatch { messages } => {
+            msg!("Instruction: SendMessageBatch");
+            Processor::process_send_message_batch(program_id, accounts, messages)
+        }
+        WhisperChainInstruction::TransferParticipant { new_pubkey, new_public_key } => {
+            msg!("Instruction: TransferParticipant");
+            Processor::process_transfer_participant(program_id, accounts, new_pubkey, new_public_key)
+        }
+        WhisperChainInstruction::MarkDelivered { up_to_index } => {
+            msg!("Instruction: MarkDelivered");
+            Processor::process_mark_delivered(program_id, accounts, up_to_index)
+        }
+        WhisperChainInstruction::Ping => {
+            msg!("Instruction: Ping");
+            Processor::process_ping()
+        }
+        WhisperChainInstruction::InitConfig => {
+            msg!("Instruction: InitConfig");
+            Processor::process_init_config(program_id, accounts)
+        }
+        WhisperChainInstruction::SetPaused { paused } => {
+            msg!("Instruction: SetPaused");
+            Processor::process_set_paused(program_id, accounts, paused)
+        }
+        WhisperChainInstruction::SoftDeleteMessage => {
+            msg!("Instruction: SoftDeleteMessage");
+            Processor::process_soft_delete_message(program_id, accounts)
+        }
+        WhisperChainInstruction::InitializeChatPair { other, public_key } => {
+            msg!("Instruction: InitializeChatPair");
+            Processor::process_initialize_chat_pair(program_id, accounts, other, public_key)
+        }
+        WhisperChainInstruction::SetFee { fee_lamports, treasury } => {
+            msg!("Instruction: SetFee");
+            Processor::process_set_fee(program_id, accounts, fee_lamports, treasury)
+        }
+        WhisperChainInstruction::CloseMessages => {
+            msg!("Instruction: CloseMessages");
+            Processor::process_close_messages(program_id, accounts)
+        }
+    };
+
+    // Log a readable reason instead of a bare custom error code
+    if let Err(error) = &result {
+        error.print::<WhisperChainError>();
     }
+    result
 }
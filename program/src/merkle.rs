@@ -0,0 +1,91 @@
+use solana_program::keccak::hashv;
+
+/// Domain separators so a leaf can never be passed off as an inner node
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+/// Hash of a serialized message, as stored in an archive tree
+pub fn leaf_hash(data: &[u8]) -> [u8; 32] {
+    hashv(&[LEAF_PREFIX, data]).to_bytes()
+}
+
+/// Pairs are hashed in sorted order, so proofs don't need direction bits
+fn node_hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    hashv(&[NODE_PREFIX, left, right]).to_bytes()
+}
+
+/// An odd node at the end of a level is promoted unchanged
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [a, b] => node_hash(a, b),
+            [a] => *a,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Merkle root over `leaves` (all zeros for an empty tree)
+pub fn root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return [0u8; 32];
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Sibling hashes proving the leaf at `index` is part of the tree
+pub fn proof(leaves: &[[u8; 32]], mut index: usize) -> Vec<[u8; 32]> {
+    let mut proof = Vec::new();
+    let mut level = leaves.to_vec();
+
+    while level.len() > 1 {
+        if let Some(sibling) = level.get(index ^ 1) {
+            proof.push(*sibling);
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    proof
+}
+
+pub fn verify(root: &[u8; 32], leaf: [u8; 32], proof: &[[u8; 32]]) -> bool {
+    proof.iter().fold(leaf, |node, sibling| node_hash(&node, sibling)) == *root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proofs_verify_for_every_leaf() {
+        let leaves: Vec<[u8; 32]> = (0u8..5).map(|i| leaf_hash(&[i])).collect();
+        let root = root(&leaves);
+
+        for (index, leaf) in leaves.iter().enumerate() {
+            assert!(verify(&root, *leaf, &proof(&leaves, index)));
+        }
+    }
+
+    #[test]
+    fn test_wrong_leaf_is_rejected() {
+        let leaves: Vec<[u8; 32]> = (0u8..3).map(|i| leaf_hash(&[i])).collect();
+        let root = root(&leaves);
+
+        assert!(!verify(&root, leaf_hash(&[9]), &proof(&leaves, 1)));
+        assert!(!verify(&root, leaves[0], &proof(&leaves, 1)));
+    }
+
+    #[test]
+    fn test_single_leaf_root_is_the_leaf() {
+        let leaf = leaf_hash(b"only");
+        assert_eq!(root(&[leaf]), leaf);
+        assert!(proof(&[leaf], 0).is_empty());
+    }
+}
@@ -1,38 +1,79 @@
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint::ProgramResult,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
+    program_utils::limited_deserialize,
     pubkey::Pubkey,
     rent::Rent,
     system_instruction,
-    sysvar::Sysvar,
+    sysvar::{instructions::get_instruction_relative, Sysvar},
     clock::Clock,
 };
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshSerialize;
 
+use crate::crypto::{is_ed25519_verification, is_valid_public_key};
 use crate::error::WhisperChainError;
-use crate::state::{Chat, Message, MAX_MESSAGE_SIZE};
+use crate::event::{
+    ChatArchivedEvent, ChatDeletedEvent, ChatInitializedEvent, Event, MessageArchivedEvent,
+    MessageDeletedEvent, MessageSentEvent, ParticipantJoinedEvent,
+};
+use crate::merkle;
+use crate::PROGRAM_VERSION;
+use crate::state::{
+    BatchEntry, BatchSent, Chat, ChatConfig, ChatInfo, ChatInit, Config, DeadLetter, Message,
+    MAX_CHATS_PER_BATCH, MAX_EXPIRY_HORIZON_SECS, MAX_MESSAGES_PER_BATCH, MAX_MESSAGE_SIZE,
+    MAX_PINNED_MESSAGES, MAX_RECENT_NONCES, MAX_REVOKED_KEYS, REACTION_SLOTS, SentMessage,
+    Stats,
+};
 
 pub struct Processor;
 
+/// Arguments of a SendMessage instruction
+pub struct SendMessageArgs {
+    pub encrypted_data: Vec<u8>,
+    pub ephemeral_public_key: [u8; 32],
+    pub expires_at: i64,
+    pub visible_at: i64,
+    pub client_timestamp: i64,
+    pub client_nonce: [u8; 16],
+    pub reply_to: Option<u64>,
+    pub show_sender: bool,
+}
+
 impl Processor {
     pub fn process_initialize_chat(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
         public_key: [u8; 32],
+        config: ChatConfig,
     ) -> ProgramResult {
         let accounts_iter = &mut accounts.iter();
 
         let initializer = next_account_info(accounts_iter)?;
         let chat_account = next_account_info(accounts_iter)?;
         let system_program = next_account_info(accounts_iter)?;
+        let config_account = next_account_info(accounts_iter)?;
+        let stats_account = Self::find_singleton(program_id, b"stats", accounts_iter.as_slice());
 
         if !initializer.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
 
+        Self::check_not_paused(program_id, config_account)?;
+
+        if !is_valid_public_key(&public_key) {
+            return Err(WhisperChainError::InvalidPublicKey.into());
+        }
+
+        // A default expiry past the horizon would be refused on every send
+        if i64::from(config.default_ttl_secs) > MAX_EXPIRY_HORIZON_SECS {
+            msg!("Error: Default TTL is more than {}s", MAX_EXPIRY_HORIZON_SECS);
+            return Err(WhisperChainError::InvalidExpiration.into());
+        }
+
         // Verify the chat account is a PDA
         let (chat_pda, chat_bump) = Pubkey::find_program_address(
             &[
@@ -47,34 +88,26 @@ impl Processor {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        // Create the chat account
-        let rent = Rent::get()?;
-        let space = Chat::LEN;
-        let lamports = rent.minimum_balance(space);
+        // create_account would refuse too, but with an opaque system error
+        if !chat_account.data_is_empty() || chat_account.lamports() != 0 {
+            msg!("Error: Chat already exists for this initializer");
+            return Err(WhisperChainError::AlreadyInitialized.into());
+        }
 
-        invoke_signed(
-            &system_instruction::create_account(
-                initializer.key,
-                chat_account.key,
-                lamports,
-                space as u64,
-                program_id,
-            ),
-            &[
-                initializer.clone(),
-                chat_account.clone(),
-                system_program.clone(),
-            ],
-            &[&[
-                b"chat",
-                initializer.key.as_ref(),
-                &[chat_bump],
-            ]],
+        // Create the chat account
+        Self::create_pda_account(
+            program_id,
+            initializer,
+            chat_account,
+            system_program,
+            Chat::LEN,
+            &[b"chat", initializer.key.as_ref(), &[chat_bump]],
         )?;
 
         // Initialize the chat data
         let clock = Clock::get()?;
-        let mut chat = Chat {
+        let chat = Chat {
+            version: Chat::VERSION,
             is_initialized: true,
             participant1: *initializer.key,
             participant2: Pubkey::default(), // Will be set when someone sends first message
@@ -82,11 +115,43 @@ impl Processor {
             participant2_public_key: [0u8; 32],
             created_at: clock.unix_timestamp,
             message_count: 0,
+            live_message_count: 0,
+            pinned_count: 0,
             last_message_at: 0,
+            participant1_read_cursor: 0,
+            participant2_read_cursor: 0,
+            participant1_delivered_cursor: 0,
+            participant2_delivered_cursor: 0,
+            participant1_sent_count: 0,
+            participant2_sent_count: 0,
+            participant1_last_sent_at: 0,
+            participant2_last_sent_at: 0,
+            participant1_blocked_other: false,
+            participant2_blocked_other: false,
+            key_version: 0,
+            last_rotation_at: 0,
+            revoked_keys: [[0u8; 32]; MAX_REVOKED_KEYS],
+            revoked_keys_next: 0,
+            recent_nonces: [[0u8; 16]; MAX_RECENT_NONCES],
+            recent_nonces_next: 0,
+            config,
+            merkle_root: [0u8; 32],
         };
 
         chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
 
+        if let Some(stats_account) = stats_account {
+            Self::record_stats(program_id, initializer, stats_account, system_program, 1, 0)?;
+        }
+
+        ChatInitializedEvent {
+            chat: *chat_account.key,
+            participant1: chat.participant1,
+            participant2: chat.participant2,
+            created_at: chat.created_at,
+        }
+        .emit()?;
+
         msg!("Chat initialized successfully");
         Ok(())
     }
@@ -94,46 +159,209 @@ impl Processor {
     pub fn process_send_message(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
-        encrypted_data: Vec<u8>,
-        ephemeral_public_key: [u8; 32],
-        timestamp: i64,
-        expires_at: i64,
+        args: SendMessageArgs,
+    ) -> ProgramResult {
+        Self::create_message(program_id, accounts, args, None, true)
+    }
+
+    /// SendMessage, recording `forwarded_from` on the new message. Sends
+    /// with `rate_limited` unset skip `min_interval_secs`, for the entries
+    /// of a batch after the one it was checked on.
+    fn create_message(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        args: SendMessageArgs,
+        forwarded_from: Option<Pubkey>,
+        rate_limited: bool,
     ) -> ProgramResult {
+        let SendMessageArgs {
+            encrypted_data,
+            ephemeral_public_key,
+            expires_at,
+            visible_at,
+            client_timestamp,
+            client_nonce,
+            reply_to,
+            show_sender,
+        } = args;
+
         let accounts_iter = &mut accounts.iter();
 
         let sender = next_account_info(accounts_iter)?;
         let chat_account = next_account_info(accounts_iter)?;
         let message_account = next_account_info(accounts_iter)?;
         let system_program = next_account_info(accounts_iter)?;
+        let clock_account = next_account_info(accounts_iter)?;
+        let config_account = next_account_info(accounts_iter)?;
+
+        // The optional trailing accounts are told apart by address
+        let optional_accounts = accounts_iter.as_slice();
+        let stats_account = Self::find_singleton(program_id, b"stats", optional_accounts);
 
         if !sender.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
 
+        let fee = Self::check_not_paused(program_id, config_account)?
+            .filter(|config| config.fee_lamports != 0);
+        let treasury_account = fee.as_ref().and_then(|config| {
+            optional_accounts.iter().find(|account| *account.key == config.treasury)
+        });
+        let dead_letter_account = optional_accounts.iter().find(|account| {
+            [stats_account, treasury_account]
+                .iter()
+                .all(|known| known.is_none_or(|known| known.key != account.key))
+        });
+
+        if !is_valid_public_key(&ephemeral_public_key) {
+            return Err(WhisperChainError::InvalidPublicKey.into());
+        }
+
         // Validate encrypted data size
         if encrypted_data.len() > MAX_MESSAGE_SIZE {
             return Err(WhisperChainError::DataTooLarge.into());
         }
 
+        // Timestamps come from the cluster clock, never from the client
+        let clock = Clock::from_account_info(clock_account)?;
+        let timestamp = clock.unix_timestamp;
+
+        if expires_at < 0 {
+            msg!("Error: Expiration is negative");
+            return Err(WhisperChainError::InvalidExpiration.into());
+        }
+
+        if expires_at != 0 && expires_at <= timestamp {
+            msg!("Error: Expiration is not in the future");
+            return Err(WhisperChainError::InvalidExpiration.into());
+        }
+
+        // Far-off expirations are almost always client garbage like i64::MAX
+        if expires_at > timestamp.saturating_add(MAX_EXPIRY_HORIZON_SECS) {
+            msg!("Error: Expiration is more than {}s away", MAX_EXPIRY_HORIZON_SECS);
+            return Err(WhisperChainError::InvalidExpiration.into());
+        }
+
+        // A scheduled message can't become visible before it was sent
+        if visible_at != 0 && visible_at < timestamp {
+            msg!("Error: Visibility time is before the send time");
+            return Err(ProgramError::InvalidArgument);
+        }
+
         // Deserialize and validate chat account
         if chat_account.owner != program_id {
             return Err(WhisperChainError::InvalidAccountOwner.into());
         }
 
-        let mut chat = Chat::try_from_slice(&chat_account.data.borrow())?;
+        // Senders who opted in park the message instead of failing on a
+        // chat that was allocated but never initialized
+        if let Some(dead_letter_account) = dead_letter_account {
+            if chat_account.data.borrow().iter().all(|&byte| byte == 0) {
+                let dead_letter = DeadLetter {
+                    version: DeadLetter::VERSION,
+                    is_initialized: true,
+                    sender: *sender.key,
+                    chat: *chat_account.key,
+                    created_at: timestamp,
+                    ephemeral_public_key,
+                    encrypted_data,
+                };
+                // Parking a message costs the same fee as delivering it
+                if let Some(config) = fee.as_ref() {
+                    let space = DeadLetter::space(dead_letter.encrypted_data.len());
+                    Self::collect_fee(sender, treasury_account, system_program, config, space)?;
+                }
+                return Self::store_dead_letter(
+                    program_id,
+                    sender,
+                    dead_letter_account,
+                    system_program,
+                    &dead_letter,
+                );
+            }
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
+        // Owner alone doesn't rule out a copy of some other chat's data
+        Self::check_chat_address(program_id, chat_account.key, &chat)?;
+
+        if !chat.config.allows_size(encrypted_data.len()) {
+            msg!("Error: Message size {} not allowed by this chat", encrypted_data.len());
+            return Err(WhisperChainError::InvalidPadding.into());
+        }
+
+        // Chats can make "never expires" mean "expires after the default TTL"
+        let expires_at = chat.config.effective_expiry(timestamp, expires_at);
 
-        if !chat.is_initialized {
-            return Err(WhisperChainError::NotInitialized.into());
+        // Replies must point at a message that was already sent
+        if reply_to.is_some_and(|parent| parent >= chat.message_count) {
+            msg!("Error: Reply parent does not exist");
+            return Err(ProgramError::InvalidArgument);
         }
 
         // If this is the first message from participant2, set them up
-        if chat.participant2 == Pubkey::default() && chat.participant1 != *sender.key {
-            chat.participant2 = *sender.key;
-            chat.participant2_public_key = ephemeral_public_key;
+        let joined = chat.try_join(sender.key, ephemeral_public_key);
+        if chat.participant2 == Pubkey::default() {
+            // Without a counterpart the owner would be the only sender
+            if !chat.config.allow_self_chat {
+                msg!("Error: Self chat not allowed, wait for participant2 to join");
+                return Err(WhisperChainError::NotAuthorized.into());
+            }
         } else if !chat.is_participant(sender.key) {
             return Err(WhisperChainError::NotAuthorized.into());
+        } else if chat.participant2 == *sender.key && chat.participant2_public_key == [0u8; 32] {
+            // Participant2 was named at init (batch onboarding) but has not sent yet
+            chat.participant2_public_key = ephemeral_public_key;
+        }
+
+        if chat.is_blocked(sender.key) {
+            msg!("Error: Sender has been blocked by the other participant");
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        // A retried send whose first attempt landed must not post twice
+        if chat.has_recent_nonce(&client_nonce) {
+            msg!("Error: Nonce was used by a recent message");
+            return Err(WhisperChainError::DuplicateNonce.into());
+        }
+        chat.record_nonce(client_nonce);
+
+        let min_interval = i64::from(chat.config.min_interval_secs);
+        let (sent_count, last_sent_at, quota) = if chat.participant1 == *sender.key {
+            (
+                &mut chat.participant1_sent_count,
+                &mut chat.participant1_last_sent_at,
+                chat.config.participant1_quota,
+            )
+        } else {
+            (
+                &mut chat.participant2_sent_count,
+                &mut chat.participant2_last_sent_at,
+                chat.config.participant2_quota,
+            )
+        };
+
+        // Quotas cap how much of the chat's rent footprint one side can take
+        if quota != 0 && *sent_count >= u64::from(quota) {
+            msg!("Error: Sender reached their quota of {} messages", quota);
+            return Err(WhisperChainError::QuotaExceeded.into());
+        }
+
+        // A sender's first message is never rate limited
+        if rate_limited
+            && *last_sent_at != 0
+            && timestamp.saturating_sub(*last_sent_at) < min_interval
+        {
+            msg!("Error: Sender must wait {}s between messages", min_interval);
+            return Err(WhisperChainError::RateLimited.into());
         }
 
+        *sent_count = sent_count
+            .checked_add(1)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        *last_sent_at = timestamp;
+
         let message_index = chat.message_count;
 
         // Create PDA for message
@@ -151,40 +379,65 @@ impl Processor {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        // Create the message account
-        let rent = Rent::get()?;
-        let space = Message::space(encrypted_data.len());
-        let lamports = rent.minimum_balance(space);
+        // Another send may have claimed this index first; fail clearly so
+        // the client retries with a fresh message_count
+        if !message_account.data_is_empty() || message_account.lamports() != 0 {
+            msg!("Error: Message {} already exists, refresh the chat and retry", message_index);
+            return Err(WhisperChainError::AlreadyInitialized.into());
+        }
 
-        invoke_signed(
-            &system_instruction::create_account(
-                sender.key,
-                message_account.key,
-                lamports,
-                space as u64,
-                program_id,
-            ),
+        let space =
+            Message::space(encrypted_data.len(), reply_to.is_some(), forwarded_from.is_some());
+
+        if let Some(config) = fee {
+            Self::collect_fee(sender, treasury_account, system_program, &config, space)?;
+        }
+
+        // Create the message account
+        Self::create_pda_account(
+            program_id,
+            sender,
+            message_account,
+            system_program,
+            space,
             &[
-                sender.clone(),
-                message_account.clone(),
-                system_program.clone(),
-            ],
-            &[&[
                 b"message",
                 chat_account.key.as_ref(),
                 &message_index.to_le_bytes(),
                 &[message_bump],
-            ]],
+            ],
         )?;
 
+        // Unattributed messages store a commitment in place of the sender key
+        let stored_sender = if show_sender {
+            *sender.key
+        } else {
+            Message::sender_commitment(sender.key, chat_account.key, message_index)
+        };
+
         // Initialize message data
         let message = Message {
+            version: Message::VERSION,
             is_initialized: true,
             chat: *chat_account.key,
-            sender: *sender.key,
+            sender: stored_sender,
+            show_sender,
             index: message_index,
             timestamp,
+            client_timestamp,
+            client_nonce,
+            clock_drift_flag: chat.config.is_drifted(timestamp, client_timestamp),
             expires_at,
+            visible_at,
+            edited_at: 0,
+            deleted: false,
+            ack_signature: [0u8; 64],
+            pinned: false,
+            reactions: [0; REACTION_SLOTS],
+            participant1_last_reaction_nonce: 0,
+            participant2_last_reaction_nonce: 0,
+            reply_to,
+            forwarded_from,
             ephemeral_public_key,
             encrypted_data,
         };
@@ -192,14 +445,225 @@ impl Processor {
         message.serialize(&mut &mut message_account.data.borrow_mut()[..])?;
 
         // Update chat metadata
-        chat.message_count += 1;
+        chat.message_count = chat
+            .message_count
+            .checked_add(1)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        chat.live_message_count = chat
+            .live_message_count
+            .checked_add(1)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
         chat.last_message_at = timestamp;
         chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
 
+        if let Some(stats_account) = stats_account {
+            Self::record_stats(program_id, sender, stats_account, system_program, 0, 1)?;
+        }
+
+        if joined {
+            ParticipantJoinedEvent {
+                chat: *chat_account.key,
+                participant: chat.participant2,
+                public_key: chat.participant2_public_key,
+            }
+            .emit()?;
+        }
+
+        MessageSentEvent {
+            chat: *chat_account.key,
+            sender: stored_sender,
+            index: message_index,
+            timestamp,
+            expires_at,
+        }
+        .emit()?;
+
+        set_return_data(
+            &SentMessage {
+                message: message_pda,
+                index: message_index,
+                bump: message_bump,
+            }
+            .try_to_vec()?,
+        );
+
         msg!("Message sent successfully. Index: {}", message_index);
         Ok(())
     }
 
+    /// Write `dead_letter` to the sender's dead-letter PDA for its chat
+    fn store_dead_letter<'a>(
+        program_id: &Pubkey,
+        sender: &AccountInfo<'a>,
+        dead_letter_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        dead_letter: &DeadLetter,
+    ) -> ProgramResult {
+        let (dead_letter_pda, dead_letter_bump) = Pubkey::find_program_address(
+            &[b"dead_letter", sender.key.as_ref(), dead_letter.chat.as_ref()],
+            program_id,
+        );
+
+        if dead_letter_pda != *dead_letter_account.key {
+            msg!("Error: Dead-letter account is not the correct PDA");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Only one undelivered message is kept per chat
+        if !dead_letter_account.data_is_empty() || dead_letter_account.lamports() != 0 {
+            msg!("Error: A dead letter is already pending for this chat");
+            return Err(WhisperChainError::AlreadyInitialized.into());
+        }
+
+        Self::create_pda_account(
+            program_id,
+            sender,
+            dead_letter_account,
+            system_program,
+            DeadLetter::space(dead_letter.encrypted_data.len()),
+            &[
+                b"dead_letter",
+                sender.key.as_ref(),
+                dead_letter.chat.as_ref(),
+                &[dead_letter_bump],
+            ],
+        )?;
+
+        dead_letter.serialize(&mut &mut dead_letter_account.data.borrow_mut()[..])?;
+
+        msg!("Chat not initialized, message stored as a dead letter");
+        Ok(())
+    }
+
+    pub fn process_initialize_chats_batch(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        entries: Vec<ChatInit>,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let initializer = next_account_info(accounts_iter)?;
+        let system_program = next_account_info(accounts_iter)?;
+        let config_account = next_account_info(accounts_iter)?;
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if entries.is_empty() || entries.len() > MAX_CHATS_PER_BATCH {
+            msg!("Error: Batch must contain 1 to {} chats", MAX_CHATS_PER_BATCH);
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let remaining = accounts_iter.as_slice();
+        if remaining.len() < entries.len() {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let (chat_accounts, optional_accounts) = remaining.split_at(entries.len());
+        let stats_account = Self::find_singleton(program_id, b"stats", optional_accounts);
+
+        Self::check_not_paused(program_id, config_account)?;
+
+        for (i, entry) in entries.iter().enumerate() {
+            if entries[..i].iter().any(|e| e.counterpart == entry.counterpart) {
+                msg!("Error: Duplicate counterpart in batch");
+                return Err(ProgramError::InvalidArgument);
+            }
+
+            if !is_valid_public_key(&entry.public_key) {
+                return Err(WhisperChainError::InvalidPublicKey.into());
+            }
+
+            // Batch chats use the default config, which never allows self chat
+            if entry.counterpart == *initializer.key {
+                msg!("Error: Initializer can't be their own counterpart");
+                return Err(WhisperChainError::NotAuthorized.into());
+            }
+        }
+
+        let clock = Clock::get()?;
+        let new_chats = entries.len() as u64;
+
+        for (entry, chat_account) in entries.into_iter().zip(chat_accounts) {
+            let (chat_pda, chat_bump) = Pubkey::find_program_address(
+                &[
+                    b"chat",
+                    initializer.key.as_ref(),
+                    entry.counterpart.as_ref(),
+                ],
+                program_id,
+            );
+
+            if chat_pda != *chat_account.key {
+                msg!("Error: Chat account is not the correct PDA");
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            Self::create_pda_account(
+                program_id,
+                initializer,
+                chat_account,
+                system_program,
+                Chat::LEN,
+                &[
+                    b"chat",
+                    initializer.key.as_ref(),
+                    entry.counterpart.as_ref(),
+                    &[chat_bump],
+                ],
+            )?;
+
+            let chat = Chat {
+                version: Chat::VERSION,
+                is_initialized: true,
+                participant1: *initializer.key,
+                participant2: entry.counterpart,
+                participant1_public_key: entry.public_key,
+                participant2_public_key: [0u8; 32], // Set by their first message
+                created_at: clock.unix_timestamp,
+                message_count: 0,
+                live_message_count: 0,
+                pinned_count: 0,
+                last_message_at: 0,
+                participant1_read_cursor: 0,
+                participant2_read_cursor: 0,
+                participant1_delivered_cursor: 0,
+                participant2_delivered_cursor: 0,
+                participant1_sent_count: 0,
+                participant2_sent_count: 0,
+                participant1_last_sent_at: 0,
+                participant2_last_sent_at: 0,
+                participant1_blocked_other: false,
+                participant2_blocked_other: false,
+                key_version: 0,
+                last_rotation_at: 0,
+                revoked_keys: [[0u8; 32]; MAX_REVOKED_KEYS],
+                revoked_keys_next: 0,
+                recent_nonces: [[0u8; 16]; MAX_RECENT_NONCES],
+                recent_nonces_next: 0,
+                config: ChatConfig::default(),
+                merkle_root: [0u8; 32],
+            };
+
+            chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+            ChatInitializedEvent {
+                chat: *chat_account.key,
+                participant1: chat.participant1,
+                participant2: chat.participant2,
+                created_at: chat.created_at,
+            }
+            .emit()?;
+        }
+
+        if let Some(stats_account) = stats_account {
+            Self::record_stats(program_id, initializer, stats_account, system_program, new_chats, 0)?;
+        }
+
+        msg!("Chats initialized successfully");
+        Ok(())
+    }
+
     pub fn process_delete_chat(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
@@ -208,6 +672,9 @@ impl Processor {
 
         let participant = next_account_info(accounts_iter)?;
         let chat_account = next_account_info(accounts_iter)?;
+        let participant1_account = next_account_info(accounts_iter)?;
+        let participant2_account = next_account_info(accounts_iter)?;
+        let message_accounts = accounts_iter.as_slice();
 
         if !participant.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
@@ -217,31 +684,109 @@ impl Processor {
             return Err(WhisperChainError::InvalidAccountOwner.into());
         }
 
-        let chat = Chat::try_from_slice(&chat_account.data.borrow())?;
-
-        if !chat.is_initialized {
-            return Err(WhisperChainError::NotInitialized.into());
-        }
+        let chat = Chat::unpack(&chat_account.data.borrow())?;
 
         if !chat.is_participant(participant.key) {
             return Err(WhisperChainError::NotAuthorized.into());
         }
 
+        // Message PDAs can't be found again once the chat is gone, so every
+        // live one must be closed here or by an earlier CloseMessages
+        if message_accounts.len() as u64 != chat.live_message_count {
+            msg!(
+                "Error: {} messages are live but {} were passed, close the rest with CloseMessages first",
+                chat.live_message_count,
+                message_accounts.len()
+            );
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+
+        Self::close_chat_messages(
+            program_id,
+            chat_account,
+            &chat,
+            participant1_account,
+            participant2_account,
+            message_accounts,
+        )?;
+
         // Close the account and transfer lamports back to participant
-        let dest_starting_lamports = participant.lamports();
-        **participant.lamports.borrow_mut() = dest_starting_lamports
-            .checked_add(chat_account.lamports())
-            .ok_or(ProgramError::ArithmeticOverflow)?;
-        **chat_account.lamports.borrow_mut() = 0;
+        Self::close_account(chat_account, participant)?;
+
+        // The DH keys are the privacy-critical part, so make sure none survive
+        if chat_account.data.borrow()[Chat::PUBLIC_KEYS_RANGE].iter().any(|&byte| byte != 0) {
+            msg!("Error: Chat public keys not zeroed on delete");
+            return Err(ProgramError::InvalidAccountData);
+        }
 
-        // Zero out the data
-        let mut chat_data = chat_account.data.borrow_mut();
-        chat_data.fill(0);
+        ChatDeletedEvent {
+            chat: *chat_account.key,
+            deleted_by: *participant.key,
+        }
+        .emit()?;
 
         msg!("Chat deleted successfully");
         Ok(())
     }
 
+    pub fn process_close_messages(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let participant1_account = next_account_info(accounts_iter)?;
+        let participant2_account = next_account_info(accounts_iter)?;
+        let message_accounts = accounts_iter.as_slice();
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
+        if !chat.is_participant(participant.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        if message_accounts.is_empty() {
+            msg!("Error: No messages to close");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let pinned_closed = Self::close_chat_messages(
+            program_id,
+            chat_account,
+            &chat,
+            participant1_account,
+            participant2_account,
+            message_accounts,
+        )?;
+
+        chat.live_message_count = chat
+            .live_message_count
+            .checked_sub(message_accounts.len() as u64)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        chat.pinned_count = chat
+            .pinned_count
+            .checked_sub(pinned_closed)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        msg!(
+            "Closed {} messages, {} still live",
+            message_accounts.len(),
+            chat.live_message_count
+        );
+        Ok(())
+    }
+
     pub fn process_delete_message(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
@@ -252,6 +797,7 @@ impl Processor {
         let message_account = next_account_info(accounts_iter)?;
         let chat_account = next_account_info(accounts_iter)?;
         let clock_account = next_account_info(accounts_iter)?;
+        let refund_account = next_account_info(accounts_iter)?;
 
         if !sender.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
@@ -261,40 +807,1867 @@ impl Processor {
             return Err(WhisperChainError::InvalidAccountOwner.into());
         }
 
-        let message = Message::try_from_slice(&message_account.data.borrow())?;
-
-        if !message.is_initialized {
-            return Err(WhisperChainError::NotInitialized.into());
-        }
+        let message = Message::unpack(&message_account.data.borrow())?;
 
         // Verify the message belongs to this chat
         if message.chat != *chat_account.key {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        // Only the sender can delete their message
-        if message.sender != *sender.key {
-            return Err(WhisperChainError::NotAuthorized.into());
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
         }
 
-        // Check if message has expired (auto-delete)
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
         let clock = Clock::from_account_info(clock_account)?;
         if message.is_expired(clock.unix_timestamp) {
+            // Expired messages can be reaped by either participant
+            if !chat.is_participant(sender.key) {
+                return Err(WhisperChainError::NotAuthorized.into());
+            }
+
             msg!("Message expired, auto-deleting");
+        } else if !message.is_sent_by(sender.key) {
+            // Only the sender can delete a live message
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        // The sender paid the rent, so the refund always goes back to them,
+        // even when the delete was triggered by someone else
+        if !message.is_sent_by(refund_account.key) {
+            msg!("Error: Refund account is not the message sender");
+            return Err(ProgramError::InvalidAccountData);
         }
 
         // Close the account and transfer lamports back to sender
-        let dest_starting_lamports = sender.lamports();
-        **sender.lamports.borrow_mut() = dest_starting_lamports
-            .checked_add(message_account.lamports())
+        Self::close_account(message_account, refund_account)?;
+
+        // A count already at zero means a delete was double-counted; ResyncChat repairs it
+        chat.live_message_count = chat
+            .live_message_count
+            .checked_sub(1)
             .ok_or(ProgramError::ArithmeticOverflow)?;
-        **message_account.lamports.borrow_mut() = 0;
+        if message.pinned {
+            chat.pinned_count = chat
+                .pinned_count
+                .checked_sub(1)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+        }
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
 
-        // Zero out the data
-        let mut message_data = message_account.data.borrow_mut();
-        message_data.fill(0);
+        MessageDeletedEvent {
+            chat: message.chat,
+            message: *message_account.key,
+            index: message.index,
+            deleted_by: *sender.key,
+        }
+        .emit()?;
 
         msg!("Message deleted successfully");
         Ok(())
     }
+
+    pub fn process_edit_message(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        new_encrypted_data: Vec<u8>,
+        new_ephemeral_public_key: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let sender = next_account_info(accounts_iter)?;
+        let message_account = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let system_program = next_account_info(accounts_iter)?;
+
+        if !sender.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if new_encrypted_data.len() > MAX_MESSAGE_SIZE {
+            return Err(WhisperChainError::DataTooLarge.into());
+        }
+
+        if !is_valid_public_key(&new_ephemeral_public_key) {
+            return Err(WhisperChainError::InvalidPublicKey.into());
+        }
+
+        if message_account.owner != program_id || chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut message = Message::unpack(&message_account.data.borrow())?;
+
+        // Verify the message belongs to this chat
+        if message.chat != *chat_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Edits must respect the chat's size buckets like any send
+        let chat = Chat::unpack(&chat_account.data.borrow())?;
+        if !chat.config.allows_size(new_encrypted_data.len()) {
+            return Err(WhisperChainError::InvalidPadding.into());
+        }
+
+        // Only the sender can edit their message
+        if !message.is_sent_by(sender.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        // Editing would bring a tombstone back to life
+        if message.deleted {
+            msg!("Error: Message was deleted");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let clock = Clock::get()?;
+        if message.is_expired(clock.unix_timestamp) {
+            return Err(WhisperChainError::MessageExpired.into());
+        }
+
+        message.encrypted_data = new_encrypted_data;
+        message.ephemeral_public_key = new_ephemeral_public_key;
+        message.edited_at = clock.unix_timestamp;
+        // An ack covers the old ciphertext, not the new one
+        message.ack_signature = [0u8; 64];
+
+        Self::resize_account(
+            sender,
+            message_account,
+            system_program,
+            Message::space(
+                message.encrypted_data.len(),
+                message.reply_to.is_some(),
+                message.forwarded_from.is_some(),
+            ),
+        )?;
+
+        message.serialize(&mut &mut message_account.data.borrow_mut()[..])?;
+
+        msg!("Message edited successfully. Index: {}", message.index);
+        Ok(())
+    }
+
+    pub fn process_mark_read(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        up_to_index: u64,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let message_accounts = accounts_iter.as_slice();
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
+        if up_to_index >= chat.message_count {
+            msg!("Error: Message {} does not exist", up_to_index);
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let strict_visibility = chat.config.strict_visibility;
+        let (cursor, delivered_cursor) = if chat.participant1 == *participant.key {
+            (
+                &mut chat.participant1_read_cursor,
+                &mut chat.participant1_delivered_cursor,
+            )
+        } else if chat.participant2 == *participant.key {
+            (
+                &mut chat.participant2_read_cursor,
+                &mut chat.participant2_delivered_cursor,
+            )
+        } else {
+            return Err(WhisperChainError::NotAuthorized.into());
+        };
+
+        // Read cursors never rewind
+        let new_cursor = up_to_index
+            .checked_add(1)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        if new_cursor < *cursor {
+            msg!("Error: Read cursor can't move backwards");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // Strict chats only count a scheduled message as read once it shows
+        if strict_visibility {
+            Self::check_visible(
+                program_id,
+                chat_account.key,
+                message_accounts,
+                *cursor..new_cursor,
+                Clock::get()?.unix_timestamp,
+            )?;
+        }
+        *cursor = new_cursor;
+
+        // Reading a message implies it was delivered
+        if *delivered_cursor < new_cursor {
+            *delivered_cursor = new_cursor;
+        }
+
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        msg!("Messages marked read up to index {}", up_to_index);
+        Ok(())
+    }
+
+    pub fn process_mark_delivered(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        up_to_index: u64,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
+        if up_to_index >= chat.message_count {
+            msg!("Error: Message {} does not exist", up_to_index);
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let cursor = if chat.participant1 == *participant.key {
+            &mut chat.participant1_delivered_cursor
+        } else if chat.participant2 == *participant.key {
+            &mut chat.participant2_delivered_cursor
+        } else {
+            return Err(WhisperChainError::NotAuthorized.into());
+        };
+
+        // MarkRead keeps this at or past the read cursor, so only moving
+        // forward is enough to never fall behind it
+        let new_cursor = up_to_index
+            .checked_add(1)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        if new_cursor < *cursor {
+            msg!("Error: Delivery cursor can't move backwards");
+            return Err(ProgramError::InvalidArgument);
+        }
+        *cursor = new_cursor;
+
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        msg!("Messages marked delivered up to index {}", up_to_index);
+        Ok(())
+    }
+
+    pub fn process_ack_message(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        index: u64,
+        signature: [u8; 64],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let recipient = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let message_account = next_account_info(accounts_iter)?;
+        let instructions_sysvar = next_account_info(accounts_iter)?;
+
+        if !recipient.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if chat_account.owner != program_id || message_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let chat = Chat::unpack(&chat_account.data.borrow())?;
+        let mut message = Message::unpack(&message_account.data.borrow())?;
+
+        if message.chat != *chat_account.key || message.index != index {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Only the other participant can acknowledge receipt
+        if !chat.is_participant(recipient.key) || message.is_sent_by(recipient.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        // The signature must have been verified by the ed25519 program
+        // in the instruction right before this one
+        let verify_ix = get_instruction_relative(-1, instructions_sysvar)
+            .map_err(|_| WhisperChainError::InvalidPublicKey)?;
+        if !is_ed25519_verification(&verify_ix, recipient.key, &message.ack_payload(), &signature) {
+            msg!("Error: No matching ed25519 verification for this ack");
+            return Err(WhisperChainError::InvalidPublicKey.into());
+        }
+
+        message.ack_signature = signature;
+        message.serialize(&mut &mut message_account.data.borrow_mut()[..])?;
+
+        msg!("Message acknowledged. Index: {}", index);
+        Ok(())
+    }
+
+    pub fn process_rotate_key(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        new_public_key: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if !is_valid_public_key(&new_public_key) {
+            return Err(WhisperChainError::InvalidPublicKey.into());
+        }
+
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
+        if !chat.is_participant(participant.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        // Constant rotation would keep invalidating the counterpart's session
+        let clock = Clock::get()?;
+        let min_interval = i64::from(chat.config.min_rotation_interval_secs);
+        if chat.last_rotation_at != 0
+            && clock.unix_timestamp.saturating_sub(chat.last_rotation_at) < min_interval
+        {
+            msg!("Error: Key can be rotated once every {}s", min_interval);
+            return Err(WhisperChainError::RotationTooFrequent.into());
+        }
+        chat.last_rotation_at = clock.unix_timestamp;
+
+        if chat.participant1 == *participant.key {
+            chat.participant1_public_key = new_public_key;
+        } else {
+            chat.participant2_public_key = new_public_key;
+        }
+        chat.key_version = chat
+            .key_version
+            .checked_add(1)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        msg!("Public key rotated. Key version: {}", chat.key_version);
+        Ok(())
+    }
+
+    pub fn process_revoke_ephemeral_key(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        key: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if key == [0u8; 32] {
+            return Err(WhisperChainError::InvalidPublicKey.into());
+        }
+
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
+        if !chat.is_participant(participant.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        chat.revoke_key(key);
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        msg!("Ephemeral key revoked");
+        Ok(())
+    }
+
+    pub fn process_clear_dead_letter(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let sender = next_account_info(accounts_iter)?;
+        let dead_letter_account = next_account_info(accounts_iter)?;
+
+        if !sender.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if dead_letter_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let dead_letter = DeadLetter::unpack(&dead_letter_account.data.borrow())?;
+
+        if dead_letter.sender != *sender.key {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        Self::close_account(dead_letter_account, sender)?;
+
+        msg!("Dead letter cleared");
+        Ok(())
+    }
+
+    pub fn process_resync_chat(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let message_accounts = accounts_iter.as_slice();
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
+        if !chat.is_participant(participant.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        let mut last_index = None;
+        let mut pinned_count: u16 = 0;
+        for message_account in message_accounts {
+            if message_account.owner != program_id {
+                return Err(WhisperChainError::InvalidAccountOwner.into());
+            }
+
+            let message = Message::unpack(&message_account.data.borrow())?;
+
+            let (message_pda, _) = Pubkey::find_program_address(
+                &[
+                    b"message",
+                    chat_account.key.as_ref(),
+                    &message.index.to_le_bytes(),
+                ],
+                program_id,
+            );
+
+            if message.chat != *chat_account.key || message_pda != *message_account.key {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            // Ascending order rules out counting an account twice
+            if last_index.is_some_and(|last| message.index <= last) {
+                msg!("Error: Message accounts must be in ascending index order");
+                return Err(ProgramError::InvalidArgument);
+            }
+            last_index = Some(message.index);
+            if message.pinned {
+                pinned_count = pinned_count
+                    .checked_add(1)
+                    .ok_or(ProgramError::ArithmeticOverflow)?;
+            }
+        }
+
+        // Nothing proves the accounts passed are all that exist, so counters
+        // only ever go up: a lower message_count would hand out indices of
+        // live messages again, and a lower live count would let DeleteChat
+        // strand the messages left out
+        if let Some(last_index) = last_index {
+            let next_index = last_index
+                .checked_add(1)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+            chat.message_count = chat.message_count.max(next_index);
+        }
+        chat.live_message_count = chat.live_message_count.max(message_accounts.len() as u64);
+        chat.pinned_count = chat.pinned_count.max(pinned_count);
+
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        msg!(
+            "Chat resynced. Message count: {}, live: {}",
+            chat.message_count,
+            chat.live_message_count
+        );
+        Ok(())
+    }
+
+    pub fn process_set_blocked(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        target: Pubkey,
+        blocked: bool,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
+        // Only the counterpart of a 1:1 chat can be blocked
+        let flag = if chat.participant1 == *participant.key && chat.participant2 == target {
+            &mut chat.participant1_blocked_other
+        } else if chat.participant2 == *participant.key && chat.participant1 == target {
+            &mut chat.participant2_blocked_other
+        } else if chat.is_participant(participant.key) {
+            msg!("Error: Target is not the other participant");
+            return Err(ProgramError::InvalidArgument);
+        } else {
+            return Err(WhisperChainError::NotAuthorized.into());
+        };
+        *flag = blocked;
+
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        msg!("Participant {} {}", target, if blocked { "blocked" } else { "unblocked" });
+        Ok(())
+    }
+
+    pub fn process_rekey_and_resend(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        new_public_key: [u8; 32],
+        new_encrypted_data: Vec<u8>,
+        new_ephemeral_public_key: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let message_account = next_account_info(accounts_iter)?;
+        let system_program = next_account_info(accounts_iter)?;
+        let new_message_account = next_account_info(accounts_iter)?;
+        let send_tail = accounts_iter.as_slice();
+
+        if message_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        // The copy inherits the original's placement in the conversation
+        let message = Message::unpack(&message_account.data.borrow())?;
+        if message.deleted {
+            msg!("Error: Message was deleted");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let clock = Clock::get()?;
+        if message.is_expired(clock.unix_timestamp) {
+            return Err(WhisperChainError::MessageExpired.into());
+        }
+
+        // Any failure in the send or the tombstone reverts the rotation with it
+        Self::process_rotate_key(
+            program_id,
+            &[participant.clone(), chat_account.clone()],
+            new_public_key,
+        )?;
+
+        let mut send_accounts = vec![
+            participant.clone(),
+            chat_account.clone(),
+            new_message_account.clone(),
+            system_program.clone(),
+        ];
+        send_accounts.extend(send_tail.iter().cloned());
+
+        Self::create_message(
+            program_id,
+            &send_accounts,
+            SendMessageArgs {
+                encrypted_data: new_encrypted_data,
+                ephemeral_public_key: new_ephemeral_public_key,
+                expires_at: message.expires_at,
+                // A copy made after the scheduled time shows right away
+                visible_at: if message.is_visible(clock.unix_timestamp) {
+                    0
+                } else {
+                    message.visible_at
+                },
+                client_timestamp: 0,
+                client_nonce: [0u8; 16],
+                reply_to: message.reply_to,
+                show_sender: message.show_sender,
+            },
+            None,
+            true,
+        )?;
+
+        // Last, as the shrink moves lamports directly and the send's CPIs
+        // must see balances as they were
+        Self::process_soft_delete_message(
+            program_id,
+            &[
+                participant.clone(),
+                message_account.clone(),
+                chat_account.clone(),
+                system_program.clone(),
+            ],
+        )
+    }
+
+    pub fn process_archive_to_compressed(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let participant1_account = next_account_info(accounts_iter)?;
+        let participant2_account = next_account_info(accounts_iter)?;
+        let message_accounts = accounts_iter.as_slice();
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
+        if !chat.is_participant(participant.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        // A chat holds a single root, so it can only be archived once
+        if chat.is_archived() {
+            return Err(WhisperChainError::AlreadyInitialized.into());
+        }
+
+        if message_accounts.is_empty() {
+            msg!("Error: No messages to archive");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        if *participant1_account.key != chat.participant1
+            || *participant2_account.key != chat.participant2
+        {
+            msg!("Error: Refund accounts are not the chat participants");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut leaves = Vec::with_capacity(message_accounts.len());
+        let mut pinned_archived: u16 = 0;
+        for message_account in message_accounts {
+            if message_account.owner != program_id {
+                return Err(WhisperChainError::InvalidAccountOwner.into());
+            }
+
+            let message = Message::unpack(&message_account.data.borrow())?;
+
+            let (message_pda, _) = Pubkey::find_program_address(
+                &[
+                    b"message",
+                    chat_account.key.as_ref(),
+                    &message.index.to_le_bytes(),
+                ],
+                program_id,
+            );
+
+            if message.chat != *chat_account.key || message_pda != *message_account.key {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            // The leaf commits to the exact bytes that were on chain
+            leaves.push(merkle::leaf_hash(&message_account.data.borrow()));
+
+            if message.pinned {
+                pinned_archived = pinned_archived
+                    .checked_add(1)
+                    .ok_or(ProgramError::ArithmeticOverflow)?;
+            }
+
+            // Rent goes back to whoever paid for the message
+            let refund_account = if message.is_sent_by(&chat.participant1) {
+                participant1_account
+            } else {
+                participant2_account
+            };
+
+            MessageArchivedEvent {
+                chat: *chat_account.key,
+                message,
+            }
+            .emit()?;
+
+            Self::close_account(message_account, refund_account)?;
+        }
+
+        chat.merkle_root = merkle::root(&leaves);
+        chat.live_message_count = chat
+            .live_message_count
+            .checked_sub(leaves.len() as u64)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        chat.pinned_count = chat
+            .pinned_count
+            .checked_sub(pinned_archived)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        msg!("Archived {} messages", leaves.len());
+        Ok(())
+    }
+
+    pub fn process_restore_from_compressed(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        message: Message,
+        proof: Vec<[u8; 32]>,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let message_account = next_account_info(accounts_iter)?;
+        let system_program = next_account_info(accounts_iter)?;
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
+        if !chat.is_participant(participant.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        message.check_version()?;
+        if message.chat != *chat_account.key || !chat.is_archived() {
+            return Err(WhisperChainError::InvalidMerkleProof.into());
+        }
+
+        let data = message.try_to_vec()?;
+        if !merkle::verify(&chat.merkle_root, merkle::leaf_hash(&data), &proof) {
+            return Err(WhisperChainError::InvalidMerkleProof.into());
+        }
+
+        // Verify the message account is the PDA it was archived from
+        let (message_pda, message_bump) = Pubkey::find_program_address(
+            &[
+                b"message",
+                chat_account.key.as_ref(),
+                &message.index.to_le_bytes(),
+            ],
+            program_id,
+        );
+
+        if message_pda != *message_account.key {
+            msg!("Error: Message account is not the correct PDA");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // The restorer pays rent for the recreated account
+        Self::create_pda_account(
+            program_id,
+            participant,
+            message_account,
+            system_program,
+            data.len(),
+            &[
+                b"message",
+                chat_account.key.as_ref(),
+                &message.index.to_le_bytes(),
+                &[message_bump],
+            ],
+        )?;
+
+        message_account.data.borrow_mut().copy_from_slice(&data);
+
+        chat.live_message_count = chat
+            .live_message_count
+            .checked_add(1)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        // The archived bytes are fixed by the proof, so a pin comes back
+        // with its message; the cap only gates new pins
+        if message.pinned {
+            chat.pinned_count = chat
+                .pinned_count
+                .checked_add(1)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+        }
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        msg!("Message restored. Index: {}", message.index);
+        Ok(())
+    }
+
+    pub fn process_forward_message(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        new_encrypted_data: Vec<u8>,
+        new_ephemeral_public_key: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let sender = next_account_info(accounts_iter)?;
+        let source_chat_account = next_account_info(accounts_iter)?;
+        let source_message_account = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let message_account = next_account_info(accounts_iter)?;
+        let system_program = next_account_info(accounts_iter)?;
+        let clock_account = next_account_info(accounts_iter)?;
+        let send_tail = accounts_iter.as_slice();
+
+        if source_chat_account.owner != program_id
+            || source_message_account.owner != program_id
+            || chat_account.owner != program_id
+        {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        // Only someone who could read the original may vouch for its origin
+        let source_chat = Chat::unpack(&source_chat_account.data.borrow())?;
+        let source_message = Message::unpack(&source_message_account.data.borrow())?;
+        if source_message.chat != *source_chat_account.key {
+            msg!("Error: Source message does not belong to the source chat");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if !source_chat.is_participant(sender.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        // Unlike SendMessage, forwarding never joins the signer to an open chat
+        let chat = Chat::unpack(&chat_account.data.borrow())?;
+        if !chat.is_participant(sender.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        let mut send_accounts = vec![
+            sender.clone(),
+            chat_account.clone(),
+            message_account.clone(),
+            system_program.clone(),
+            clock_account.clone(),
+        ];
+        send_accounts.extend(send_tail.iter().cloned());
+
+        Self::create_message(
+            program_id,
+            &send_accounts,
+            SendMessageArgs {
+                encrypted_data: new_encrypted_data,
+                ephemeral_public_key: new_ephemeral_public_key,
+                expires_at: 0,
+                visible_at: 0,
+                client_timestamp: 0,
+                client_nonce: [0u8; 16],
+                reply_to: None,
+                show_sender: true,
+            },
+            Some(*source_message_account.key),
+            true,
+        )
+    }
+
+    pub fn process_set_pinned(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pinned: bool,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let message_account = next_account_info(accounts_iter)?;
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if chat_account.owner != program_id || message_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+        let mut message = Message::unpack(&message_account.data.borrow())?;
+
+        if message.chat != *chat_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Pins are shared chat state, so either participant may change them
+        if !chat.is_participant(participant.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        // Repeating the current state must not count the message twice
+        if message.pinned == pinned {
+            msg!("Message {} already {}", message.index, if pinned { "pinned" } else { "unpinned" });
+            return Ok(());
+        }
+
+        chat.pinned_count = if pinned {
+            if chat.pinned_count >= MAX_PINNED_MESSAGES {
+                msg!("Error: Chat already has {} pinned messages", MAX_PINNED_MESSAGES);
+                return Err(WhisperChainError::PinLimitReached.into());
+            }
+            chat.pinned_count.checked_add(1)
+        } else {
+            chat.pinned_count.checked_sub(1)
+        }
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+        message.pinned = pinned;
+
+        message.serialize(&mut &mut message_account.data.borrow_mut()[..])?;
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        msg!("Message {} {}", message.index, if pinned { "pinned" } else { "unpinned" });
+        Ok(())
+    }
+
+    pub fn process_react(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        slot: u8,
+        add: bool,
+        nonce: u64,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let message_account = next_account_info(accounts_iter)?;
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let slot = usize::from(slot);
+        if slot >= REACTION_SLOTS {
+            msg!("Error: Reaction slot {} out of range", slot);
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        if chat_account.owner != program_id || message_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let chat = Chat::unpack(&chat_account.data.borrow())?;
+        let mut message = Message::unpack(&message_account.data.borrow())?;
+
+        if message.chat != *chat_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if !chat.is_participant(participant.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        let last_nonce = if *participant.key == chat.participant1 {
+            &mut message.participant1_last_reaction_nonce
+        } else {
+            &mut message.participant2_last_reaction_nonce
+        };
+        if nonce != 0 {
+            // A retry of the reaction already counted succeeds without counting again
+            if *last_nonce == nonce {
+                msg!("Reaction nonce {} already counted", nonce);
+                return Ok(());
+            }
+            *last_nonce = nonce;
+        }
+
+        let count = &mut message.reactions[slot];
+        *count = if add {
+            count.checked_add(1)
+        } else {
+            count.checked_sub(1)
+        }
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        message.serialize(&mut &mut message_account.data.borrow_mut()[..])?;
+
+        msg!("Message {} reaction {} now {}", message.index, slot, message.reactions[slot]);
+        Ok(())
+    }
+
+    pub fn process_get_chat_info(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let chat_account = next_account_info(accounts_iter)?;
+
+        // Callers act on this data, so it must come from a real chat
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let chat = Chat::unpack(&chat_account.data.borrow())?;
+        set_return_data(&ChatInfo::from(&chat).try_to_vec()?);
+        Ok(())
+    }
+
+    pub fn process_archive_chat(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let chat_account = accounts.get(1).ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        // The digest has to be taken while the chat data still exists
+        let digest = Chat::unpack(&chat_account.data.borrow())?.state_digest();
+
+        Self::process_delete_chat(program_id, accounts)?;
+
+        set_return_data(&digest);
+        ChatArchivedEvent {
+            chat: *chat_account.key,
+            digest,
+        }
+        .emit()?;
+
+        msg!("Chat archived");
+        Ok(())
+    }
+
+    pub fn process_send_message_batch(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        messages: Vec<BatchEntry>,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let sender = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let system_program = next_account_info(accounts_iter)?;
+        let clock_account = next_account_info(accounts_iter)?;
+        let config_account = next_account_info(accounts_iter)?;
+
+        if messages.is_empty() || messages.len() > MAX_MESSAGES_PER_BATCH {
+            msg!("Error: Batch must contain 1 to {} messages", MAX_MESSAGES_PER_BATCH);
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let message_accounts = messages
+            .iter()
+            .map(|_| next_account_info(accounts_iter))
+            .collect::<Result<Vec<_>, _>>()?;
+        let send_tail = accounts_iter.as_slice();
+
+        // A dead letter holds a single message, so batches never fall back to one
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+        Chat::unpack(&chat_account.data.borrow())?;
+
+        let count = messages.len() as u64;
+
+        // Each send re-reads the chat, so the expected index advances per entry.
+        // The batch counts as one send against the rate limit.
+        for (i, (entry, message_account)) in messages.into_iter().zip(message_accounts).enumerate() {
+            let mut send_accounts = vec![
+                sender.clone(),
+                chat_account.clone(),
+                message_account.clone(),
+                system_program.clone(),
+                clock_account.clone(),
+                config_account.clone(),
+            ];
+            send_accounts.extend(send_tail.iter().cloned());
+
+            Self::create_message(
+                program_id,
+                &send_accounts,
+                SendMessageArgs {
+                    encrypted_data: entry.encrypted_data,
+                    ephemeral_public_key: entry.ephemeral_public_key,
+                    expires_at: entry.expires_at,
+                    visible_at: 0,
+                    client_timestamp: 0,
+                    client_nonce: [0u8; 16],
+                    reply_to: None,
+                    show_sender: true,
+                },
+                None,
+                i == 0,
+            )?;
+        }
+
+        // Replaces the last entry's SentMessage
+        let chat = Chat::unpack(&chat_account.data.borrow())?;
+        set_return_data(
+            &BatchSent {
+                next_index: chat.message_count,
+                count,
+            }
+            .try_to_vec()?,
+        );
+
+        Ok(())
+    }
+
+    pub fn process_transfer_participant(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        new_pubkey: Pubkey,
+        new_public_key: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let participant = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+
+        if !participant.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if !is_valid_public_key(&new_public_key) {
+            return Err(WhisperChainError::InvalidPublicKey.into());
+        }
+
+        if chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut chat = Chat::unpack(&chat_account.data.borrow())?;
+
+        if chat.participant2 != *participant.key || *participant.key == Pubkey::default() {
+            msg!("Error: Only participant2 can transfer their slot");
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        // Batch chats are keyed by participant2, so the address would go stale
+        let (chat_pda, _) =
+            Pubkey::find_program_address(&[b"chat", chat.participant1.as_ref()], program_id);
+        if chat_pda != *chat_account.key {
+            msg!("Error: Only chats keyed by participant1 alone can be transferred");
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        if new_pubkey == Pubkey::default() {
+            msg!("Error: New participant can't be the default key");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        // Same rule as InitializeChatsBatch: a transfer can't produce a self chat
+        if new_pubkey == chat.participant1 {
+            msg!("Error: New participant is already participant1");
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        // Cursors, counters and block flags belong to the slot and carry over
+        chat.participant2 = new_pubkey;
+        chat.participant2_public_key = new_public_key;
+        chat.key_version = chat
+            .key_version
+            .checked_add(1)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        msg!("Participant2 transferred to {}", new_pubkey);
+        Ok(())
+    }
+
+    pub fn process_ping() -> ProgramResult {
+        msg!("WhisperChain v{}", PROGRAM_VERSION);
+        set_return_data(PROGRAM_VERSION.as_bytes());
+        Ok(())
+    }
+
+    pub fn process_init_config(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let admin = next_account_info(accounts_iter)?;
+        let config_account = next_account_info(accounts_iter)?;
+        let system_program = next_account_info(accounts_iter)?;
+        let program_data_account = next_account_info(accounts_iter)?;
+
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        // Whoever can redeploy the program can already do anything an admin
+        // can, so they are the only one allowed to claim the role
+        if Self::upgrade_authority(program_id, program_data_account)? != Some(*admin.key) {
+            msg!("Error: Only the program's upgrade authority can initialize the config");
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        let (config_pda, config_bump) = Pubkey::find_program_address(&[b"config"], program_id);
+        if config_pda != *config_account.key {
+            msg!("Error: Config account is not the correct PDA");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // The singleton can only be claimed once
+        if !config_account.data_is_empty() || config_account.lamports() != 0 {
+            return Err(WhisperChainError::AlreadyInitialized.into());
+        }
+
+        Self::create_pda_account(
+            program_id,
+            admin,
+            config_account,
+            system_program,
+            Config::LEN,
+            &[b"config", &[config_bump]],
+        )?;
+
+        let config = Config {
+            version: Config::VERSION,
+            is_initialized: true,
+            admin: *admin.key,
+            paused: false,
+            fee_lamports: 0,
+            treasury: Pubkey::default(),
+        };
+        config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+
+        msg!("Config initialized. Admin: {}", admin.key);
+        Ok(())
+    }
+
+    pub fn process_set_paused(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        paused: bool,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let admin = next_account_info(accounts_iter)?;
+        let config_account = next_account_info(accounts_iter)?;
+
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if config_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut config = Config::unpack(&config_account.data.borrow())?;
+        if config.admin != *admin.key {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        config.paused = paused;
+        config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+
+        msg!("Program {}", if paused { "paused" } else { "resumed" });
+        Ok(())
+    }
+
+    pub fn process_soft_delete_message(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let sender = next_account_info(accounts_iter)?;
+        let message_account = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let system_program = next_account_info(accounts_iter)?;
+
+        if !sender.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if message_account.owner != program_id || chat_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut message = Message::unpack(&message_account.data.borrow())?;
+        if message.chat != *chat_account.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if !message.is_sent_by(sender.key) {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        // Index, timestamp and sender stay so clients can draw a placeholder
+        message.deleted = true;
+        message.encrypted_data = Vec::new();
+        // An ack covers the wiped ciphertext
+        message.ack_signature = [0u8; 64];
+
+        Self::resize_account(
+            sender,
+            message_account,
+            system_program,
+            Message::space(0, message.reply_to.is_some(), message.forwarded_from.is_some()),
+        )?;
+
+        message.serialize(&mut &mut message_account.data.borrow_mut()[..])?;
+
+        msg!("Message soft-deleted. Index: {}", message.index);
+        Ok(())
+    }
+
+    pub fn process_initialize_chat_pair(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        other: Pubkey,
+        public_key: [u8; 32],
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let initializer = next_account_info(accounts_iter)?;
+        let chat_account = next_account_info(accounts_iter)?;
+        let system_program = next_account_info(accounts_iter)?;
+        let config_account = next_account_info(accounts_iter)?;
+        let stats_account = Self::find_singleton(program_id, b"stats", accounts_iter.as_slice());
+
+        if !initializer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        Self::check_not_paused(program_id, config_account)?;
+
+        if !is_valid_public_key(&public_key) {
+            return Err(WhisperChainError::InvalidPublicKey.into());
+        }
+
+        // Pair chats use the default config, which never allows self chat
+        if other == *initializer.key || other == Pubkey::default() {
+            msg!("Error: Pair chat needs two distinct participants");
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        let (lower, higher) = Chat::sorted_pair(initializer.key, &other);
+        let (chat_pda, chat_bump) = Pubkey::find_program_address(
+            &[b"chat_pair", lower.as_ref(), higher.as_ref()],
+            program_id,
+        );
+
+        if chat_pda != *chat_account.key {
+            msg!("Error: Chat account is not the correct PDA");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Whoever initialized first owns the pair's only chat
+        if !chat_account.data_is_empty() || chat_account.lamports() != 0 {
+            msg!("Error: Chat already exists for this pair");
+            return Err(WhisperChainError::AlreadyInitialized.into());
+        }
+
+        Self::create_pda_account(
+            program_id,
+            initializer,
+            chat_account,
+            system_program,
+            Chat::LEN,
+            &[b"chat_pair", lower.as_ref(), higher.as_ref(), &[chat_bump]],
+        )?;
+
+        let clock = Clock::get()?;
+        let chat = Chat {
+            version: Chat::VERSION,
+            is_initialized: true,
+            participant1: *initializer.key,
+            participant2: other,
+            participant1_public_key: public_key,
+            participant2_public_key: [0u8; 32], // Set by their first message
+            created_at: clock.unix_timestamp,
+            message_count: 0,
+            live_message_count: 0,
+            pinned_count: 0,
+            last_message_at: 0,
+            participant1_read_cursor: 0,
+            participant2_read_cursor: 0,
+            participant1_delivered_cursor: 0,
+            participant2_delivered_cursor: 0,
+            participant1_sent_count: 0,
+            participant2_sent_count: 0,
+            participant1_last_sent_at: 0,
+            participant2_last_sent_at: 0,
+            participant1_blocked_other: false,
+            participant2_blocked_other: false,
+            key_version: 0,
+            last_rotation_at: 0,
+            revoked_keys: [[0u8; 32]; MAX_REVOKED_KEYS],
+            revoked_keys_next: 0,
+            recent_nonces: [[0u8; 16]; MAX_RECENT_NONCES],
+            recent_nonces_next: 0,
+            config: ChatConfig::default(),
+            merkle_root: [0u8; 32],
+        };
+
+        chat.serialize(&mut &mut chat_account.data.borrow_mut()[..])?;
+
+        if let Some(stats_account) = stats_account {
+            Self::record_stats(program_id, initializer, stats_account, system_program, 1, 0)?;
+        }
+
+        ChatInitializedEvent {
+            chat: *chat_account.key,
+            participant1: chat.participant1,
+            participant2: chat.participant2,
+            created_at: chat.created_at,
+        }
+        .emit()?;
+
+        msg!("Pair chat initialized successfully");
+        Ok(())
+    }
+
+    pub fn process_set_fee(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        fee_lamports: u64,
+        treasury: Pubkey,
+    ) -> ProgramResult {
+        let accounts_iter = &mut accounts.iter();
+
+        let admin = next_account_info(accounts_iter)?;
+        let config_account = next_account_info(accounts_iter)?;
+
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if config_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let mut config = Config::unpack(&config_account.data.borrow())?;
+        if config.admin != *admin.key {
+            return Err(WhisperChainError::NotAuthorized.into());
+        }
+
+        if fee_lamports != 0 && treasury == Pubkey::default() {
+            msg!("Error: A send fee needs a treasury");
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        config.fee_lamports = fee_lamports;
+        config.treasury = treasury;
+        config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+
+        msg!("Send fee set to {} lamports", fee_lamports);
+        Ok(())
+    }
+
+    /// Upgrade authority recorded in the BPF upgradeable loader's
+    /// ProgramData account for `program_id` (None = immutable program)
+    fn upgrade_authority(
+        program_id: &Pubkey,
+        program_data_account: &AccountInfo,
+    ) -> Result<Option<Pubkey>, ProgramError> {
+        let (program_data_pda, _) =
+            Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+        if program_data_pda != *program_data_account.key
+            || *program_data_account.owner != bpf_loader_upgradeable::id()
+        {
+            msg!("Error: Account is not the ProgramData of this program");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        match limited_deserialize(
+            &program_data_account.data.borrow(),
+            UpgradeableLoaderState::size_of_programdata_metadata() as u64,
+        ) {
+            Ok(UpgradeableLoaderState::ProgramData {
+                upgrade_authority_address,
+                ..
+            }) => Ok(upgrade_authority_address),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// The `[seed]` PDA among `accounts`, the optional trailing accounts of
+    /// an instruction, which clients may pass in any order
+    fn find_singleton<'a, 'b>(
+        program_id: &Pubkey,
+        seed: &[u8],
+        accounts: &'b [AccountInfo<'a>],
+    ) -> Option<&'b AccountInfo<'a>> {
+        if accounts.is_empty() {
+            return None;
+        }
+        let (pda, _) = Pubkey::find_program_address(&[seed], program_id);
+        accounts.iter().find(|account| *account.key == pda)
+    }
+
+    /// Refuse new writes while the admin has paused the program, returning
+    /// the config if there is one. Only a config that was never initialized
+    /// counts as not paused.
+    fn check_not_paused(
+        program_id: &Pubkey,
+        config_account: &AccountInfo,
+    ) -> Result<Option<Config>, ProgramError> {
+        let (config_pda, _) = Pubkey::find_program_address(&[b"config"], program_id);
+        if config_pda != *config_account.key {
+            msg!("Error: Config account is not the correct PDA");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        if config_account.data_is_empty() {
+            return Ok(None);
+        }
+        if config_account.owner != program_id {
+            return Err(WhisperChainError::InvalidAccountOwner.into());
+        }
+
+        let config = Config::unpack(&config_account.data.borrow())?;
+        if config.paused {
+            msg!("Error: Program is paused by its admin");
+            return Err(WhisperChainError::ProgramPaused.into());
+        }
+        Ok(Some(config))
+    }
+
+    /// Pay the config's send fee from `payer` to its treasury, as long as
+    /// `payer` can still fund the `space`-byte account it is about to create
+    fn collect_fee<'a>(
+        payer: &AccountInfo<'a>,
+        treasury_account: Option<&AccountInfo<'a>>,
+        system_program: &AccountInfo<'a>,
+        config: &Config,
+        space: usize,
+    ) -> ProgramResult {
+        let Some(treasury_account) = treasury_account else {
+            msg!("Error: Treasury account is required while a send fee is set");
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+
+        let required = config
+            .fee_lamports
+            .checked_add(Rent::get()?.minimum_balance(space))
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        if payer.lamports() < required {
+            msg!("Error: Sender needs {} lamports for the fee and rent", required);
+            return Err(WhisperChainError::InsufficientFunds.into());
+        }
+
+        invoke(
+            &system_instruction::transfer(payer.key, treasury_account.key, config.fee_lamports),
+            &[
+                payer.clone(),
+                treasury_account.clone(),
+                system_program.clone(),
+            ],
+        )
+    }
+
+    /// Add to the counters in the stats PDA, creating it on first use
+    fn record_stats<'a>(
+        program_id: &Pubkey,
+        payer: &AccountInfo<'a>,
+        stats_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        new_chats: u64,
+        new_messages: u64,
+    ) -> ProgramResult {
+        let (stats_pda, stats_bump) = Pubkey::find_program_address(&[b"stats"], program_id);
+        if stats_pda != *stats_account.key {
+            msg!("Error: Stats account is not the correct PDA");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut stats = if stats_account.data_is_empty() {
+            Self::create_pda_account(
+                program_id,
+                payer,
+                stats_account,
+                system_program,
+                Stats::LEN,
+                &[b"stats", &[stats_bump]],
+            )?;
+            Stats {
+                version: Stats::VERSION,
+                is_initialized: true,
+                total_chats: 0,
+                total_messages: 0,
+            }
+        } else {
+            if stats_account.owner != program_id {
+                return Err(WhisperChainError::InvalidAccountOwner.into());
+            }
+            Stats::unpack(&stats_account.data.borrow())?
+        };
+
+        stats.total_chats = stats
+            .total_chats
+            .checked_add(new_chats)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        stats.total_messages = stats
+            .total_messages
+            .checked_add(new_messages)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        stats.serialize(&mut &mut stats_account.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    /// Check that `message_accounts` are the message PDAs for every index in
+    /// `indices`, in order, and that each one still open is visible at `now`
+    fn check_visible(
+        program_id: &Pubkey,
+        chat_key: &Pubkey,
+        message_accounts: &[AccountInfo],
+        indices: core::ops::Range<u64>,
+        now: i64,
+    ) -> ProgramResult {
+        if message_accounts.len() as u64 != indices.end - indices.start {
+            msg!(
+                "Error: Expected message accounts for indices {} to {}",
+                indices.start,
+                indices.end.saturating_sub(1)
+            );
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+
+        for (index, message_account) in indices.zip(message_accounts) {
+            let (message_pda, _) = Pubkey::find_program_address(
+                &[b"message", chat_key.as_ref(), &index.to_le_bytes()],
+                program_id,
+            );
+            if message_pda != *message_account.key {
+                msg!("Error: Account is not the PDA of message {}", index);
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            // Deleted and archived messages have nothing left to hide
+            if message_account.data_is_empty() || message_account.lamports() == 0 {
+                continue;
+            }
+            if message_account.owner != program_id {
+                return Err(WhisperChainError::InvalidAccountOwner.into());
+            }
+
+            let message = Message::unpack(&message_account.data.borrow())?;
+            if !message.is_visible(now) {
+                msg!("Error: Message {} is visible from {}", index, message.visible_at);
+                return Err(WhisperChainError::NotYetVisible.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Chats live at `[b"chat", participant1]`, additionally keyed by
+    /// participant2 when created by InitializeChatsBatch, or at
+    /// `[b"chat_pair", lower, higher]` when created by InitializeChatPair
+    fn check_chat_address(program_id: &Pubkey, address: &Pubkey, chat: &Chat) -> ProgramResult {
+        let (single_pda, _) =
+            Pubkey::find_program_address(&[b"chat", chat.participant1.as_ref()], program_id);
+        if single_pda == *address {
+            return Ok(());
+        }
+
+        let (batch_pda, _) = Pubkey::find_program_address(
+            &[b"chat", chat.participant1.as_ref(), chat.participant2.as_ref()],
+            program_id,
+        );
+        if batch_pda == *address {
+            return Ok(());
+        }
+
+        let (lower, higher) = Chat::sorted_pair(&chat.participant1, &chat.participant2);
+        let (pair_pda, _) = Pubkey::find_program_address(
+            &[b"chat_pair", lower.as_ref(), higher.as_ref()],
+            program_id,
+        );
+        if pair_pda != *address {
+            msg!("Error: Chat account is not the PDA of its participants");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Ok(())
+    }
+
+    /// Create a program-owned PDA account of `space` bytes, funded by `payer`
+    fn create_pda_account<'a>(
+        program_id: &Pubkey,
+        payer: &AccountInfo<'a>,
+        new_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        space: usize,
+        signer_seeds: &[&[u8]],
+    ) -> ProgramResult {
+        let rent = Rent::get()?;
+        let lamports = rent.minimum_balance(space);
+
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                new_account.key,
+                lamports,
+                space as u64,
+                program_id,
+            ),
+            &[
+                payer.clone(),
+                new_account.clone(),
+                system_program.clone(),
+            ],
+            &[signer_seeds],
+        )
+    }
+
+    /// Resize a program-owned account to `new_len` bytes, topping up rent
+    /// from `payer` when it grows and returning the excess when it shrinks
+    fn resize_account<'a>(
+        payer: &AccountInfo<'a>,
+        account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        new_len: usize,
+    ) -> ProgramResult {
+        Self::check_realloc_growth(account.data_len(), new_len)?;
+
+        let rent = Rent::get()?;
+        let required = rent.minimum_balance(new_len);
+        let current = account.lamports();
+
+        if required > current {
+            invoke(
+                &system_instruction::transfer(payer.key, account.key, required - current),
+                &[
+                    payer.clone(),
+                    account.clone(),
+                    system_program.clone(),
+                ],
+            )?;
+        } else if current > required {
+            **account.lamports.borrow_mut() = required;
+            **payer.lamports.borrow_mut() = payer
+                .lamports()
+                .checked_add(current - required)
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+        }
+
+        account.realloc(new_len, false)
+    }
+
+    /// Close message accounts of `chat`, refunding each one's rent to the
+    /// participant who sent it, and return how many of them were pinned
+    fn close_chat_messages<'a>(
+        program_id: &Pubkey,
+        chat_account: &AccountInfo<'a>,
+        chat: &Chat,
+        participant1_account: &AccountInfo<'a>,
+        participant2_account: &AccountInfo<'a>,
+        message_accounts: &[AccountInfo<'a>],
+    ) -> Result<u16, ProgramError> {
+        if *participant1_account.key != chat.participant1
+            || *participant2_account.key != chat.participant2
+        {
+            msg!("Error: Refund accounts are not the chat participants");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut pinned_closed: u16 = 0;
+        for message_account in message_accounts {
+            if message_account.owner != program_id {
+                return Err(WhisperChainError::InvalidAccountOwner.into());
+            }
+
+            // A repeated account was zeroed by the first close and fails here
+            let message = Message::unpack(&message_account.data.borrow())?;
+
+            let (message_pda, _) = Pubkey::find_program_address(
+                &[
+                    b"message",
+                    chat_account.key.as_ref(),
+                    &message.index.to_le_bytes(),
+                ],
+                program_id,
+            );
+
+            if message.chat != *chat_account.key || message_pda != *message_account.key {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            if message.pinned {
+                pinned_closed = pinned_closed
+                    .checked_add(1)
+                    .ok_or(ProgramError::ArithmeticOverflow)?;
+            }
+
+            // Rent goes back to whoever paid for the message
+            let refund_account = if message.is_sent_by(&chat.participant1) {
+                participant1_account
+            } else {
+                participant2_account
+            };
+            Self::close_account(message_account, refund_account)?;
+        }
+
+        Ok(pinned_closed)
+    }
+
+    /// Move all lamports of `account` to `destination` and zero its data
+    fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+        let dest_starting_lamports = destination.lamports();
+        **destination.lamports.borrow_mut() = dest_starting_lamports
+            .checked_add(account.lamports())
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        **account.lamports.borrow_mut() = 0;
+
+        account.data.borrow_mut().fill(0);
+        Ok(())
+    }
+
+    /// Accounts can grow by at most 10KB per instruction; fail clearly
+    /// before calling `realloc` rather than with an opaque runtime error
+    fn check_realloc_growth(current_len: usize, new_len: usize) -> ProgramResult {
+        if new_len.saturating_sub(current_len) > MAX_PERMITTED_DATA_INCREASE {
+            msg!(
+                "Error: Growing {} -> {} bytes exceeds the {} byte realloc limit, split the operation",
+                current_len,
+                new_len,
+                MAX_PERMITTED_DATA_INCREASE
+            );
+            return Err(WhisperChainError::ReallocLimitExceeded.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_realloc_growth_limit() {
+        assert!(Processor::check_realloc_growth(100, 100 + MAX_PERMITTED_DATA_INCREASE).is_ok());
+        assert!(Processor::check_realloc_growth(20_000, 100).is_ok());
+
+        let result = Processor::check_realloc_growth(100, 101 + MAX_PERMITTED_DATA_INCREASE);
+        assert_eq!(result, Err(WhisperChainError::ReallocLimitExceeded.into()));
+    }
 }
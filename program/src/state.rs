@@ -1,12 +1,139 @@
 use borsh::{BorshDeserialize, BorshSerialize};
-use solana_program::pubkey::Pubkey;
+use solana_program::{
+    entrypoint::ProgramResult,
+    hash::{hash, hashv},
+    keccak,
+    program::MAX_RETURN_DATA,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::error::WhisperChainError;
 
 /// Maximum size for encrypted message data (512 bytes)
 pub const MAX_MESSAGE_SIZE: usize = 512;
 
+/// Maximum number of chats created by a single InitializeChatsBatch
+pub const MAX_CHATS_PER_BATCH: usize = 8;
+
+/// Maximum number of messages sent by a single SendMessageBatch
+pub const MAX_MESSAGES_PER_BATCH: usize = 8;
+
+/// Number of revoked ephemeral keys a chat remembers
+pub const MAX_REVOKED_KEYS: usize = 4;
+
+/// Number of recent client nonces a chat remembers for deduplication
+pub const MAX_RECENT_NONCES: usize = 8;
+
+/// Maximum number of messages pinned in one chat at a time
+pub const MAX_PINNED_MESSAGES: u16 = 50;
+
+/// Number of reaction counters on a message; clients map slots to a fixed palette
+pub const REACTION_SLOTS: usize = 8;
+
+/// Furthest in the future a message may be set to expire (365 days)
+pub const MAX_EXPIRY_HORIZON_SECS: i64 = 365 * 24 * 60 * 60;
+
+/// Client clock drift tolerated before a message is flagged, when a chat doesn't set its own
+pub const DEFAULT_MAX_CLOCK_DRIFT_SECS: u32 = 300;
+
+/// One chat to open in an InitializeChatsBatch instruction
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ChatInit {
+    /// The other participant of the chat
+    pub counterpart: Pubkey,
+
+    /// Initializer's public key for DH exchange with this counterpart
+    pub public_key: [u8; 32],
+}
+
+/// One message to send in a SendMessageBatch instruction
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct BatchEntry {
+    /// Encrypted message data (max 512 bytes)
+    pub encrypted_data: Vec<u8>,
+
+    /// Ephemeral public key for this message (32 bytes)
+    pub ephemeral_public_key: [u8; 32],
+
+    /// Optional expiration timestamp (0 = never expires)
+    pub expires_at: i64,
+}
+
+/// Per-chat settings chosen by the initializer
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct ChatConfig {
+    /// Permitted ciphertext sizes in bytes (zero entries are ignored, all zero allows any size)
+    pub allowed_sizes: [u16; 4],
+
+    /// Client clock drift in seconds before a message is flagged (0 = default)
+    pub max_clock_drift_secs: u32,
+
+    /// Let participant1 send before anyone has joined, using the chat as a notebook
+    pub allow_self_chat: bool,
+
+    /// Most messages participant1 may send (0 = unlimited)
+    pub participant1_quota: u32,
+
+    /// Most messages participant2 may send (0 = unlimited)
+    pub participant2_quota: u32,
+
+    /// Seconds each sender must wait between their own messages (0 = unlimited)
+    pub min_interval_secs: u32,
+
+    /// Seconds between RotateKey calls on this chat (0 = unlimited)
+    pub min_rotation_interval_secs: u32,
+
+    /// Lifetime given to messages sent without an `expires_at` (0 = never expire)
+    pub default_ttl_secs: u32,
+
+    /// Refuse MarkRead over messages whose `visible_at` hasn't passed yet
+    pub strict_visibility: bool,
+}
+
+impl ChatConfig {
+    pub const LEN: usize = 2 * 4 + // allowed_sizes
+        4 + // max_clock_drift_secs
+        1 + // allow_self_chat
+        4 + // participant1_quota
+        4 + // participant2_quota
+        4 + // min_interval_secs
+        4 + // min_rotation_interval_secs
+        4 + // default_ttl_secs
+        1; // strict_visibility
+
+    /// Restricting ciphertexts to a few sizes keeps traffic in a handful of buckets
+    pub fn allows_size(&self, size: usize) -> bool {
+        let mut buckets = self.allowed_sizes.iter().filter(|&&bucket| bucket != 0).peekable();
+        buckets.peek().is_none() || buckets.any(|&bucket| bucket as usize == size)
+    }
+
+    /// Whether the client-reported time is too far from the cluster clock
+    pub fn is_drifted(&self, received_at: i64, client_timestamp: i64) -> bool {
+        let max_drift = match self.max_clock_drift_secs {
+            0 => DEFAULT_MAX_CLOCK_DRIFT_SECS,
+            secs => secs,
+        };
+        client_timestamp != 0 && received_at.abs_diff(client_timestamp) > u64::from(max_drift)
+    }
+
+    /// Expiry stored for a message sent at `sent_at` that asked for
+    /// `expires_at`; an explicit expiry always wins over the default TTL
+    pub fn effective_expiry(&self, sent_at: i64, expires_at: i64) -> i64 {
+        if expires_at == 0 && self.default_ttl_secs != 0 {
+            sent_at.saturating_add(i64::from(self.default_ttl_secs))
+        } else {
+            expires_at
+        }
+    }
+}
+
 /// Chat account state
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct Chat {
+    /// Layout version the account was written with
+    pub version: u8,
+
     /// Is this chat initialized
     pub is_initialized: bool,
 
@@ -28,46 +155,315 @@ pub struct Chat {
     /// Total messages in this chat
     pub message_count: u64,
 
+    /// Message accounts that currently exist (not deleted or archived)
+    pub live_message_count: u64,
+
+    /// Live messages with `pinned` set
+    pub pinned_count: u16,
+
     /// Last message timestamp
     pub last_message_at: i64,
+
+    /// Read cursor of participant 1: every index below it has been read
+    pub participant1_read_cursor: u64,
+
+    /// Read cursor of participant 2: every index below it has been read
+    pub participant2_read_cursor: u64,
+
+    /// Delivery cursor of participant 1: every index below it has been
+    /// downloaded by their client (never behind the read cursor)
+    pub participant1_delivered_cursor: u64,
+
+    /// Delivery cursor of participant 2: every index below it has been
+    /// downloaded by their client (never behind the read cursor)
+    pub participant2_delivered_cursor: u64,
+
+    /// Messages sent by participant 1, counted against their quota
+    pub participant1_sent_count: u64,
+
+    /// Messages sent by participant 2, counted against their quota
+    pub participant2_sent_count: u64,
+
+    /// Timestamp of participant 1's last message (0 = never sent)
+    pub participant1_last_sent_at: i64,
+
+    /// Timestamp of participant 2's last message (0 = never sent)
+    pub participant2_last_sent_at: i64,
+
+    /// Participant 1 refuses further messages from participant 2
+    pub participant1_blocked_other: bool,
+
+    /// Participant 2 refuses further messages from participant 1
+    pub participant2_blocked_other: bool,
+
+    /// Bumped on every RotateKey so clients can spot a stale DH key
+    pub key_version: u64,
+
+    /// Timestamp of the last RotateKey (0 = never rotated)
+    pub last_rotation_at: i64,
+
+    /// Ring of leaked ephemeral keys, oldest overwritten first (zero = empty slot)
+    pub revoked_keys: [[u8; 32]; MAX_REVOKED_KEYS],
+
+    /// Slot in `revoked_keys` the next revocation is written to
+    pub revoked_keys_next: u8,
+
+    /// Ring of the latest sends' client nonces, oldest overwritten first (zero = empty slot)
+    pub recent_nonces: [[u8; 16]; MAX_RECENT_NONCES],
+
+    /// Slot in `recent_nonces` the next nonce is written to
+    pub recent_nonces_next: u8,
+
+    /// Settings chosen at initialization
+    pub config: ChatConfig,
+
+    /// Root over the messages moved out by ArchiveToCompressed (zero = not archived)
+    pub merkle_root: [u8; 32],
 }
 
 impl Chat {
-    pub const LEN: usize = 1 + // is_initialized
+    /// Current account layout version
+    pub const VERSION: u8 = 9;
+
+    pub const LEN: usize = 1 + // version
+        1 + // is_initialized
         32 + // participant1
         32 + // participant2
         32 + // participant1_public_key
         32 + // participant2_public_key
         8 +  // created_at
         8 +  // message_count
-        8;   // last_message_at
+        8 +  // live_message_count
+        2 +  // pinned_count
+        8 +  // last_message_at
+        8 +  // participant1_read_cursor
+        8 +  // participant2_read_cursor
+        8 +  // participant1_delivered_cursor
+        8 +  // participant2_delivered_cursor
+        8 +  // participant1_sent_count
+        8 +  // participant2_sent_count
+        8 +  // participant1_last_sent_at
+        8 +  // participant2_last_sent_at
+        1 +  // participant1_blocked_other
+        1 +  // participant2_blocked_other
+        8 +  // key_version
+        8 +  // last_rotation_at
+        32 * MAX_REVOKED_KEYS + // revoked_keys
+        1 +  // revoked_keys_next
+        16 * MAX_RECENT_NONCES + // recent_nonces
+        1 +  // recent_nonces_next
+        ChatConfig::LEN + // config
+        32; // merkle_root
+
+    /// Byte range of both participants' DH public keys in the account data,
+    /// which follow version, is_initialized and the two participants
+    pub const PUBLIC_KEYS_RANGE: core::ops::Range<usize> = 66..66 + 2 * 32;
+
+    /// Reject accounts written by a newer program than this one
+    pub fn check_version(&self) -> ProgramResult {
+        if self.version > Self::VERSION {
+            return Err(WhisperChainError::UnsupportedVersion.into());
+        }
+        Ok(())
+    }
+
+    /// Decode a live chat account, rejecting closed (zeroed) and truncated ones
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let chat: Self = unpack_prefix(data, Self::LEN)?;
+        chat.check_version()?;
+        if !chat.is_initialized {
+            return Err(WhisperChainError::NotInitialized.into());
+        }
+        Ok(chat)
+    }
+
+    /// Keccak digest of the chat's identity and activity, recorded by
+    /// ArchiveChat as proof the chat existed
+    pub fn state_digest(&self) -> [u8; 32] {
+        keccak::hashv(&[
+            self.participant1.as_ref(),
+            self.participant2.as_ref(),
+            &self.message_count.to_le_bytes(),
+            &self.created_at.to_le_bytes(),
+            &self.last_message_at.to_le_bytes(),
+        ])
+        .to_bytes()
+    }
+
+    /// The pair in the order `[b"chat_pair", lower, higher]` seeds use, so
+    /// both sides derive the same address
+    pub fn sorted_pair<'a>(a: &'a Pubkey, b: &'a Pubkey) -> (&'a Pubkey, &'a Pubkey) {
+        if a <= b {
+            (a, b)
+        } else {
+            (b, a)
+        }
+    }
+
+    /// Take `sender` on as participant2 while the chat is still open,
+    /// returning whether this call is the one that joined them
+    pub fn try_join(&mut self, sender: &Pubkey, public_key: [u8; 32]) -> bool {
+        if self.participant2 != Pubkey::default() || self.participant1 == *sender {
+            return false;
+        }
+        self.participant2 = *sender;
+        self.participant2_public_key = public_key;
+        true
+    }
 
     pub fn is_participant(&self, pubkey: &Pubkey) -> bool {
         self.participant1 == *pubkey || self.participant2 == *pubkey
     }
+
+    /// The read cursor of `pubkey`, if they are a participant
+    pub fn read_cursor(&self, pubkey: &Pubkey) -> Option<u64> {
+        if self.participant1 == *pubkey {
+            Some(self.participant1_read_cursor)
+        } else if self.participant2 == *pubkey {
+            Some(self.participant2_read_cursor)
+        } else {
+            None
+        }
+    }
+
+    /// Whether `pubkey` has read the message at `index`
+    pub fn has_read(&self, pubkey: &Pubkey, index: u64) -> bool {
+        self.read_cursor(pubkey).is_some_and(|cursor| index < cursor)
+    }
+
+    /// The delivery cursor of `pubkey`, if they are a participant
+    pub fn delivered_cursor(&self, pubkey: &Pubkey) -> Option<u64> {
+        if self.participant1 == *pubkey {
+            Some(self.participant1_delivered_cursor)
+        } else if self.participant2 == *pubkey {
+            Some(self.participant2_delivered_cursor)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the message at `index` has reached `pubkey`'s client
+    pub fn has_delivered(&self, pubkey: &Pubkey, index: u64) -> bool {
+        self.delivered_cursor(pubkey).is_some_and(|cursor| index < cursor)
+    }
+
+    /// Whether the other participant has blocked `sender`
+    pub fn is_blocked(&self, sender: &Pubkey) -> bool {
+        if self.participant1 == *sender {
+            self.participant2_blocked_other
+        } else if self.participant2 == *sender {
+            self.participant1_blocked_other
+        } else {
+            false
+        }
+    }
+
+    pub fn is_key_revoked(&self, key: &[u8; 32]) -> bool {
+        *key != [0u8; 32] && self.revoked_keys.contains(key)
+    }
+
+    /// Record a leaked ephemeral key, evicting the oldest once the ring is full
+    pub fn revoke_key(&mut self, key: [u8; 32]) {
+        if self.is_key_revoked(&key) {
+            return;
+        }
+        let slot = self.revoked_keys_next as usize % MAX_REVOKED_KEYS;
+        self.revoked_keys[slot] = key;
+        self.revoked_keys_next = ((slot + 1) % MAX_REVOKED_KEYS) as u8;
+    }
+
+    pub fn has_recent_nonce(&self, nonce: &[u8; 16]) -> bool {
+        *nonce != [0u8; 16] && self.recent_nonces.contains(nonce)
+    }
+
+    /// Remember a send's nonce, evicting the oldest once the ring is full
+    pub fn record_nonce(&mut self, nonce: [u8; 16]) {
+        if nonce == [0u8; 16] {
+            return;
+        }
+        let slot = self.recent_nonces_next as usize % MAX_RECENT_NONCES;
+        self.recent_nonces[slot] = nonce;
+        self.recent_nonces_next = ((slot + 1) % MAX_RECENT_NONCES) as u8;
+    }
+
+    /// Messages encrypted with a revoked key should be treated as compromised
+    pub fn is_compromised(&self, message: &Message) -> bool {
+        self.is_key_revoked(&message.ephemeral_public_key)
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.merkle_root != [0u8; 32]
+    }
 }
 
 /// Message account state
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct Message {
+    /// Layout version the account was written with
+    pub version: u8,
+
     /// Is this message initialized
     pub is_initialized: bool,
 
     /// Associated chat account
     pub chat: Pubkey,
 
-    /// Message sender
+    /// Message sender, or their commitment when `show_sender` is false
     pub sender: Pubkey,
 
+    /// Whether clients should attribute the message to its sender. This is a
+    /// display preference, not anonymity: the sender signed the transaction
+    /// and the chat's per-participant counters record who sent it.
+    pub show_sender: bool,
+
     /// Message index in the chat
     pub index: u64,
 
-    /// Message timestamp
+    /// Message timestamp (cluster clock when the message was received)
     pub timestamp: i64,
 
+    /// Send time reported by the client (0 = not reported)
+    pub client_timestamp: i64,
+
+    /// Client-chosen dedup value from SendMessage (zero = none)
+    pub client_nonce: [u8; 16],
+
+    /// Set when `client_timestamp` drifted too far from `timestamp`
+    pub clock_drift_flag: bool,
+
     /// Expiration timestamp (0 = never expires)
     pub expires_at: i64,
 
+    /// Timestamp from which clients should show the message (0 = immediately)
+    pub visible_at: i64,
+
+    /// Timestamp of the last edit (0 = never edited)
+    pub edited_at: i64,
+
+    /// Tombstone left by SoftDeleteMessage; the ciphertext has been wiped
+    pub deleted: bool,
+
+    /// Recipient's ed25519 signature over `ack_payload` (zero = not acknowledged)
+    pub ack_signature: [u8; 64],
+
+    /// Highlighted by a participant, counted in the chat's `pinned_count`
+    pub pinned: bool,
+
+    /// Reaction count per palette slot
+    pub reactions: [u32; REACTION_SLOTS],
+
+    /// Nonce of participant1's last counted React (zero = none), so a retry is ignored
+    pub participant1_last_reaction_nonce: u64,
+
+    /// Nonce of participant2's last counted React (zero = none)
+    pub participant2_last_reaction_nonce: u64,
+
+    /// Index of the message this one replies to (None = top level)
+    pub reply_to: Option<u64>,
+
+    /// Message account this one was forwarded from (None = original message)
+    pub forwarded_from: Option<Pubkey>,
+
     /// Ephemeral public key for this message
     pub ephemeral_public_key: [u8; 32],
 
@@ -76,21 +472,361 @@ pub struct Message {
 }
 
 impl Message {
+    /// Current account layout version
+    pub const VERSION: u8 = 7;
+
     /// Calculate the space needed for a message with given data size
-    pub fn space(data_size: usize) -> usize {
+    pub fn space(data_size: usize, is_reply: bool, is_forward: bool) -> usize {
+        1 +  // version
         1 +  // is_initialized
         32 + // chat
         32 + // sender
+        1 +  // show_sender
         8 +  // index
         8 +  // timestamp
+        8 +  // client_timestamp
+        16 + // client_nonce
+        1 +  // clock_drift_flag
         8 +  // expires_at
+        8 +  // visible_at
+        8 +  // edited_at
+        1 +  // deleted
+        64 + // ack_signature
+        1 +  // pinned
+        4 * REACTION_SLOTS + // reactions
+        8 +  // participant1_last_reaction_nonce
+        8 +  // participant2_last_reaction_nonce
+        (if is_reply { 1 + 8 } else { 1 }) + // reply_to (option tag + index)
+        (if is_forward { 1 + 32 } else { 1 }) + // forwarded_from (option tag + key)
         32 + // ephemeral_public_key
         4 + data_size // encrypted_data (vec has 4 byte length prefix)
     }
 
+    /// Reject accounts written by a newer program than this one
+    pub fn check_version(&self) -> ProgramResult {
+        if self.version > Self::VERSION {
+            return Err(WhisperChainError::UnsupportedVersion.into());
+        }
+        Ok(())
+    }
+
+    /// Decode a live message account, rejecting closed (zeroed) and truncated ones
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let message: Self = unpack_prefix(data, Self::space(0, false, false))?;
+        message.check_version()?;
+        if !message.is_initialized {
+            return Err(WhisperChainError::NotInitialized.into());
+        }
+        Ok(message)
+    }
+
     pub fn is_expired(&self, current_timestamp: i64) -> bool {
         self.expires_at > 0 && current_timestamp >= self.expires_at
     }
+
+    /// Stand-in for the sender key on unattributed messages
+    ///
+    /// Built only from public values, so anyone can recompute it for each
+    /// participant and learn the sender; it keeps the key out of the account
+    /// layout, it does not hide who sent the message.
+    pub fn sender_commitment(sender: &Pubkey, chat: &Pubkey, index: u64) -> Pubkey {
+        let digest = hashv(&[b"sender", sender.as_ref(), chat.as_ref(), &index.to_le_bytes()]);
+        Pubkey::new_from_array(digest.to_bytes())
+    }
+
+    /// Whether `pubkey` sent this message, whether or not it is attributed
+    pub fn is_sent_by(&self, pubkey: &Pubkey) -> bool {
+        if self.show_sender {
+            self.sender == *pubkey
+        } else {
+            self.sender == Self::sender_commitment(pubkey, &self.chat, self.index)
+        }
+    }
+
+    pub fn is_acknowledged(&self) -> bool {
+        self.ack_signature != [0u8; 64]
+    }
+
+    /// Bytes a recipient signs to acknowledge this message: chat, index and
+    /// the SHA-256 of the ciphertext
+    pub fn ack_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(32 + 8 + 32);
+        payload.extend_from_slice(self.chat.as_ref());
+        payload.extend_from_slice(&self.index.to_le_bytes());
+        payload.extend_from_slice(hash(&self.encrypted_data).as_ref());
+        payload
+    }
+
+    /// Scheduled messages stay hidden until their `visible_at` time
+    pub fn is_visible(&self, current_timestamp: i64) -> bool {
+        current_timestamp >= self.visible_at
+    }
+}
+
+/// Every `Message` field before the ciphertext, plus the ciphertext length.
+///
+/// Must list the same fields in the same order as `Message`, so it decodes
+/// from the front of a message account.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct MessageHeader {
+    pub version: u8,
+    pub is_initialized: bool,
+    pub chat: Pubkey,
+    pub sender: Pubkey,
+    pub show_sender: bool,
+    pub index: u64,
+    pub timestamp: i64,
+    pub client_timestamp: i64,
+    pub client_nonce: [u8; 16],
+    pub clock_drift_flag: bool,
+    pub expires_at: i64,
+    pub visible_at: i64,
+    pub edited_at: i64,
+    pub deleted: bool,
+    pub ack_signature: [u8; 64],
+    pub pinned: bool,
+    pub reactions: [u32; REACTION_SLOTS],
+    pub participant1_last_reaction_nonce: u64,
+    pub participant2_last_reaction_nonce: u64,
+    pub reply_to: Option<u64>,
+    pub forwarded_from: Option<Pubkey>,
+    pub ephemeral_public_key: [u8; 32],
+    /// Length prefix of `encrypted_data`
+    pub encrypted_data_len: u32,
+}
+
+/// Chat summary GetChatInfo hands to calling programs as return data
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ChatInfo {
+    pub message_count: u64,
+    pub participant1: Pubkey,
+    pub participant2: Pubkey,
+    pub last_message_at: i64,
+}
+
+impl ChatInfo {
+    pub const LEN: usize = 8 + // message_count
+        32 + // participant1
+        32 + // participant2
+        8; // last_message_at
+}
+
+const _: () = assert!(ChatInfo::LEN <= MAX_RETURN_DATA);
+
+impl From<&Chat> for ChatInfo {
+    fn from(chat: &Chat) -> Self {
+        Self {
+            message_count: chat.message_count,
+            participant1: chat.participant1,
+            participant2: chat.participant2,
+            last_message_at: chat.last_message_at,
+        }
+    }
+}
+
+/// Message account a send created, returned by SendMessage as return data
+///
+/// Saves the caller from re-deriving the PDA from a `message_count` that
+/// another send may already have moved past.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct SentMessage {
+    pub message: Pubkey,
+    pub index: u64,
+    pub bump: u8,
+}
+
+impl SentMessage {
+    pub const LEN: usize = 32 + // message
+        8 + // index
+        1; // bump
+}
+
+const _: () = assert!(SentMessage::LEN <= MAX_RETURN_DATA);
+
+/// Outcome of a SendMessageBatch, returned as return data
+///
+/// `next_index` is the chat's `message_count` after the batch, so the client
+/// can confirm the sequence without re-fetching the chat.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct BatchSent {
+    pub next_index: u64,
+    pub count: u64,
+}
+
+impl BatchSent {
+    pub const LEN: usize = 8 + // next_index
+        8; // count
+}
+
+const _: () = assert!(BatchSent::LEN <= MAX_RETURN_DATA);
+
+/// Message parked because its chat was allocated but never initialized.
+///
+/// One per sender and chat, at `[b"dead_letter", sender, chat]`. The sender
+/// can read it back, retry the send once the chat exists, and close it
+/// with ClearDeadLetter.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+    /// Layout version the account was written with
+    pub version: u8,
+
+    /// Is this dead letter initialized
+    pub is_initialized: bool,
+
+    /// Sender that paid for the account
+    pub sender: Pubkey,
+
+    /// Chat the message was addressed to
+    pub chat: Pubkey,
+
+    /// When the send was attempted
+    pub created_at: i64,
+
+    /// Ephemeral public key the data was encrypted with
+    pub ephemeral_public_key: [u8; 32],
+
+    /// Encrypted message data
+    pub encrypted_data: Vec<u8>,
+}
+
+impl DeadLetter {
+    /// Current account layout version
+    pub const VERSION: u8 = 1;
+
+    pub fn space(data_size: usize) -> usize {
+        1 +  // version
+        1 +  // is_initialized
+        32 + // sender
+        32 + // chat
+        8 +  // created_at
+        32 + // ephemeral_public_key
+        4 + data_size // encrypted_data (vec has 4 byte length prefix)
+    }
+
+    /// Reject accounts written by a newer program than this one
+    pub fn check_version(&self) -> ProgramResult {
+        if self.version > Self::VERSION {
+            return Err(WhisperChainError::UnsupportedVersion.into());
+        }
+        Ok(())
+    }
+
+    /// Decode a live dead letter account, rejecting closed (zeroed) and truncated ones
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let dead_letter: Self = unpack_prefix(data, Self::space(0))?;
+        dead_letter.check_version()?;
+        if !dead_letter.is_initialized {
+            return Err(WhisperChainError::NotInitialized.into());
+        }
+        Ok(dead_letter)
+    }
+}
+
+/// Program-wide settings, a singleton at `[b"config"]`.
+///
+/// Until InitConfig runs the program behaves as if it is not paused.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Layout version the account was written with
+    pub version: u8,
+
+    /// Is this config initialized
+    pub is_initialized: bool,
+
+    /// Key allowed to pause and unpause the program
+    pub admin: Pubkey,
+
+    /// While set, new chats and messages are refused; deletes still work
+    pub paused: bool,
+
+    /// Charged to the sender of every new message (0 = no fee)
+    pub fee_lamports: u64,
+
+    /// Account the send fee is paid to
+    pub treasury: Pubkey,
+}
+
+impl Config {
+    /// Current account layout version
+    pub const VERSION: u8 = 1;
+
+    pub const LEN: usize = 1 + // version
+        1 + // is_initialized
+        32 + // admin
+        1 + // paused
+        8 + // fee_lamports
+        32; // treasury
+
+    /// Reject accounts written by a newer program than this one
+    pub fn check_version(&self) -> ProgramResult {
+        if self.version > Self::VERSION {
+            return Err(WhisperChainError::UnsupportedVersion.into());
+        }
+        Ok(())
+    }
+
+    /// Decode a live config account, rejecting closed (zeroed) and truncated ones
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let config: Self = unpack_prefix(data, Self::LEN)?;
+        config.check_version()?;
+        if !config.is_initialized {
+            return Err(WhisperChainError::NotInitialized.into());
+        }
+        Ok(config)
+    }
+}
+
+/// Program-wide counters at PDA `[b"stats"]`, created by the first
+/// InitializeChat or SendMessage that passes it
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// Layout version the account was written with
+    pub version: u8,
+
+    /// Is this account initialized
+    pub is_initialized: bool,
+
+    /// Chats created while the account was passed
+    pub total_chats: u64,
+
+    /// Messages sent while the account was passed
+    pub total_messages: u64,
+}
+
+impl Stats {
+    /// Current account layout version
+    pub const VERSION: u8 = 1;
+
+    pub const LEN: usize = 1 + // version
+        1 + // is_initialized
+        8 + // total_chats
+        8; // total_messages
+
+    /// Reject accounts written by a newer program than this one
+    pub fn check_version(&self) -> ProgramResult {
+        if self.version > Self::VERSION {
+            return Err(WhisperChainError::UnsupportedVersion.into());
+        }
+        Ok(())
+    }
+
+    /// Decode a live stats account, rejecting closed (zeroed) and truncated ones
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let stats: Self = unpack_prefix(data, Self::LEN)?;
+        stats.check_version()?;
+        if !stats.is_initialized {
+            return Err(WhisperChainError::NotInitialized.into());
+        }
+        Ok(stats)
+    }
+}
+
+/// Decode the start of an account at least `min_len` bytes long
+fn unpack_prefix<T: BorshDeserialize>(data: &[u8], min_len: usize) -> Result<T, ProgramError> {
+    if data.len() < min_len {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    T::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)
 }
 
 #[cfg(test)]
@@ -100,37 +836,282 @@ mod tests {
     #[test]
     fn test_chat_len() {
         let chat = Chat {
+            version: Chat::VERSION,
             is_initialized: true,
             participant1: Pubkey::default(),
             participant2: Pubkey::default(),
-            participant1_public_key: [0u8; 32],
-            participant2_public_key: [0u8; 32],
+            participant1_public_key: [1u8; 32],
+            participant2_public_key: [2u8; 32],
             created_at: 0,
             message_count: 0,
+            live_message_count: 0,
+            pinned_count: 0,
             last_message_at: 0,
+            participant1_read_cursor: 0,
+            participant2_read_cursor: 0,
+            participant1_delivered_cursor: 0,
+            participant2_delivered_cursor: 0,
+            participant1_sent_count: 0,
+            participant2_sent_count: 0,
+            participant1_last_sent_at: 0,
+            participant2_last_sent_at: 0,
+            participant1_blocked_other: false,
+            participant2_blocked_other: false,
+            key_version: 0,
+            last_rotation_at: 0,
+            revoked_keys: [[0u8; 32]; MAX_REVOKED_KEYS],
+            revoked_keys_next: 0,
+            recent_nonces: [[0u8; 16]; MAX_RECENT_NONCES],
+            recent_nonces_next: 0,
+            config: ChatConfig::default(),
+            merkle_root: [0u8; 32],
         };
 
         let serialized = chat.try_to_vec().unwrap();
         assert_eq!(serialized.len(), Chat::LEN);
+
+        let keys = &serialized[Chat::PUBLIC_KEYS_RANGE];
+        assert_eq!(keys[..32], chat.participant1_public_key);
+        assert_eq!(keys[32..], chat.participant2_public_key);
     }
 
     #[test]
     fn test_message_space() {
         let data_size = 256;
-        let space = Message::space(data_size);
+        let space = Message::space(data_size, false, false);
 
         let message = Message {
+            version: Message::VERSION,
             is_initialized: true,
             chat: Pubkey::default(),
             sender: Pubkey::default(),
+            show_sender: true,
             index: 0,
             timestamp: 0,
+            client_timestamp: 0,
+            client_nonce: [0u8; 16],
+            clock_drift_flag: false,
             expires_at: 0,
+            visible_at: 0,
+            edited_at: 0,
+            deleted: false,
+            ack_signature: [0u8; 64],
+            pinned: false,
+            reactions: [0; REACTION_SLOTS],
+            participant1_last_reaction_nonce: 0,
+            participant2_last_reaction_nonce: 0,
+            reply_to: None,
+            forwarded_from: None,
             ephemeral_public_key: [0u8; 32],
             encrypted_data: vec![0u8; data_size],
         };
 
         let serialized = message.try_to_vec().unwrap();
         assert_eq!(serialized.len(), space);
+
+        let reply = Message { reply_to: Some(3), ..message };
+        assert_eq!(reply.try_to_vec().unwrap().len(), Message::space(data_size, true, false));
+
+        let forward = Message { forwarded_from: Some(Pubkey::new_unique()), ..reply };
+        assert_eq!(forward.try_to_vec().unwrap().len(), Message::space(data_size, true, true));
+    }
+
+    #[test]
+    fn test_allowed_sizes() {
+        assert!(ChatConfig::default().allows_size(77));
+
+        let config = ChatConfig {
+            allowed_sizes: [128, 0, 256, 0],
+            ..ChatConfig::default()
+        };
+        assert!(config.allows_size(128));
+        assert!(config.allows_size(256));
+        assert!(!config.allows_size(0));
+        assert!(!config.allows_size(200));
+    }
+
+    #[test]
+    fn test_clock_drift() {
+        let config = ChatConfig::default();
+        assert!(!config.is_drifted(10_000, 0));
+        assert!(!config.is_drifted(10_000, 10_000 - DEFAULT_MAX_CLOCK_DRIFT_SECS as i64));
+        assert!(config.is_drifted(10_000, 10_001 + DEFAULT_MAX_CLOCK_DRIFT_SECS as i64));
+
+        let strict = ChatConfig {
+            max_clock_drift_secs: 10,
+            ..ChatConfig::default()
+        };
+        assert!(strict.is_drifted(10_000, 9_989));
+    }
+
+    #[test]
+    fn test_effective_expiry() {
+        let config = ChatConfig::default();
+        assert_eq!(config.effective_expiry(10_000, 0), 0);
+
+        let ephemeral = ChatConfig {
+            default_ttl_secs: 60,
+            ..ChatConfig::default()
+        };
+        assert_eq!(ephemeral.effective_expiry(10_000, 0), 10_060);
+        assert_eq!(ephemeral.effective_expiry(10_000, 20_000), 20_000);
+    }
+
+    #[test]
+    fn test_try_join_only_once() {
+        let mut chat = Chat::try_from_slice(&[0u8; Chat::LEN]).unwrap();
+        let owner = Pubkey::new_unique();
+        let joiner = Pubkey::new_unique();
+        chat.participant1 = owner;
+
+        // The owner writing notes to an open chat doesn't fill the seat
+        assert!(!chat.try_join(&owner, [1u8; 32]));
+        assert_eq!(chat.participant2, Pubkey::default());
+
+        assert!(chat.try_join(&joiner, [2u8; 32]));
+        assert_eq!(chat.participant2, joiner);
+        assert_eq!(chat.participant2_public_key, [2u8; 32]);
+
+        // Later messages, from either side, are plain sends
+        assert!(!chat.try_join(&joiner, [3u8; 32]));
+        assert!(!chat.try_join(&Pubkey::new_unique(), [4u8; 32]));
+        assert_eq!(chat.participant2_public_key, [2u8; 32]);
+    }
+
+    #[test]
+    fn test_revoked_keys_ring() {
+        let mut chat = Chat::try_from_slice(&[0u8; Chat::LEN]).unwrap();
+        assert!(!chat.is_key_revoked(&[0u8; 32]));
+
+        for key in 1..=MAX_REVOKED_KEYS as u8 {
+            chat.revoke_key([key; 32]);
+        }
+        // Revoking a key twice doesn't use up a slot
+        chat.revoke_key([1u8; 32]);
+        assert!(chat.is_key_revoked(&[1u8; 32]));
+
+        chat.revoke_key([9u8; 32]);
+        assert!(chat.is_key_revoked(&[9u8; 32]));
+        assert!(!chat.is_key_revoked(&[1u8; 32]));
+        assert!(chat.is_key_revoked(&[2u8; 32]));
+    }
+
+    #[test]
+    fn test_recent_nonces_ring() {
+        let mut chat = Chat::try_from_slice(&[0u8; Chat::LEN]).unwrap();
+
+        // The zero nonce opts out and is never remembered
+        chat.record_nonce([0u8; 16]);
+        assert!(!chat.has_recent_nonce(&[0u8; 16]));
+        assert_eq!(chat.recent_nonces_next, 0);
+
+        for nonce in 1..=MAX_RECENT_NONCES as u8 {
+            chat.record_nonce([nonce; 16]);
+        }
+        assert!(chat.has_recent_nonce(&[1u8; 16]));
+
+        chat.record_nonce([99u8; 16]);
+        assert!(chat.has_recent_nonce(&[99u8; 16]));
+        assert!(!chat.has_recent_nonce(&[1u8; 16]));
+        assert!(chat.has_recent_nonce(&[2u8; 16]));
+    }
+
+    #[test]
+    fn test_state_digest() {
+        let mut data = [0u8; Chat::LEN];
+        data[0] = Chat::VERSION;
+        data[1] = 1;
+        let mut chat = Chat::unpack(&data).unwrap();
+        chat.participant1 = Pubkey::new_unique();
+        chat.participant2 = Pubkey::new_unique();
+        chat.message_count = 5;
+        chat.created_at = 1_700_000_000;
+        chat.last_message_at = 1_700_000_500;
+
+        // Fields outside the digest don't affect it
+        let mut same = chat.clone();
+        same.live_message_count = 2;
+        same.merkle_root = [9u8; 32];
+        assert_eq!(chat.state_digest(), same.state_digest());
+
+        let mut more = chat.clone();
+        more.message_count = 6;
+        assert_ne!(chat.state_digest(), more.state_digest());
+    }
+
+    #[test]
+    fn test_unpack() {
+        let mut data = [0u8; Chat::LEN];
+        assert_eq!(
+            Chat::unpack(&data).unwrap_err(),
+            WhisperChainError::NotInitialized.into()
+        );
+        assert_eq!(
+            Chat::unpack(&data[..Chat::LEN - 1]).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+
+        data[0] = Chat::VERSION;
+        data[1] = 1;
+        let chat = Chat::unpack(&data).unwrap();
+        assert!(chat.is_initialized);
+
+        let mut data = vec![0u8; Message::space(3, false, false)];
+        assert_eq!(
+            Message::unpack(&data).unwrap_err(),
+            WhisperChainError::NotInitialized.into()
+        );
+        assert_eq!(
+            Message::unpack(&data[..Message::space(0, false, false) - 1]).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+
+        data[0] = Message::VERSION;
+        data[1] = 1;
+        let len_offset = Message::space(0, false, false) - 4;
+        data[len_offset] = 3;
+        data[len_offset + 4..].copy_from_slice(&[7, 8, 9]);
+        let message = Message::unpack(&data).unwrap();
+        assert_eq!(message.encrypted_data, vec![7, 8, 9]);
+
+        // A length prefix running past the buffer is truncation too
+        data[len_offset] = 4;
+        assert_eq!(
+            Message::unpack(&data).unwrap_err(),
+            ProgramError::InvalidAccountData
+        );
+
+        let mut data = vec![0u8; DeadLetter::space(0)];
+        assert_eq!(
+            DeadLetter::unpack(&data).unwrap_err(),
+            WhisperChainError::NotInitialized.into()
+        );
+        data[0] = DeadLetter::VERSION;
+        data[1] = 1;
+        assert!(DeadLetter::unpack(&data).is_ok());
+    }
+
+    #[test]
+    fn test_version_check() {
+        let mut data = [0u8; Chat::LEN];
+        data[0] = Chat::VERSION;
+        let chat = Chat::try_from_slice(&data).unwrap();
+        assert_eq!(chat.version, Chat::VERSION);
+        assert!(chat.check_version().is_ok());
+
+        data[0] = Chat::VERSION + 1;
+        let forged = Chat::try_from_slice(&data).unwrap();
+        assert_eq!(
+            forged.check_version(),
+            Err(WhisperChainError::UnsupportedVersion.into())
+        );
+
+        let mut data = vec![0u8; Message::space(0, false, false)];
+        data[0] = Message::VERSION + 1;
+        let forged = Message::try_from_slice(&data).unwrap();
+        assert_eq!(
+            forged.check_version(),
+            Err(WhisperChainError::UnsupportedVersion.into())
+        );
     }
 }
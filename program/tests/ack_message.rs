@@ -0,0 +1,64 @@
+mod common;
+
+use common::*;
+use solana_program::pubkey::Pubkey;
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use whisperchain::error::WhisperChainError;
+
+/// Chat where alice sent message 0 and bob has joined with message 1
+async fn chat_with_message(
+    context: &mut ProgramTestContext,
+    program_id: &Pubkey,
+) -> (Keypair, Keypair, Pubkey) {
+    let (alice, chat) = create_chat(context, program_id).await;
+    let bob = funded_keypair(context).await;
+
+    let send = send_message_ix(program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(context, &[send], &[&alice]).await.unwrap();
+    let join = send_message_ix(program_id, &bob.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    process(context, &[join], &[&bob]).await.unwrap();
+
+    (alice, bob, chat)
+}
+
+#[tokio::test]
+async fn recipient_acknowledges_with_signature() {
+    let (mut context, program_id) = start().await;
+    let (_alice, bob, chat) = chat_with_message(&mut context, &program_id).await;
+
+    let message = get_message(&mut context, &message_pda(&program_id, &chat, 0)).await;
+    let payload = message.ack_payload();
+    let signature: [u8; 64] = bob.sign_message(&payload).into();
+
+    let verify = ed25519_verify_ix(&bob.pubkey(), &payload, &signature);
+    let ack = ack_message_ix(&program_id, &bob.pubkey(), &chat, 0, signature);
+    process(&mut context, &[verify, ack], &[&bob]).await.unwrap();
+
+    let message = get_message(&mut context, &message_pda(&program_id, &chat, 0)).await;
+    assert!(message.is_acknowledged());
+    assert_eq!(message.ack_signature, signature);
+}
+
+#[tokio::test]
+async fn forged_ack_is_rejected() {
+    let (mut context, program_id) = start().await;
+    let (_alice, bob, chat) = chat_with_message(&mut context, &program_id).await;
+    let mallory = Keypair::new();
+
+    let message = get_message(&mut context, &message_pda(&program_id, &chat, 0)).await;
+    let payload = message.ack_payload();
+
+    // Mallory's valid signature submitted as bob's ack
+    let forged: [u8; 64] = mallory.sign_message(&payload).into();
+    let verify = ed25519_verify_ix(&mallory.pubkey(), &payload, &forged);
+    let ack = ack_message_ix(&program_id, &bob.pubkey(), &chat, 0, forged);
+    let result = process(&mut context, &[verify, ack], &[&bob]).await;
+    assert_custom_error(result, WhisperChainError::InvalidPublicKey);
+
+    // No verification instruction at all
+    let signature: [u8; 64] = bob.sign_message(&payload).into();
+    let ack = ack_message_ix(&program_id, &bob.pubkey(), &chat, 0, signature);
+    let result = process(&mut context, &[ack], &[&bob]).await;
+    assert_custom_error(result, WhisperChainError::InvalidPublicKey);
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::*;
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, merkle};
+
+#[tokio::test]
+async fn archive_three_messages_and_restore_one() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    for index in 1..3 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![index as u8; 32], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+
+    let accounts: Vec<_> = (0..3).map(|index| message_pda(&program_id, &chat, index)).collect();
+    let mut messages = Vec::new();
+    for account in &accounts {
+        messages.push(get_message(&mut context, account).await);
+    }
+    let leaves: Vec<_> = messages
+        .iter()
+        .map(|message| merkle::leaf_hash(&message.try_to_vec().unwrap()))
+        .collect();
+
+    let bob_before = lamports(&mut context, &bob.pubkey()).await;
+    let bob_rent = lamports(&mut context, &accounts[0]).await;
+
+    let archive = archive_to_compressed_ix(
+        &program_id,
+        &alice.pubkey(),
+        &chat,
+        (&alice.pubkey(), &bob.pubkey()),
+        &accounts,
+    );
+    process(&mut context, &[archive], &[&alice]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.merkle_root, merkle::root(&leaves));
+    for account in &accounts {
+        assert_eq!(lamports(&mut context, account).await, 0);
+    }
+    // Bob paid for message 0, so its rent goes back to him
+    assert_eq!(lamports(&mut context, &bob.pubkey()).await, bob_before + bob_rent);
+
+    let restore = restore_from_compressed_ix(
+        &program_id,
+        &bob.pubkey(),
+        &chat,
+        messages[1].clone(),
+        merkle::proof(&leaves, 1),
+    );
+    process(&mut context, &[restore], &[&bob]).await.unwrap();
+
+    assert_eq!(get_message(&mut context, &accounts[1]).await, messages[1]);
+}
+
+#[tokio::test]
+async fn restore_rejects_tampered_message() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    for index in 0..2 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![5u8; 16], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+
+    let accounts: Vec<_> = (0..2).map(|index| message_pda(&program_id, &chat, index)).collect();
+    let mut messages = Vec::new();
+    for account in &accounts {
+        messages.push(get_message(&mut context, account).await);
+    }
+    let leaves: Vec<_> = messages
+        .iter()
+        .map(|message| merkle::leaf_hash(&message.try_to_vec().unwrap()))
+        .collect();
+
+    let state = get_chat(&mut context, &chat).await;
+    let archive = archive_to_compressed_ix(
+        &program_id,
+        &alice.pubkey(),
+        &chat,
+        (&state.participant1, &state.participant2),
+        &accounts,
+    );
+    process(&mut context, &[archive], &[&alice]).await.unwrap();
+
+    let mut forged = messages[0].clone();
+    forged.encrypted_data = vec![6u8; 16];
+    let restore = restore_from_compressed_ix(
+        &program_id,
+        &alice.pubkey(),
+        &chat,
+        forged,
+        merkle::proof(&leaves, 0),
+    );
+    let result = process(&mut context, &[restore], &[&alice]).await;
+
+    assert_custom_error(result, WhisperChainError::InvalidMerkleProof);
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::*;
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, state::ChatInfo};
+
+#[tokio::test]
+async fn chat_info_is_returned_to_the_caller() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let ix = get_chat_info_ix(&program_id, &chat);
+    let return_data = simulated_return_data(&mut context, &[ix], &[]).await.unwrap();
+    assert_eq!(return_data.program_id, program_id);
+    assert_eq!(return_data.data.len(), ChatInfo::LEN);
+
+    let state = get_chat(&mut context, &chat).await;
+    let info = ChatInfo::try_from_slice(&return_data.data).unwrap();
+    assert_eq!(
+        info,
+        ChatInfo {
+            message_count: 2,
+            participant1: alice.pubkey(),
+            participant2: bob.pubkey(),
+            last_message_at: state.last_message_at,
+        }
+    );
+}
+
+#[tokio::test]
+async fn chat_info_requires_a_program_owned_chat() {
+    let (mut context, program_id) = start().await;
+    let impostor = funded_keypair(&mut context).await;
+
+    let ix = get_chat_info_ix(&program_id, &impostor.pubkey());
+    let result = process(&mut context, &[ix], &[]).await;
+
+    assert_custom_error(result, WhisperChainError::InvalidAccountOwner);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::{dead_letter_pda, funded_keypair, get_chat, get_message, lamports, process, start};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use whisperchain::{
+    client,
+    processor::SendMessageArgs,
+    state::{ChatConfig, ChatInit},
+};
+
+fn text(data: &[u8]) -> SendMessageArgs {
+    SendMessageArgs {
+        encrypted_data: data.to_vec(),
+        ephemeral_public_key: [7u8; 32],
+        expires_at: 0,
+        visible_at: 0,
+        client_timestamp: 0,
+        client_nonce: [0u8; 16],
+        reply_to: None,
+        show_sender: true,
+    }
+}
+
+#[tokio::test]
+async fn builders_match_processor_account_order() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let init = client::initialize_chat_ix(&program_id, &alice.pubkey(), [1u8; 32], ChatConfig::default());
+    process(&mut context, &[init], &[&alice]).await.unwrap();
+    let (chat, _) = client::chat_pda(&program_id, &alice.pubkey());
+
+    let carol = Pubkey::new_unique();
+    let entries = vec![ChatInit { counterpart: carol, public_key: [2u8; 32] }];
+    let batch = client::initialize_chats_batch_ix(&program_id, &alice.pubkey(), entries);
+    process(&mut context, &[batch], &[&alice]).await.unwrap();
+    let (batch_chat, _) = client::batch_chat_pda(&program_id, &alice.pubkey(), &carol);
+    assert_eq!(get_chat(&mut context, &batch_chat).await.participant2, carol);
+
+    let join = client::send_message_ix(&program_id, &bob.pubkey(), &chat, 0, text(b"hi alice"));
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    let reply = client::send_message_ix(&program_id, &alice.pubkey(), &chat, 1, text(b"hi bob"));
+    process(&mut context, &[reply], &[&alice]).await.unwrap();
+
+    let delete = client::delete_message_ix(&program_id, &bob.pubkey(), &chat, 0, &bob.pubkey());
+    process(&mut context, &[delete], &[&bob]).await.unwrap();
+
+    let participants = (&alice.pubkey(), &bob.pubkey());
+    let delete_chat = client::delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &[1]);
+    process(&mut context, &[delete_chat], &[&alice]).await.unwrap();
+    assert_eq!(lamports(&mut context, &chat).await, 0);
+}
+
+#[tokio::test]
+async fn chat_addresses_match_processor_accounts() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+    let bob = funded_keypair(&mut context).await;
+    let addresses = client::ChatAddresses::derive(&program_id, &alice.pubkey(), &bob.pubkey());
+    let chat = addresses.chat.0;
+
+    let entries = vec![ChatInit { counterpart: bob.pubkey(), public_key: [2u8; 32] }];
+    let batch = client::initialize_chats_batch_ix(&program_id, &alice.pubkey(), entries);
+    process(&mut context, &[batch], &[&alice]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.participant2, bob.pubkey());
+
+    let join = client::send_message_ix(&program_id, &bob.pubkey(), &chat, 0, text(b"hi alice"));
+    assert_eq!(join.accounts[2].pubkey, addresses.first_message.0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    assert_eq!(get_message(&mut context, &addresses.first_message.0).await.index, 0);
+
+    assert_eq!(addresses.initializer_dead_letter.0, dead_letter_pda(&program_id, &alice.pubkey(), &chat));
+    assert_eq!(addresses.counterpart_dead_letter.0, dead_letter_pda(&program_id, &bob.pubkey(), &chat));
+
+    // A chat from InitializeChat shares the same derivations for its messages
+    let single = client::chat_pda(&program_id, &alice.pubkey());
+    let single_addresses = client::ChatAddresses::for_chat(&program_id, single, &alice.pubkey(), &bob.pubkey());
+    assert_eq!(single_addresses.first_message, client::message_pda(&program_id, &single.0, 0));
+}
@@ -0,0 +1,837 @@
+#![allow(dead_code)]
+
+use borsh::BorshSerialize;
+use solana_program::{
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    ed25519_program,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    system_instruction, system_program,
+    sysvar::{self, clock::Clock},
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transaction_context::TransactionReturnData,
+};
+use whisperchain::{
+    error::WhisperChainError,
+    instruction::WhisperChainInstruction,
+    state::{BatchEntry, Chat, ChatConfig, ChatInit, Message},
+};
+
+pub const SOL: u64 = 1_000_000_000;
+
+pub async fn start() -> (ProgramTestContext, Pubkey) {
+    let (context, program_id, _) = start_with_upgrade_authority().await;
+    (context, program_id)
+}
+
+/// Like `start`, also returning the funded keypair the program's
+/// ProgramData account names as its upgrade authority
+pub async fn start_with_upgrade_authority() -> (ProgramTestContext, Pubkey, Keypair) {
+    let program_id = Pubkey::new_unique();
+    let mut program_test = ProgramTest::new(
+        "whisperchain",
+        program_id,
+        processor!(whisperchain::process_instruction),
+    );
+    program_test.prefer_bpf(false);
+
+    let authority = Keypair::new();
+    program_test.add_account(authority.pubkey(), Account::new(10 * SOL, 0, &system_program::id()));
+    let program_data = UpgradeableLoaderState::ProgramData {
+        slot: 0,
+        upgrade_authority_address: Some(authority.pubkey()),
+    };
+    program_test.add_account(
+        program_data_pda(&program_id),
+        Account::new_data(SOL, &program_data, &bpf_loader_upgradeable::id()).unwrap(),
+    );
+
+    (program_test.start_with_context().await, program_id, authority)
+}
+
+/// Creates a new keypair funded by the test payer
+pub async fn funded_keypair(context: &mut ProgramTestContext) -> Keypair {
+    let keypair = Keypair::new();
+    let ix = system_instruction::transfer(&context.payer.pubkey(), &keypair.pubkey(), 10 * SOL);
+    let payer = context.payer.insecure_clone();
+    process(context, &[ix], &[&payer]).await.unwrap();
+    keypair
+}
+
+/// Signs with the test payer as fee payer plus every keypair in `signers`
+pub async fn process(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), BanksClientError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context.banks_client.process_transaction(transaction).await
+}
+
+/// Compute units the transaction would consume, without committing it
+pub async fn simulated_units(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> u64 {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    let simulation = context
+        .banks_client
+        .simulate_transaction(transaction)
+        .await
+        .unwrap();
+    simulation.result.unwrap().unwrap();
+    simulation.simulation_details.unwrap().units_consumed
+}
+
+/// Return data the transaction would set, without committing it
+pub async fn simulated_return_data(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Option<TransactionReturnData> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+
+    let transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    let simulation = context
+        .banks_client
+        .simulate_transaction(transaction)
+        .await
+        .unwrap();
+    simulation.result.unwrap().unwrap();
+    simulation.simulation_details.unwrap().return_data
+}
+
+pub async fn set_unix_timestamp(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp = unix_timestamp;
+    context.set_sysvar(&clock);
+}
+
+pub async fn lamports(context: &mut ProgramTestContext, pubkey: &Pubkey) -> u64 {
+    context
+        .banks_client
+        .get_balance(*pubkey)
+        .await
+        .unwrap()
+}
+
+pub fn assert_custom_error(result: Result<(), BanksClientError>, expected: WhisperChainError) {
+    assert_instruction_error(result, InstructionError::Custom(expected as u32));
+}
+
+pub fn assert_instruction_error(result: Result<(), BanksClientError>, expected: InstructionError) {
+    match result.unwrap_err().unwrap() {
+        TransactionError::InstructionError(_, err) => assert_eq!(err, expected),
+        other => panic!("unexpected transaction error: {:?}", other),
+    }
+}
+
+pub async fn get_chat(context: &mut ProgramTestContext, pubkey: &Pubkey) -> Chat {
+    let account = context
+        .banks_client
+        .get_account(*pubkey)
+        .await
+        .unwrap()
+        .expect("chat account exists");
+    Chat::unpack(&account.data).unwrap()
+}
+
+pub async fn get_message(context: &mut ProgramTestContext, pubkey: &Pubkey) -> Message {
+    let account = context
+        .banks_client
+        .get_account(*pubkey)
+        .await
+        .unwrap()
+        .expect("message account exists");
+    Message::unpack(&account.data).unwrap()
+}
+
+pub fn chat_pda(program_id: &Pubkey, initializer: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"chat", initializer.as_ref()], program_id).0
+}
+
+pub fn batch_chat_pda(program_id: &Pubkey, initializer: &Pubkey, counterpart: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"chat", initializer.as_ref(), counterpart.as_ref()],
+        program_id,
+    )
+    .0
+}
+
+pub fn message_pda(program_id: &Pubkey, chat: &Pubkey, index: u64) -> Pubkey {
+    Pubkey::find_program_address(&[b"message", chat.as_ref(), &index.to_le_bytes()], program_id).0
+}
+
+pub fn config_pda(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"config"], program_id).0
+}
+
+pub fn program_data_pda(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+pub fn pair_chat_pda(program_id: &Pubkey, a: &Pubkey, b: &Pubkey) -> Pubkey {
+    let (lower, higher) = if a <= b { (a, b) } else { (b, a) };
+    Pubkey::find_program_address(&[b"chat_pair", lower.as_ref(), higher.as_ref()], program_id).0
+}
+
+pub fn stats_pda(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"stats"], program_id).0
+}
+
+pub fn dead_letter_pda(program_id: &Pubkey, sender: &Pubkey, chat: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"dead_letter", sender.as_ref(), chat.as_ref()], program_id).0
+}
+
+fn instruction(
+    program_id: &Pubkey,
+    accounts: Vec<AccountMeta>,
+    data: WhisperChainInstruction,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: data.try_to_vec().unwrap(),
+    }
+}
+
+pub fn initialize_chat_ix(program_id: &Pubkey, initializer: &Pubkey, public_key: [u8; 32]) -> Instruction {
+    initialize_chat_with_config_ix(program_id, initializer, public_key, ChatConfig::default())
+}
+
+pub fn initialize_chat_with_config_ix(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    public_key: [u8; 32],
+    config: ChatConfig,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*initializer, true),
+            AccountMeta::new(chat_pda(program_id, initializer), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(config_pda(program_id), false),
+        ],
+        WhisperChainInstruction::InitializeChat { public_key, config },
+    )
+}
+
+pub fn initialize_chats_batch_ix(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    entries: Vec<ChatInit>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*initializer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(config_pda(program_id), false),
+    ];
+    accounts.extend(entries.iter().map(|entry| {
+        AccountMeta::new(batch_chat_pda(program_id, initializer, &entry.counterpart), false)
+    }));
+
+    instruction(
+        program_id,
+        accounts,
+        WhisperChainInstruction::InitializeChatsBatch { entries },
+    )
+}
+
+pub fn send_message_ix(
+    program_id: &Pubkey,
+    sender: &Pubkey,
+    chat: &Pubkey,
+    index: u64,
+    encrypted_data: Vec<u8>,
+    expires_at: i64,
+) -> Instruction {
+    send_instruction(
+        program_id,
+        sender,
+        chat,
+        index,
+        WhisperChainInstruction::SendMessage {
+            encrypted_data,
+            ephemeral_public_key: [7u8; 32],
+            expires_at,
+            visible_at: 0,
+            client_timestamp: 0,
+            client_nonce: [0u8; 16],
+            reply_to: None,
+            show_sender: true,
+        },
+    )
+}
+
+/// Wraps custom SendMessage data with the standard send accounts
+pub fn send_instruction(
+    program_id: &Pubkey,
+    sender: &Pubkey,
+    chat: &Pubkey,
+    index: u64,
+    data: WhisperChainInstruction,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*sender, true),
+            AccountMeta::new(*chat, false),
+            AccountMeta::new(message_pda(program_id, chat, index), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(config_pda(program_id), false),
+        ],
+        data,
+    )
+}
+
+pub fn edit_message_ix(
+    program_id: &Pubkey,
+    sender: &Pubkey,
+    message: &Pubkey,
+    chat: &Pubkey,
+    new_encrypted_data: Vec<u8>,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*sender, true),
+            AccountMeta::new(*message, false),
+            AccountMeta::new_readonly(*chat, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        WhisperChainInstruction::EditMessage {
+            new_encrypted_data,
+            new_ephemeral_public_key: [9u8; 32],
+        },
+    )
+}
+
+pub fn mark_read_ix(program_id: &Pubkey, participant: &Pubkey, chat: &Pubkey, up_to_index: u64) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*participant, true),
+            AccountMeta::new(*chat, false),
+        ],
+        WhisperChainInstruction::MarkRead { up_to_index },
+    )
+}
+
+pub fn mark_delivered_ix(program_id: &Pubkey, participant: &Pubkey, chat: &Pubkey, up_to_index: u64) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*participant, true),
+            AccountMeta::new(*chat, false),
+        ],
+        WhisperChainInstruction::MarkDelivered { up_to_index },
+    )
+}
+
+pub fn rotate_key_ix(program_id: &Pubkey, participant: &Pubkey, chat: &Pubkey, new_public_key: [u8; 32]) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*participant, true),
+            AccountMeta::new(*chat, false),
+        ],
+        WhisperChainInstruction::RotateKey { new_public_key },
+    )
+}
+
+pub fn revoke_ephemeral_key_ix(program_id: &Pubkey, participant: &Pubkey, chat: &Pubkey, key: [u8; 32]) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*participant, true),
+            AccountMeta::new(*chat, false),
+        ],
+        WhisperChainInstruction::RevokeEphemeralKey { key },
+    )
+}
+
+pub fn ack_message_ix(
+    program_id: &Pubkey,
+    recipient: &Pubkey,
+    chat: &Pubkey,
+    index: u64,
+    signature: [u8; 64],
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*recipient, true),
+            AccountMeta::new_readonly(*chat, false),
+            AccountMeta::new(message_pda(program_id, chat, index), false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ],
+        WhisperChainInstruction::AckMessage { index, signature },
+    )
+}
+
+/// Ed25519 program instruction checking `signature` by `pubkey` over
+/// `message`, laid out like `new_ed25519_instruction` with data inline
+pub fn ed25519_verify_ix(pubkey: &Pubkey, message: &[u8], signature: &[u8; 64]) -> Instruction {
+    let public_key_offset: u16 = 16;
+    let signature_offset = public_key_offset + 32;
+    let message_offset = signature_offset + 64;
+
+    let mut data = vec![1u8, 0];
+    for field in [
+        signature_offset,
+        u16::MAX,
+        public_key_offset,
+        u16::MAX,
+        message_offset,
+        message.len() as u16,
+        u16::MAX,
+    ] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(pubkey.as_ref());
+    data.extend_from_slice(signature);
+    data.extend_from_slice(message);
+
+    Instruction {
+        program_id: ed25519_program::id(),
+        accounts: vec![],
+        data,
+    }
+}
+
+pub fn clear_dead_letter_ix(program_id: &Pubkey, sender: &Pubkey, chat: &Pubkey) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*sender, true),
+            AccountMeta::new(dead_letter_pda(program_id, sender, chat), false),
+        ],
+        WhisperChainInstruction::ClearDeadLetter,
+    )
+}
+
+pub fn resync_chat_ix(program_id: &Pubkey, participant: &Pubkey, chat: &Pubkey, messages: &[Pubkey]) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*participant, true),
+        AccountMeta::new(*chat, false),
+    ];
+    accounts.extend(messages.iter().map(|message| AccountMeta::new_readonly(*message, false)));
+
+    instruction(program_id, accounts, WhisperChainInstruction::ResyncChat)
+}
+
+pub fn set_blocked_ix(program_id: &Pubkey, participant: &Pubkey, chat: &Pubkey, target: &Pubkey, blocked: bool) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*participant, true),
+            AccountMeta::new(*chat, false),
+        ],
+        WhisperChainInstruction::SetBlocked {
+            target: *target,
+            blocked,
+        },
+    )
+}
+
+pub fn rekey_and_resend_ix(
+    program_id: &Pubkey,
+    participant: &Pubkey,
+    chat: &Pubkey,
+    message: &Pubkey,
+    new_index: u64,
+    new_public_key: [u8; 32],
+    new_encrypted_data: Vec<u8>,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*participant, true),
+            AccountMeta::new(*chat, false),
+            AccountMeta::new(*message, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(message_pda(program_id, chat, new_index), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(config_pda(program_id), false),
+        ],
+        WhisperChainInstruction::RekeyAndResend {
+            new_public_key,
+            new_encrypted_data,
+            new_ephemeral_public_key: [8u8; 32],
+        },
+    )
+}
+
+pub fn forward_message_ix(
+    program_id: &Pubkey,
+    sender: &Pubkey,
+    source_chat: &Pubkey,
+    source_message: &Pubkey,
+    chat: &Pubkey,
+    index: u64,
+    new_encrypted_data: Vec<u8>,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*sender, true),
+            AccountMeta::new_readonly(*source_chat, false),
+            AccountMeta::new_readonly(*source_message, false),
+            AccountMeta::new(*chat, false),
+            AccountMeta::new(message_pda(program_id, chat, index), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new_readonly(config_pda(program_id), false),
+        ],
+        WhisperChainInstruction::ForwardMessage {
+            new_encrypted_data,
+            new_ephemeral_public_key: [8u8; 32],
+        },
+    )
+}
+
+pub fn set_pinned_ix(
+    program_id: &Pubkey,
+    participant: &Pubkey,
+    chat: &Pubkey,
+    message: &Pubkey,
+    pinned: bool,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*participant, true),
+            AccountMeta::new(*chat, false),
+            AccountMeta::new(*message, false),
+        ],
+        WhisperChainInstruction::SetPinned { pinned },
+    )
+}
+
+pub fn react_ix(
+    program_id: &Pubkey,
+    participant: &Pubkey,
+    chat: &Pubkey,
+    message: &Pubkey,
+    slot: u8,
+    add: bool,
+    nonce: u64,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*participant, true),
+            AccountMeta::new_readonly(*chat, false),
+            AccountMeta::new(*message, false),
+        ],
+        WhisperChainInstruction::React { slot, add, nonce },
+    )
+}
+
+pub fn get_chat_info_ix(program_id: &Pubkey, chat: &Pubkey) -> Instruction {
+    instruction(
+        program_id,
+        vec![AccountMeta::new_readonly(*chat, false)],
+        WhisperChainInstruction::GetChatInfo,
+    )
+}
+
+/// `first_index` must be the chat's current `message_count`
+pub fn send_message_batch_ix(
+    program_id: &Pubkey,
+    sender: &Pubkey,
+    chat: &Pubkey,
+    first_index: u64,
+    messages: Vec<BatchEntry>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*sender, true),
+        AccountMeta::new(*chat, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::clock::id(), false),
+        AccountMeta::new_readonly(config_pda(program_id), false),
+    ];
+    accounts.extend(
+        (first_index..first_index + messages.len() as u64)
+            .map(|index| AccountMeta::new(message_pda(program_id, chat, index), false)),
+    );
+
+    instruction(
+        program_id,
+        accounts,
+        WhisperChainInstruction::SendMessageBatch { messages },
+    )
+}
+
+pub fn transfer_participant_ix(
+    program_id: &Pubkey,
+    participant: &Pubkey,
+    chat: &Pubkey,
+    new_pubkey: &Pubkey,
+    new_public_key: [u8; 32],
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*participant, true),
+            AccountMeta::new(*chat, false),
+        ],
+        WhisperChainInstruction::TransferParticipant {
+            new_pubkey: *new_pubkey,
+            new_public_key,
+        },
+    )
+}
+
+pub fn ping_ix(program_id: &Pubkey) -> Instruction {
+    instruction(program_id, vec![], WhisperChainInstruction::Ping)
+}
+
+pub fn init_config_ix(program_id: &Pubkey, admin: &Pubkey) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(config_pda(program_id), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(program_data_pda(program_id), false),
+        ],
+        WhisperChainInstruction::InitConfig,
+    )
+}
+
+pub fn set_paused_ix(program_id: &Pubkey, admin: &Pubkey, paused: bool) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(config_pda(program_id), false),
+        ],
+        WhisperChainInstruction::SetPaused { paused },
+    )
+}
+
+pub fn soft_delete_message_ix(
+    program_id: &Pubkey,
+    sender: &Pubkey,
+    message: &Pubkey,
+    chat: &Pubkey,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*sender, true),
+            AccountMeta::new(*message, false),
+            AccountMeta::new_readonly(*chat, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        WhisperChainInstruction::SoftDeleteMessage,
+    )
+}
+
+pub fn initialize_chat_pair_ix(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    other: &Pubkey,
+    public_key: [u8; 32],
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*initializer, true),
+            AccountMeta::new(pair_chat_pda(program_id, initializer, other), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(config_pda(program_id), false),
+        ],
+        WhisperChainInstruction::InitializeChatPair {
+            other: *other,
+            public_key,
+        },
+    )
+}
+
+pub fn set_fee_ix(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    fee_lamports: u64,
+    treasury: &Pubkey,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(config_pda(program_id), false),
+        ],
+        WhisperChainInstruction::SetFee {
+            fee_lamports,
+            treasury: *treasury,
+        },
+    )
+}
+
+pub fn delete_chat_ix(
+    program_id: &Pubkey,
+    participant: &Pubkey,
+    chat: &Pubkey,
+    participants: (&Pubkey, &Pubkey),
+    messages: &[Pubkey],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*participant, true),
+        AccountMeta::new(*chat, false),
+        AccountMeta::new(*participants.0, false),
+        AccountMeta::new(*participants.1, false),
+    ];
+    accounts.extend(messages.iter().map(|message| AccountMeta::new(*message, false)));
+
+    instruction(program_id, accounts, WhisperChainInstruction::DeleteChat)
+}
+
+pub fn archive_chat_ix(
+    program_id: &Pubkey,
+    participant: &Pubkey,
+    chat: &Pubkey,
+    participants: (&Pubkey, &Pubkey),
+    messages: &[Pubkey],
+) -> Instruction {
+    let mut ix = delete_chat_ix(program_id, participant, chat, participants, messages);
+    ix.data = WhisperChainInstruction::ArchiveChat.try_to_vec().unwrap();
+    ix
+}
+
+pub fn close_messages_ix(
+    program_id: &Pubkey,
+    participant: &Pubkey,
+    chat: &Pubkey,
+    participants: (&Pubkey, &Pubkey),
+    messages: &[Pubkey],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*participant, true),
+        AccountMeta::new(*chat, false),
+        AccountMeta::new(*participants.0, false),
+        AccountMeta::new(*participants.1, false),
+    ];
+    accounts.extend(messages.iter().map(|message| AccountMeta::new(*message, false)));
+
+    instruction(program_id, accounts, WhisperChainInstruction::CloseMessages)
+}
+
+pub fn delete_message_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    message: &Pubkey,
+    chat: &Pubkey,
+    refund: &Pubkey,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*message, false),
+            AccountMeta::new(*chat, false),
+            AccountMeta::new_readonly(sysvar::clock::id(), false),
+            AccountMeta::new(*refund, false),
+        ],
+        WhisperChainInstruction::DeleteMessage,
+    )
+}
+
+pub fn archive_to_compressed_ix(
+    program_id: &Pubkey,
+    participant: &Pubkey,
+    chat: &Pubkey,
+    participants: (&Pubkey, &Pubkey),
+    messages: &[Pubkey],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*participant, true),
+        AccountMeta::new(*chat, false),
+        AccountMeta::new(*participants.0, false),
+        AccountMeta::new(*participants.1, false),
+    ];
+    accounts.extend(messages.iter().map(|message| AccountMeta::new(*message, false)));
+
+    instruction(program_id, accounts, WhisperChainInstruction::ArchiveToCompressed)
+}
+
+pub fn restore_from_compressed_ix(
+    program_id: &Pubkey,
+    participant: &Pubkey,
+    chat: &Pubkey,
+    message: Message,
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    instruction(
+        program_id,
+        vec![
+            AccountMeta::new(*participant, true),
+            AccountMeta::new(*chat, false),
+            AccountMeta::new(message_pda(program_id, chat, message.index), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        WhisperChainInstruction::RestoreFromCompressed {
+            message: Box::new(message),
+            proof,
+        },
+    )
+}
+
+/// Config that lets the initializer post before a counterpart joins
+pub fn self_chat_config() -> ChatConfig {
+    ChatConfig {
+        allow_self_chat: true,
+        ..ChatConfig::default()
+    }
+}
+
+/// Initializes a self-chat enabled chat owned by a fresh funded keypair and returns both
+pub async fn create_chat(context: &mut ProgramTestContext, program_id: &Pubkey) -> (Keypair, Pubkey) {
+    create_chat_with_config(context, program_id, self_chat_config()).await
+}
+
+pub async fn create_chat_with_config(
+    context: &mut ProgramTestContext,
+    program_id: &Pubkey,
+    config: ChatConfig,
+) -> (Keypair, Pubkey) {
+    let initializer = funded_keypair(context).await;
+    let ix = initialize_chat_with_config_ix(program_id, &initializer.pubkey(), [1u8; 32], config);
+    process(context, &[ix], &[&initializer]).await.unwrap();
+
+    let chat = chat_pda(program_id, &initializer.pubkey());
+    (initializer, chat)
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::*;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use whisperchain::{client::recommended_cu, instruction::WhisperChainInstruction, state::ChatInit};
+
+async fn assert_within_estimate(
+    context: &mut ProgramTestContext,
+    ix: Instruction,
+    signers: &[&Keypair],
+) {
+    let estimate = recommended_cu(&WhisperChainInstruction::try_from_slice(&ix.data).unwrap());
+    let measured = simulated_units(context, std::slice::from_ref(&ix), signers).await;
+    assert!(
+        u64::from(estimate) > measured,
+        "estimate {} does not cover measured {}",
+        estimate,
+        measured
+    );
+    process(context, &[ix], signers).await.unwrap();
+}
+
+#[tokio::test]
+async fn estimates_cover_measured_usage() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+    let chat = chat_pda(&program_id, &alice.pubkey());
+
+    let init = initialize_chat_with_config_ix(&program_id, &alice.pubkey(), [1u8; 32], self_chat_config());
+    assert_within_estimate(&mut context, init, &[&alice]).await;
+
+    let entries = vec![
+        ChatInit { counterpart: Pubkey::new_unique(), public_key: [2u8; 32] },
+        ChatInit { counterpart: Pubkey::new_unique(), public_key: [3u8; 32] },
+    ];
+    let batch = initialize_chats_batch_ix(&program_id, &alice.pubkey(), entries);
+    assert_within_estimate(&mut context, batch, &[&alice]).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 512], 0);
+    assert_within_estimate(&mut context, send, &[&alice]).await;
+
+    let message = message_pda(&program_id, &chat, 0);
+    let delete = delete_message_ix(&program_id, &alice.pubkey(), &message, &chat, &alice.pubkey());
+    assert_within_estimate(&mut context, delete, &[&alice]).await;
+
+    let participants = (&alice.pubkey(), &Pubkey::default());
+    let delete_chat = delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &[]);
+    assert_within_estimate(&mut context, delete_chat, &[&alice]).await;
+}
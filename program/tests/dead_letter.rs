@@ -0,0 +1,57 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::*;
+use solana_program::{instruction::AccountMeta, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{account::Account, signature::Signer};
+use whisperchain::{
+    error::WhisperChainError,
+    state::{Chat, DeadLetter},
+};
+
+/// A program-owned chat account that was allocated but never initialized
+fn zeroed_chat(context: &mut ProgramTestContext, program_id: &Pubkey) -> Pubkey {
+    let chat = Pubkey::new_unique();
+    let account = Account::new(SOL, Chat::LEN, program_id);
+    context.set_account(&chat, &account.into());
+    chat
+}
+
+#[tokio::test]
+async fn uninitialized_chat_is_rejected_by_default() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+    let chat = zeroed_chat(&mut context, &program_id);
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    let result = process(&mut context, &[send], &[&alice]).await;
+
+    assert_custom_error(result, WhisperChainError::NotInitialized);
+}
+
+#[tokio::test]
+async fn opted_in_send_lands_in_dead_letter() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+    let chat = zeroed_chat(&mut context, &program_id);
+    let dead_letter = dead_letter_pda(&program_id, &alice.pubkey(), &chat);
+
+    let mut send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![3u8; 16], 0);
+    send.accounts.push(AccountMeta::new(dead_letter, false));
+    process(&mut context, &[send.clone()], &[&alice]).await.unwrap();
+
+    let account = context.banks_client.get_account(dead_letter).await.unwrap().unwrap();
+    let stored = DeadLetter::try_from_slice(&account.data).unwrap();
+    assert_eq!(stored.sender, alice.pubkey());
+    assert_eq!(stored.chat, chat);
+    assert_eq!(stored.encrypted_data, vec![3u8; 16]);
+
+    // A second undelivered send waits until the first is cleared
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::AlreadyInitialized);
+
+    let clear = clear_dead_letter_ix(&program_id, &alice.pubkey(), &chat);
+    process(&mut context, &[clear], &[&alice]).await.unwrap();
+    assert_eq!(lamports(&mut context, &dead_letter).await, 0);
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use common::*;
+use solana_program::{instruction::InstructionError, pubkey::Pubkey, system_instruction};
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, state::Chat};
+
+#[tokio::test]
+async fn deleting_chat_closes_every_message() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    for index in 0..3 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![1u8; 16], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+    let messages: Vec<_> = (0..3).map(|index| message_pda(&program_id, &chat, index)).collect();
+
+    let mut reclaimed = lamports(&mut context, &chat).await;
+    for message in &messages {
+        reclaimed += lamports(&mut context, message).await;
+    }
+    let alice_before = lamports(&mut context, &alice.pubkey()).await;
+
+    let participants = (&alice.pubkey(), &Pubkey::default());
+    let delete = delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &messages);
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+
+    for account in messages.iter().chain([&chat]) {
+        assert_eq!(lamports(&mut context, account).await, 0);
+    }
+    // Fees are paid by the payer, not alice
+    assert_eq!(lamports(&mut context, &alice.pubkey()).await, alice_before + reclaimed);
+}
+
+#[tokio::test]
+async fn deleting_chat_requires_all_live_messages() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    for index in 0..3 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![1u8; 16], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+
+    // Message 1 is already gone, so only 0 and 2 need closing
+    let message = message_pda(&program_id, &chat, 1);
+    let delete = delete_message_ix(&program_id, &alice.pubkey(), &message, &chat, &alice.pubkey());
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+
+    let first = message_pda(&program_id, &chat, 0);
+    let last = message_pda(&program_id, &chat, 2);
+
+    let participants = (&alice.pubkey(), &Pubkey::default());
+    let delete = delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &[first]);
+    let result = process(&mut context, &[delete], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::NotEnoughAccountKeys);
+
+    let delete = delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &[first, last]);
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+    assert_eq!(lamports(&mut context, &chat).await, 0);
+}
+
+#[tokio::test]
+async fn large_chat_is_emptied_in_batches_before_delete() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    for index in 1..4 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![1u8; 16], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+    let messages: Vec<_> = (0..4).map(|index| message_pda(&program_id, &chat, index)).collect();
+    let participants = (&alice.pubkey(), &bob.pubkey());
+
+    let delete = delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &[]);
+    let result = process(&mut context, &[delete], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::NotEnoughAccountKeys);
+
+    let mallory = funded_keypair(&mut context).await;
+    let close = close_messages_ix(&program_id, &mallory.pubkey(), &chat, participants, &messages[..2]);
+    let result = process(&mut context, &[close], &[&mallory]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    // Bob paid for message 0, so its rent goes back to him
+    let bob_before = lamports(&mut context, &bob.pubkey()).await;
+    let bob_rent = lamports(&mut context, &messages[0]).await;
+    let close = close_messages_ix(&program_id, &alice.pubkey(), &chat, participants, &messages[..2]);
+    process(&mut context, &[close], &[&alice]).await.unwrap();
+    assert_eq!(lamports(&mut context, &bob.pubkey()).await, bob_before + bob_rent);
+    assert_eq!(get_chat(&mut context, &chat).await.live_message_count, 2);
+
+    let close = close_messages_ix(&program_id, &alice.pubkey(), &chat, participants, &messages[2..]);
+    process(&mut context, &[close], &[&alice]).await.unwrap();
+
+    let delete = delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &[]);
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+    for account in messages.iter().chain([&chat]) {
+        assert_eq!(lamports(&mut context, account).await, 0);
+    }
+}
+
+#[tokio::test]
+async fn each_side_can_close_the_others_messages() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    let bobs = message_pda(&program_id, &chat, 0);
+    let alices = message_pda(&program_id, &chat, 1);
+    let participants = (&alice.pubkey(), &bob.pubkey());
+
+    // Bob closes alice's message and the rent goes back to her
+    let alice_before = lamports(&mut context, &alice.pubkey()).await;
+    let alice_rent = lamports(&mut context, &alices).await;
+    let close = close_messages_ix(&program_id, &bob.pubkey(), &chat, participants, &[alices]);
+    process(&mut context, &[close], &[&bob]).await.unwrap();
+    assert_eq!(lamports(&mut context, &alice.pubkey()).await, alice_before + alice_rent);
+
+    // Alice deletes the chat with bob's message still live: bob gets its
+    // rent, alice only the chat's
+    let alice_before = lamports(&mut context, &alice.pubkey()).await;
+    let bob_before = lamports(&mut context, &bob.pubkey()).await;
+    let chat_rent = lamports(&mut context, &chat).await;
+    let bob_rent = lamports(&mut context, &bobs).await;
+    let delete = delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &[bobs]);
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+
+    assert_eq!(lamports(&mut context, &bobs).await, 0);
+    assert_eq!(lamports(&mut context, &chat).await, 0);
+    assert_eq!(lamports(&mut context, &bob.pubkey()).await, bob_before + bob_rent);
+    assert_eq!(lamports(&mut context, &alice.pubkey()).await, alice_before + chat_rent);
+}
+
+#[tokio::test]
+async fn deleting_chat_rejects_wrong_refund_accounts() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    let messages = [message_pda(&program_id, &chat, 0)];
+
+    // Alice can't route bob's refund to herself
+    let participants = (&alice.pubkey(), &alice.pubkey());
+    let delete = delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &messages);
+    let result = process(&mut context, &[delete], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn deleting_chat_zeroes_the_public_keys() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    let messages = [message_pda(&program_id, &chat, 0)];
+
+    // Refunding the closed account in the same transaction keeps its buffer around
+    let participants = (&alice.pubkey(), &bob.pubkey());
+    let delete = delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &messages);
+    let revive = system_instruction::transfer(&alice.pubkey(), &chat, SOL);
+    process(&mut context, &[delete, revive], &[&alice]).await.unwrap();
+
+    let account = context.banks_client.get_account(chat).await.unwrap().unwrap();
+    assert_eq!(account.data.len(), Chat::LEN);
+    assert!(account.data[Chat::PUBLIC_KEYS_RANGE].iter().all(|&byte| byte == 0));
+}
+
+#[tokio::test]
+async fn archive_chat_returns_the_state_digest_and_closes_the_chat() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    let message = message_pda(&program_id, &chat, 0);
+    let expected = get_chat(&mut context, &chat).await.state_digest();
+
+    let participants = (&alice.pubkey(), &Pubkey::default());
+    let archive = archive_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &[message]);
+    let return_data = simulated_return_data(&mut context, std::slice::from_ref(&archive), &[&alice])
+        .await
+        .unwrap();
+    assert_eq!(return_data.program_id, program_id);
+    assert_eq!(return_data.data, expected);
+
+    process(&mut context, &[archive], &[&alice]).await.unwrap();
+    assert_eq!(lamports(&mut context, &chat).await, 0);
+    assert_eq!(lamports(&mut context, &message).await, 0);
+}
+
+#[tokio::test]
+async fn archive_chat_checks_match_delete_chat() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    // Leaving the live message out would strand it
+    let participants = (&alice.pubkey(), &Pubkey::default());
+    let archive = archive_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &[]);
+    let result = process(&mut context, &[archive], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::NotEnoughAccountKeys);
+}
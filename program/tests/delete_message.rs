@@ -0,0 +1,114 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::*;
+use solana_program::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use whisperchain::error::WhisperChainError;
+
+#[tokio::test]
+async fn expired_message_refund_goes_to_sender() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    set_unix_timestamp(&mut context, 1_000).await;
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 64], 2_000);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message = message_pda(&program_id, &chat, 0);
+    let rent = lamports(&mut context, &message).await;
+    let before = lamports(&mut context, &alice.pubkey()).await;
+
+    set_unix_timestamp(&mut context, 3_000).await;
+    let delete = delete_message_ix(&program_id, &alice.pubkey(), &message, &chat, &alice.pubkey());
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+
+    assert_eq!(lamports(&mut context, &message).await, 0);
+    assert_eq!(lamports(&mut context, &alice.pubkey()).await, before + rent);
+}
+
+#[tokio::test]
+async fn refund_to_someone_other_than_sender_is_rejected() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let mallory = funded_keypair(&mut context).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 64], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message = message_pda(&program_id, &chat, 0);
+    let delete = delete_message_ix(&program_id, &alice.pubkey(), &message, &chat, &mallory.pubkey());
+    let result = process(&mut context, &[delete], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn recipient_can_reap_expired_message() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    set_unix_timestamp(&mut context, 1_000).await;
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![2u8; 64], 2_000);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message = message_pda(&program_id, &chat, 1);
+    let delete = delete_message_ix(&program_id, &bob.pubkey(), &message, &chat, &alice.pubkey());
+
+    // Still live: only alice may delete it
+    let result = process(&mut context, std::slice::from_ref(&delete), &[&bob]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    let rent = lamports(&mut context, &message).await;
+    let before = lamports(&mut context, &alice.pubkey()).await;
+
+    set_unix_timestamp(&mut context, 2_000).await;
+    process(&mut context, &[delete], &[&bob]).await.unwrap();
+
+    assert_eq!(lamports(&mut context, &message).await, 0);
+    assert_eq!(lamports(&mut context, &alice.pubkey()).await, before + rent);
+}
+
+#[tokio::test]
+async fn non_participant_cannot_reap_expired_message() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let mallory = funded_keypair(&mut context).await;
+
+    set_unix_timestamp(&mut context, 1_000).await;
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 64], 2_000);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    set_unix_timestamp(&mut context, 3_000).await;
+    let message = message_pda(&program_id, &chat, 0);
+    let delete = delete_message_ix(&program_id, &mallory.pubkey(), &message, &chat, &alice.pubkey());
+    let result = process(&mut context, &[delete], &[&mallory]).await;
+
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+}
+
+#[tokio::test]
+async fn delete_with_zero_live_count_fails_cleanly() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    // Simulate a delete that was already counted
+    let mut account = context.banks_client.get_account(chat).await.unwrap().unwrap();
+    let mut state = get_chat(&mut context, &chat).await;
+    state.live_message_count = 0;
+    account.data = state.try_to_vec().unwrap();
+    context.set_account(&chat, &account.into());
+
+    let message = message_pda(&program_id, &chat, 0);
+    let delete = delete_message_ix(&program_id, &alice.pubkey(), &message, &chat, &alice.pubkey());
+    let result = process(&mut context, &[delete], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::ArithmeticOverflow);
+    assert_eq!(get_message(&mut context, &message).await.index, 0);
+}
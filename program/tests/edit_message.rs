@@ -0,0 +1,72 @@
+mod common;
+
+use common::*;
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, state::Message};
+
+#[tokio::test]
+async fn same_size_edit_replaces_ciphertext_in_place() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    set_unix_timestamp(&mut context, 1_000).await;
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 64], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message_key = message_pda(&program_id, &chat, 0);
+    let rent_before = lamports(&mut context, &message_key).await;
+
+    set_unix_timestamp(&mut context, 1_500).await;
+    let edit = edit_message_ix(&program_id, &alice.pubkey(), &message_key, &chat, vec![2u8; 64]);
+    process(&mut context, &[edit], &[&alice]).await.unwrap();
+
+    let message = get_message(&mut context, &message_key).await;
+    assert_eq!(message.encrypted_data, vec![2u8; 64]);
+    assert_eq!(message.ephemeral_public_key, [9u8; 32]);
+    assert_eq!(message.index, 0);
+    assert_eq!(message.timestamp, 1_000);
+    assert_eq!(message.edited_at, 1_500);
+    assert_eq!(lamports(&mut context, &message_key).await, rent_before);
+}
+
+#[tokio::test]
+async fn growing_edit_reallocates_and_tops_up_rent() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message_key = message_pda(&program_id, &chat, 0);
+    let edit = edit_message_ix(&program_id, &alice.pubkey(), &message_key, &chat, vec![2u8; 400]);
+    process(&mut context, &[edit], &[&alice]).await.unwrap();
+
+    let account = context
+        .banks_client
+        .get_account(message_key)
+        .await
+        .unwrap()
+        .unwrap();
+    let rent = context.banks_client.get_rent().await.unwrap();
+    assert_eq!(account.data.len(), Message::space(400, false, false));
+    assert_eq!(account.lamports, rent.minimum_balance(Message::space(400, false, false)));
+
+    let message = get_message(&mut context, &message_key).await;
+    assert_eq!(message.encrypted_data, vec![2u8; 400]);
+}
+
+#[tokio::test]
+async fn only_sender_can_edit() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message_key = message_pda(&program_id, &chat, 0);
+    let edit = edit_message_ix(&program_id, &bob.pubkey(), &message_key, &chat, vec![2u8; 16]);
+    let result = process(&mut context, &[edit], &[&bob]).await;
+
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use common::*;
+use solana_program::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, state::Message};
+
+#[tokio::test]
+async fn forward_records_the_source_message() {
+    let (mut context, program_id) = start().await;
+    let (alice, source_chat) = create_chat(&mut context, &program_id).await;
+    let (carol, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &source_chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    let send = send_message_ix(&program_id, &carol.pubkey(), &chat, 0, vec![2u8; 16], 0);
+    process(&mut context, &[send], &[&carol]).await.unwrap();
+    // Alice joins the destination chat as participant2
+    let join = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![3u8; 16], 0);
+    process(&mut context, &[join], &[&alice]).await.unwrap();
+
+    let source_message = message_pda(&program_id, &source_chat, 0);
+    let forward = forward_message_ix(
+        &program_id,
+        &alice.pubkey(),
+        &source_chat,
+        &source_message,
+        &chat,
+        2,
+        vec![4u8; 32],
+    );
+    process(&mut context, &[forward], &[&alice]).await.unwrap();
+
+    let forwarded = get_message(&mut context, &message_pda(&program_id, &chat, 2)).await;
+    assert_eq!(forwarded.version, Message::VERSION);
+    assert_eq!(forwarded.forwarded_from, Some(source_message));
+    assert_eq!(forwarded.chat, chat);
+    assert_eq!(forwarded.sender, alice.pubkey());
+    assert_eq!(forwarded.ephemeral_public_key, [8u8; 32]);
+    assert_eq!(forwarded.encrypted_data, vec![4u8; 32]);
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 3);
+
+    // The original is untouched
+    let original = get_message(&mut context, &source_message).await;
+    assert_eq!(original.forwarded_from, None);
+    assert_eq!(original.encrypted_data, vec![1u8; 16]);
+}
+
+#[tokio::test]
+async fn cannot_forward_from_a_chat_the_signer_is_not_in() {
+    let (mut context, program_id) = start().await;
+    let (alice, source_chat) = create_chat(&mut context, &program_id).await;
+    let (mallory, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &source_chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let forward = forward_message_ix(
+        &program_id,
+        &mallory.pubkey(),
+        &source_chat,
+        &message_pda(&program_id, &source_chat, 0),
+        &chat,
+        0,
+        vec![1u8; 16],
+    );
+    let result = process(&mut context, &[forward], &[&mallory]).await;
+
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+}
+
+#[tokio::test]
+async fn forwarding_does_not_join_the_destination_chat() {
+    let (mut context, program_id) = start().await;
+    let (alice, source_chat) = create_chat(&mut context, &program_id).await;
+    let (carol, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &source_chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    // SendMessage would let alice take the open participant2 slot
+    let forward = forward_message_ix(
+        &program_id,
+        &alice.pubkey(),
+        &source_chat,
+        &message_pda(&program_id, &source_chat, 0),
+        &chat,
+        0,
+        vec![1u8; 16],
+    );
+    let result = process(&mut context, &[forward], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant1, carol.pubkey());
+    assert_eq!(state.participant2, Default::default());
+    assert_eq!(state.message_count, 0);
+}
+
+#[tokio::test]
+async fn source_message_must_belong_to_the_source_chat() {
+    let (mut context, program_id) = start().await;
+    let (alice, source_chat) = create_chat(&mut context, &program_id).await;
+    let (carol, other_chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &carol.pubkey(), &other_chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&carol]).await.unwrap();
+
+    // Alice is in source_chat but the message is from carol's chat
+    let forward = forward_message_ix(
+        &program_id,
+        &alice.pubkey(),
+        &source_chat,
+        &message_pda(&program_id, &other_chat, 0),
+        &source_chat,
+        0,
+        vec![1u8; 16],
+    );
+    let result = process(&mut context, &[forward], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+}
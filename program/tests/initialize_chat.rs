@@ -0,0 +1,116 @@
+mod common;
+
+use common::*;
+use solana_program::{instruction::InstructionError, pubkey::Pubkey, system_instruction};
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, instruction::WhisperChainInstruction, state::ChatInit};
+
+#[tokio::test]
+async fn batch_initializes_every_chat() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+    let bob = Pubkey::new_unique();
+    let carol = Pubkey::new_unique();
+
+    let ix = initialize_chats_batch_ix(
+        &program_id,
+        &alice.pubkey(),
+        vec![
+            ChatInit { counterpart: bob, public_key: [2u8; 32] },
+            ChatInit { counterpart: carol, public_key: [3u8; 32] },
+        ],
+    );
+    process(&mut context, &[ix], &[&alice]).await.unwrap();
+
+    for (counterpart, public_key) in [(bob, [2u8; 32]), (carol, [3u8; 32])] {
+        let chat_key = batch_chat_pda(&program_id, &alice.pubkey(), &counterpart);
+        let chat = get_chat(&mut context, &chat_key).await;
+
+        assert!(chat.is_initialized);
+        assert_eq!(chat.participant1, alice.pubkey());
+        assert_eq!(chat.participant2, counterpart);
+        assert_eq!(chat.participant1_public_key, public_key);
+        assert_eq!(chat.message_count, 0);
+    }
+}
+
+#[tokio::test]
+async fn batch_rejects_duplicate_counterparts() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+    let bob = Pubkey::new_unique();
+
+    let entry = ChatInit { counterpart: bob, public_key: [2u8; 32] };
+    let ix = initialize_chats_batch_ix(&program_id, &alice.pubkey(), vec![entry.clone(), entry]);
+    let result = process(&mut context, &[ix], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+}
+
+#[tokio::test]
+async fn batch_rejects_self_as_counterpart() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+
+    let entry = ChatInit { counterpart: alice.pubkey(), public_key: [2u8; 32] };
+    let ix = initialize_chats_batch_ix(&program_id, &alice.pubkey(), vec![entry]);
+    let result = process(&mut context, &[ix], &[&alice]).await;
+
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+}
+
+#[tokio::test]
+async fn unsafe_dh_keys_are_rejected() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+
+    // u = 1 has order 4, [0xff; 32] is not a reduced field element
+    let mut low_order = [0u8; 32];
+    low_order[0] = 1;
+    for key in [low_order, [0xff; 32]] {
+        let ix = initialize_chat_ix(&program_id, &alice.pubkey(), key);
+        let result = process(&mut context, &[ix], &[&alice]).await;
+        assert_custom_error(result, WhisperChainError::InvalidPublicKey);
+    }
+
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let data = WhisperChainInstruction::SendMessage {
+        encrypted_data: vec![1u8; 16],
+        ephemeral_public_key: low_order,
+        expires_at: 0,
+        visible_at: 0,
+        client_timestamp: 0,
+        client_nonce: [0u8; 16],
+        reply_to: None,
+        show_sender: true,
+    };
+    let send = send_instruction(&program_id, &alice.pubkey(), &chat, 0, data);
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::InvalidPublicKey);
+}
+
+#[tokio::test]
+async fn second_initialize_is_already_initialized() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let again = initialize_chat_ix(&program_id, &alice.pubkey(), [2u8; 32]);
+    let result = process(&mut context, &[again], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::AlreadyInitialized);
+
+    assert_eq!(get_chat(&mut context, &chat).await.participant1_public_key, [1u8; 32]);
+}
+
+#[tokio::test]
+async fn prefunded_chat_address_is_already_initialized() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+    let chat = chat_pda(&program_id, &alice.pubkey());
+
+    let fund = system_instruction::transfer(&alice.pubkey(), &chat, 1_000_000);
+    process(&mut context, &[fund], &[&alice]).await.unwrap();
+
+    let init = initialize_chat_ix(&program_id, &alice.pubkey(), [1u8; 32]);
+    let result = process(&mut context, &[init], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::AlreadyInitialized);
+}
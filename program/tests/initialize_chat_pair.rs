@@ -0,0 +1,45 @@
+mod common;
+
+use common::*;
+use solana_sdk::signature::Signer;
+use whisperchain::error::WhisperChainError;
+
+#[tokio::test]
+async fn either_side_derives_the_same_chat() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let chat = pair_chat_pda(&program_id, &alice.pubkey(), &bob.pubkey());
+    assert_eq!(chat, pair_chat_pda(&program_id, &bob.pubkey(), &alice.pubkey()));
+
+    let init = initialize_chat_pair_ix(&program_id, &alice.pubkey(), &bob.pubkey(), [1u8; 32]);
+    process(&mut context, &[init], &[&alice]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant1, alice.pubkey());
+    assert_eq!(state.participant2, bob.pubkey());
+
+    // Bob found the chat without asking who created it, and can reply
+    let send = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&bob]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.participant2_public_key, [7u8; 32]);
+}
+
+#[tokio::test]
+async fn pair_can_only_be_initialized_once() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let init = initialize_chat_pair_ix(&program_id, &alice.pubkey(), &bob.pubkey(), [1u8; 32]);
+    process(&mut context, &[init], &[&alice]).await.unwrap();
+
+    let again = initialize_chat_pair_ix(&program_id, &bob.pubkey(), &alice.pubkey(), [2u8; 32]);
+    let result = process(&mut context, &[again], &[&bob]).await;
+    assert_custom_error(result, WhisperChainError::AlreadyInitialized);
+
+    let alone = initialize_chat_pair_ix(&program_id, &alice.pubkey(), &alice.pubkey(), [1u8; 32]);
+    let result = process(&mut context, &[alone], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+}
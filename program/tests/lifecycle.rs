@@ -0,0 +1,87 @@
+mod common;
+
+use common::*;
+use solana_program::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use whisperchain::state::{Chat, Message};
+
+/// A chat from creation to deletion, checking state and rent at each step
+#[tokio::test]
+async fn chat_lifecycle_end_to_end() {
+    let (mut context, program_id) = start().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let alice = funded_keypair(&mut context).await;
+    let bob = funded_keypair(&mut context).await;
+    let chat = chat_pda(&program_id, &alice.pubkey());
+
+    // Initialize: alice pays the chat's rent
+    let alice_start = lamports(&mut context, &alice.pubkey()).await;
+    let init = initialize_chat_ix(&program_id, &alice.pubkey(), [1u8; 32]);
+    process(&mut context, &[init], &[&alice]).await.unwrap();
+
+    let chat_rent = rent.minimum_balance(Chat::LEN);
+    assert_eq!(lamports(&mut context, &chat).await, chat_rent);
+    assert_eq!(lamports(&mut context, &alice.pubkey()).await, alice_start - chat_rent);
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant1, alice.pubkey());
+    assert_eq!(state.participant1_public_key, [1u8; 32]);
+    assert_eq!(state.participant2, Default::default());
+
+    // Two messages, each paid for by its sender; bob's first one joins him
+    let message_rent = rent.minimum_balance(Message::space(16, false, false));
+    let bob_start = lamports(&mut context, &bob.pubkey()).await;
+    let send = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&bob]).await.unwrap();
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let first = message_pda(&program_id, &chat, 0);
+    let second = message_pda(&program_id, &chat, 1);
+    assert_eq!(lamports(&mut context, &first).await, message_rent);
+    assert_eq!(lamports(&mut context, &second).await, message_rent);
+    assert_eq!(lamports(&mut context, &bob.pubkey()).await, bob_start - message_rent);
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant2, bob.pubkey());
+    assert_eq!(state.message_count, 2);
+    assert_eq!(state.live_message_count, 2);
+    let message = get_message(&mut context, &first).await;
+    assert_eq!((message.chat, message.sender, message.index), (chat, bob.pubkey(), 0));
+    assert_eq!(message.encrypted_data, vec![1u8; 16]);
+
+    // Bob reads alice's message, then deletes his own and gets its rent back
+    let read = mark_read_ix(&program_id, &bob.pubkey(), &chat, 1);
+    process(&mut context, &[read], &[&bob]).await.unwrap();
+    assert!(get_chat(&mut context, &chat).await.has_read(&bob.pubkey(), 1));
+
+    let delete = delete_message_ix(&program_id, &bob.pubkey(), &first, &chat, &bob.pubkey());
+    process(&mut context, &[delete], &[&bob]).await.unwrap();
+    assert_eq!(lamports(&mut context, &first).await, 0);
+    assert_eq!(lamports(&mut context, &bob.pubkey()).await, bob_start);
+    assert_eq!(get_chat(&mut context, &chat).await.live_message_count, 1);
+
+    // Deleting the chat closes the remaining message with it
+    let alice_before = lamports(&mut context, &alice.pubkey()).await;
+    let participants = (&alice.pubkey(), &bob.pubkey());
+    let delete = delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &[second]);
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+
+    assert_eq!(lamports(&mut context, &chat).await, 0);
+    assert_eq!(lamports(&mut context, &second).await, 0);
+    assert_eq!(
+        lamports(&mut context, &alice.pubkey()).await,
+        alice_before + chat_rent + message_rent
+    );
+}
+
+#[tokio::test]
+async fn send_without_sender_signature_is_rejected() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let mut send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    send.accounts[0].is_signer = false;
+    let result = process(&mut context, &[send], &[]).await;
+
+    assert_instruction_error(result, InstructionError::MissingRequiredSignature);
+}
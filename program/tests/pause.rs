@@ -0,0 +1,128 @@
+mod common;
+
+use common::*;
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::{Keypair, Signer};
+use whisperchain::{error::WhisperChainError, state::Config};
+
+/// Starts the program and initializes the config, returning its admin
+async fn init_config() -> (ProgramTestContext, Pubkey, Keypair) {
+    let (mut context, program_id, admin) = start_with_upgrade_authority().await;
+    let ix = init_config_ix(&program_id, &admin.pubkey());
+    process(&mut context, &[ix], &[&admin]).await.unwrap();
+    (context, program_id, admin)
+}
+
+#[tokio::test]
+async fn only_the_upgrade_authority_can_initialize_the_config() {
+    let (mut context, program_id) = start().await;
+
+    let usurper = funded_keypair(&mut context).await;
+    let ix = init_config_ix(&program_id, &usurper.pubkey());
+    let result = process(&mut context, &[ix], &[&usurper]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    // Nor can they vouch for themselves with an account of their own
+    let mut ix = init_config_ix(&program_id, &usurper.pubkey());
+    ix.accounts[3].pubkey = usurper.pubkey();
+    let result = process(&mut context, &[ix], &[&usurper]).await;
+    assert_instruction_error(result, InstructionError::InvalidAccountData);
+}
+
+#[tokio::test]
+async fn config_can_only_be_initialized_once() {
+    let (mut context, program_id, admin) = init_config().await;
+
+    let account = context
+        .banks_client
+        .get_account(config_pda(&program_id))
+        .await
+        .unwrap()
+        .unwrap();
+    let config = Config::unpack(&account.data).unwrap();
+    assert_eq!(config.admin, admin.pubkey());
+    assert!(!config.paused);
+
+    let usurper = funded_keypair(&mut context).await;
+    let ix = init_config_ix(&program_id, &usurper.pubkey());
+    let result = process(&mut context, &[ix], &[&usurper]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    let ix = init_config_ix(&program_id, &admin.pubkey());
+    let result = process(&mut context, &[ix], &[&admin]).await;
+    assert_custom_error(result, WhisperChainError::AlreadyInitialized);
+}
+
+#[tokio::test]
+async fn only_the_admin_can_pause() {
+    let (mut context, program_id, _admin) = init_config().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let pause = set_paused_ix(&program_id, &alice.pubkey(), true);
+    let result = process(&mut context, &[pause], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+}
+
+#[tokio::test]
+async fn pause_blocks_new_writes_until_resumed() {
+    let (mut context, program_id, admin) = init_config().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let pause = set_paused_ix(&program_id, &admin.pubkey(), true);
+    process(&mut context, &[pause], &[&admin]).await.unwrap();
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::ProgramPaused);
+
+    let bob = funded_keypair(&mut context).await;
+    let init = initialize_chat_ix(&program_id, &bob.pubkey(), [1u8; 32]);
+    let result = process(&mut context, &[init], &[&bob]).await;
+    assert_custom_error(result, WhisperChainError::ProgramPaused);
+
+    // Deletes still go through so users can reclaim rent
+    let delete = delete_message_ix(
+        &program_id,
+        &alice.pubkey(),
+        &message_pda(&program_id, &chat, 0),
+        &chat,
+        &alice.pubkey(),
+    );
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+
+    let resume = set_paused_ix(&program_id, &admin.pubkey(), false);
+    process(&mut context, &[resume], &[&admin]).await.unwrap();
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 2);
+}
+
+#[tokio::test]
+async fn paused_send_without_the_config_account_fails() {
+    let (mut context, program_id, admin) = init_config().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let pause = set_paused_ix(&program_id, &admin.pubkey(), true);
+    process(&mut context, &[pause], &[&admin]).await.unwrap();
+
+    let mut send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    send.accounts.pop();
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::NotEnoughAccountKeys);
+
+    // Nor can an empty account stand in for it
+    let mut send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    send.accounts[5].pubkey = stats_pda(&program_id);
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::InvalidAccountData);
+
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 0);
+}
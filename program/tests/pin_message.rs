@@ -0,0 +1,98 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::*;
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, state::MAX_PINNED_MESSAGES};
+
+#[tokio::test]
+async fn either_participant_can_pin_and_unpin() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    let message = message_pda(&program_id, &chat, 0);
+
+    // Alice pins bob's message
+    let pin = set_pinned_ix(&program_id, &alice.pubkey(), &chat, &message, true);
+    process(&mut context, &[pin], &[&alice]).await.unwrap();
+    assert!(get_message(&mut context, &message).await.pinned);
+    assert_eq!(get_chat(&mut context, &chat).await.pinned_count, 1);
+
+    // Pinning again doesn't count twice
+    let pin = set_pinned_ix(&program_id, &bob.pubkey(), &chat, &message, true);
+    process(&mut context, &[pin], &[&bob]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.pinned_count, 1);
+
+    let unpin = set_pinned_ix(&program_id, &bob.pubkey(), &chat, &message, false);
+    process(&mut context, &[unpin], &[&bob]).await.unwrap();
+    assert!(!get_message(&mut context, &message).await.pinned);
+    assert_eq!(get_chat(&mut context, &chat).await.pinned_count, 0);
+}
+
+#[tokio::test]
+async fn non_participant_cannot_pin() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let mallory = funded_keypair(&mut context).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message = message_pda(&program_id, &chat, 0);
+    let pin = set_pinned_ix(&program_id, &mallory.pubkey(), &chat, &message, true);
+    let result = process(&mut context, &[pin], &[&mallory]).await;
+
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+}
+
+#[tokio::test]
+async fn pins_are_capped_per_chat() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    for index in 0..2 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![1u8; 16], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+    let pin = set_pinned_ix(&program_id, &alice.pubkey(), &chat, &message_pda(&program_id, &chat, 0), true);
+    process(&mut context, &[pin], &[&alice]).await.unwrap();
+
+    // Stand in for the other pinned messages
+    let mut account = context.banks_client.get_account(chat).await.unwrap().unwrap();
+    let mut state = get_chat(&mut context, &chat).await;
+    state.pinned_count = MAX_PINNED_MESSAGES;
+    account.data = state.try_to_vec().unwrap();
+    context.set_account(&chat, &account.into());
+
+    let pin = set_pinned_ix(&program_id, &alice.pubkey(), &chat, &message_pda(&program_id, &chat, 1), true);
+    let result = process(&mut context, &[pin], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::PinLimitReached);
+
+    // Unpinning frees a slot
+    let unpin = set_pinned_ix(&program_id, &alice.pubkey(), &chat, &message_pda(&program_id, &chat, 0), false);
+    process(&mut context, &[unpin], &[&alice]).await.unwrap();
+    let pin = set_pinned_ix(&program_id, &alice.pubkey(), &chat, &message_pda(&program_id, &chat, 1), true);
+    process(&mut context, &[pin], &[&alice]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.pinned_count, MAX_PINNED_MESSAGES);
+}
+
+#[tokio::test]
+async fn deleting_a_pinned_message_releases_its_pin() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    let message = message_pda(&program_id, &chat, 0);
+
+    let pin = set_pinned_ix(&program_id, &alice.pubkey(), &chat, &message, true);
+    process(&mut context, &[pin], &[&alice]).await.unwrap();
+
+    let delete = delete_message_ix(&program_id, &alice.pubkey(), &message, &chat, &alice.pubkey());
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+
+    assert_eq!(get_chat(&mut context, &chat).await.pinned_count, 0);
+}
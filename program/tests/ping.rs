@@ -0,0 +1,18 @@
+mod common;
+
+use common::*;
+
+#[tokio::test]
+async fn ping_returns_the_program_version() {
+    let (mut context, program_id) = start().await;
+
+    let ix = ping_ix(&program_id);
+    let return_data = simulated_return_data(&mut context, &[ix], &[]).await.unwrap();
+    assert_eq!(return_data.program_id, program_id);
+    assert_eq!(return_data.data, whisperchain::PROGRAM_VERSION.as_bytes());
+    assert_eq!(whisperchain::PROGRAM_VERSION, env!("CARGO_PKG_VERSION"));
+
+    // Nothing is written, so the real transaction lands too
+    let ix = ping_ix(&program_id);
+    process(&mut context, &[ix], &[]).await.unwrap();
+}
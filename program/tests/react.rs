@@ -0,0 +1,119 @@
+mod common;
+
+use common::*;
+use solana_program::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, state::REACTION_SLOTS};
+
+#[tokio::test]
+async fn participants_add_and_remove_reactions() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    let message = message_pda(&program_id, &chat, 0);
+
+    let react = react_ix(&program_id, &alice.pubkey(), &chat, &message, 3, true, 0);
+    process(&mut context, &[react], &[&alice]).await.unwrap();
+    let react = react_ix(&program_id, &bob.pubkey(), &chat, &message, 3, true, 0);
+    process(&mut context, &[react], &[&bob]).await.unwrap();
+    assert_eq!(get_message(&mut context, &message).await.reactions, [0, 0, 0, 2, 0, 0, 0, 0]);
+
+    let unreact = react_ix(&program_id, &bob.pubkey(), &chat, &message, 3, false, 0);
+    process(&mut context, &[unreact], &[&bob]).await.unwrap();
+    assert_eq!(get_message(&mut context, &message).await.reactions[3], 1);
+}
+
+#[tokio::test]
+async fn removing_an_absent_reaction_is_rejected() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message = message_pda(&program_id, &chat, 0);
+    let unreact = react_ix(&program_id, &alice.pubkey(), &chat, &message, 0, false, 0);
+    let result = process(&mut context, &[unreact], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::ArithmeticOverflow);
+}
+
+#[tokio::test]
+async fn out_of_range_slot_is_rejected() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message = message_pda(&program_id, &chat, 0);
+    let slot = REACTION_SLOTS as u8;
+    let react = react_ix(&program_id, &alice.pubkey(), &chat, &message, slot, true, 0);
+    let result = process(&mut context, &[react], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+}
+
+#[tokio::test]
+async fn non_participant_cannot_react() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let mallory = funded_keypair(&mut context).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message = message_pda(&program_id, &chat, 0);
+    let react = react_ix(&program_id, &mallory.pubkey(), &chat, &message, 0, true, 0);
+    let result = process(&mut context, &[react], &[&mallory]).await;
+
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+}
+
+#[tokio::test]
+async fn retried_reaction_is_counted_once() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    let message = message_pda(&program_id, &chat, 0);
+
+    let react = react_ix(&program_id, &alice.pubkey(), &chat, &message, 2, true, 41);
+    process(&mut context, std::slice::from_ref(&react), &[&alice]).await.unwrap();
+    process(&mut context, &[react], &[&alice]).await.unwrap();
+    assert_eq!(get_message(&mut context, &message).await.reactions[2], 1);
+
+    // Nonces are tracked per participant, so bob's reuse of 41 still counts
+    let react = react_ix(&program_id, &bob.pubkey(), &chat, &message, 2, true, 41);
+    process(&mut context, &[react], &[&bob]).await.unwrap();
+    let state = get_message(&mut context, &message).await;
+    assert_eq!(state.reactions[2], 2);
+    assert_eq!(state.participant1_last_reaction_nonce, 41);
+    assert_eq!(state.participant2_last_reaction_nonce, 41);
+}
+
+#[tokio::test]
+async fn reaction_with_a_new_nonce_is_counted() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    let message = message_pda(&program_id, &chat, 0);
+
+    let react = react_ix(&program_id, &alice.pubkey(), &chat, &message, 5, true, 1);
+    process(&mut context, &[react], &[&alice]).await.unwrap();
+    let react = react_ix(&program_id, &alice.pubkey(), &chat, &message, 5, true, 2);
+    process(&mut context, &[react], &[&alice]).await.unwrap();
+    let unreact = react_ix(&program_id, &alice.pubkey(), &chat, &message, 5, false, 3);
+    process(&mut context, &[unreact], &[&alice]).await.unwrap();
+
+    let state = get_message(&mut context, &message).await;
+    assert_eq!(state.reactions[5], 1);
+    assert_eq!(state.participant1_last_reaction_nonce, 3);
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use common::*;
+use solana_program::instruction::{AccountMeta, InstructionError};
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, instruction::WhisperChainInstruction, state::ChatConfig};
+
+#[tokio::test]
+async fn read_cursor_only_moves_forward() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    for index in 1..3 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![1u8; 16], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+
+    let read = mark_read_ix(&program_id, &bob.pubkey(), &chat, 1);
+    process(&mut context, &[read], &[&bob]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert!(state.has_read(&bob.pubkey(), 1));
+    assert!(!state.has_read(&bob.pubkey(), 2));
+    assert!(!state.has_read(&alice.pubkey(), 0));
+
+    let rewind = mark_read_ix(&program_id, &bob.pubkey(), &chat, 0);
+    let result = process(&mut context, &[rewind], &[&bob]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+
+    let beyond = mark_read_ix(&program_id, &bob.pubkey(), &chat, 3);
+    let result = process(&mut context, &[beyond], &[&bob]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+
+    let read = mark_read_ix(&program_id, &bob.pubkey(), &chat, 2);
+    process(&mut context, &[read], &[&bob]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.participant2_read_cursor, 3);
+}
+
+#[tokio::test]
+async fn non_participant_cannot_mark_read() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let mallory = funded_keypair(&mut context).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let read = mark_read_ix(&program_id, &mallory.pubkey(), &chat, 0);
+    let result = process(&mut context, &[read], &[&mallory]).await;
+
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+}
+
+#[tokio::test]
+async fn delivery_cursor_only_moves_forward() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    for index in 1..3 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![1u8; 16], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+
+    let delivered = mark_delivered_ix(&program_id, &bob.pubkey(), &chat, 2);
+    process(&mut context, &[delivered], &[&bob]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert!(state.has_delivered(&bob.pubkey(), 2));
+    assert!(!state.has_read(&bob.pubkey(), 0));
+
+    let rewind = mark_delivered_ix(&program_id, &bob.pubkey(), &chat, 1);
+    let result = process(&mut context, &[rewind], &[&bob]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+
+    let beyond = mark_delivered_ix(&program_id, &bob.pubkey(), &chat, 3);
+    let result = process(&mut context, &[beyond], &[&bob]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+
+    // Reading behind the delivery cursor leaves it alone
+    let read = mark_read_ix(&program_id, &bob.pubkey(), &chat, 0);
+    process(&mut context, &[read], &[&bob]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.participant2_delivered_cursor, 3);
+
+    let mallory = funded_keypair(&mut context).await;
+    let delivered = mark_delivered_ix(&program_id, &mallory.pubkey(), &chat, 0);
+    let result = process(&mut context, &[delivered], &[&mallory]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+}
+
+#[tokio::test]
+async fn reading_past_delivery_advances_it() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    for index in 1..3 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![1u8; 16], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+
+    let delivered = mark_delivered_ix(&program_id, &bob.pubkey(), &chat, 0);
+    process(&mut context, &[delivered], &[&bob]).await.unwrap();
+
+    let read = mark_read_ix(&program_id, &bob.pubkey(), &chat, 2);
+    process(&mut context, &[read], &[&bob]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant2_read_cursor, 3);
+    assert_eq!(state.participant2_delivered_cursor, 3);
+
+    // Delivery can't be rewound below what has been read
+    let rewind = mark_delivered_ix(&program_id, &bob.pubkey(), &chat, 1);
+    let result = process(&mut context, &[rewind], &[&bob]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+}
+
+#[tokio::test]
+async fn strict_chat_rejects_reading_before_visible_at() {
+    let (mut context, program_id) = start().await;
+    let config = ChatConfig {
+        strict_visibility: true,
+        ..self_chat_config()
+    };
+    let (alice, chat) = create_chat_with_config(&mut context, &program_id, config).await;
+    let bob = funded_keypair(&mut context).await;
+
+    set_unix_timestamp(&mut context, 5_000).await;
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    let data = WhisperChainInstruction::SendMessage {
+        encrypted_data: vec![2u8; 16],
+        ephemeral_public_key: [7u8; 32],
+        expires_at: 0,
+        visible_at: 8_000,
+        client_timestamp: 0,
+        client_nonce: [0u8; 16],
+        reply_to: None,
+        show_sender: true,
+    };
+    let send = send_instruction(&program_id, &alice.pubkey(), &chat, 1, data);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let messages = [message_pda(&program_id, &chat, 0), message_pda(&program_id, &chat, 1)];
+    let strict_read = |up_to_index: u64, messages: &[_]| {
+        let mut ix = mark_read_ix(&program_id, &alice.pubkey(), &chat, up_to_index);
+        ix.accounts
+            .extend(messages.iter().map(|message| AccountMeta::new_readonly(*message, false)));
+        ix
+    };
+
+    let early = strict_read(1, &messages);
+    let result = process(&mut context, &[early], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::NotYetVisible);
+
+    // Strict reads must carry the messages they cover
+    let unchecked = strict_read(1, &[]);
+    let result = process(&mut context, &[unchecked], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::NotEnoughAccountKeys);
+
+    let read = strict_read(0, &messages[..1]);
+    process(&mut context, &[read], &[&alice]).await.unwrap();
+
+    set_unix_timestamp(&mut context, 8_000).await;
+    let read = strict_read(1, &messages[1..]);
+    process(&mut context, &[read], &[&alice]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.participant1_read_cursor, 2);
+}
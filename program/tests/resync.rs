@@ -0,0 +1,82 @@
+mod common;
+
+use borsh::BorshSerialize;
+use common::*;
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_sdk::signature::Signer;
+
+#[tokio::test]
+async fn resync_repairs_drifted_counters() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    for index in 0..3 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![1u8; 16], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+    let message = message_pda(&program_id, &chat, 0);
+    let delete = delete_message_ix(&program_id, &alice.pubkey(), &message, &chat, &alice.pubkey());
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+
+    // Corrupt the header counters
+    let mut account = context.banks_client.get_account(chat).await.unwrap().unwrap();
+    let mut state = get_chat(&mut context, &chat).await;
+    state.message_count = 1;
+    state.live_message_count = 0;
+    account.data = state.try_to_vec().unwrap();
+    context.set_account(&chat, &account.into());
+
+    let live = [message_pda(&program_id, &chat, 1), message_pda(&program_id, &chat, 2)];
+    let resync = resync_chat_ix(&program_id, &alice.pubkey(), &chat, &live);
+    process(&mut context, &[resync], &[&alice]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.message_count, 3);
+    assert_eq!(state.live_message_count, 2);
+
+    // Sending works again at the repaired index
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 3, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+}
+
+#[tokio::test]
+async fn resync_with_partial_set_keeps_counters() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    for index in 0..3 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![1u8; 16], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+
+    let partial = [message_pda(&program_id, &chat, 0)];
+    let resync = resync_chat_ix(&program_id, &alice.pubkey(), &chat, &partial);
+    process(&mut context, &[resync], &[&alice]).await.unwrap();
+    let resync = resync_chat_ix(&program_id, &alice.pubkey(), &chat, &[]);
+    process(&mut context, &[resync], &[&alice]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.message_count, 3);
+    assert_eq!(state.live_message_count, 3);
+
+    // DeleteChat still insists on the messages that were left out
+    let participants = (&alice.pubkey(), &Pubkey::default());
+    let delete = delete_chat_ix(&program_id, &alice.pubkey(), &chat, participants, &partial);
+    let result = process(&mut context, &[delete], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::NotEnoughAccountKeys);
+}
+
+#[tokio::test]
+async fn resync_rejects_repeated_messages() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message = message_pda(&program_id, &chat, 0);
+    let resync = resync_chat_ix(&program_id, &alice.pubkey(), &chat, &[message, message]);
+    let result = process(&mut context, &[resync], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::*;
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, instruction::WhisperChainInstruction};
+
+#[tokio::test]
+async fn messages_using_a_revoked_key_are_compromised() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    // Message 0 uses the standard [7; 32] ephemeral key, message 1 another one
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    let data = WhisperChainInstruction::SendMessage {
+        encrypted_data: vec![1u8; 16],
+        ephemeral_public_key: [8u8; 32],
+        expires_at: 0,
+        visible_at: 0,
+        client_timestamp: 0,
+        client_nonce: [0u8; 16],
+        reply_to: None,
+        show_sender: true,
+    };
+    let send = send_instruction(&program_id, &alice.pubkey(), &chat, 1, data);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let revoke = revoke_ephemeral_key_ix(&program_id, &alice.pubkey(), &chat, [7u8; 32]);
+    process(&mut context, &[revoke], &[&alice]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    let leaked = get_message(&mut context, &message_pda(&program_id, &chat, 0)).await;
+    let safe = get_message(&mut context, &message_pda(&program_id, &chat, 1)).await;
+    assert!(state.is_compromised(&leaked));
+    assert!(!state.is_compromised(&safe));
+}
+
+#[tokio::test]
+async fn revocation_rejects_zero_key_and_outsiders() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let mallory = funded_keypair(&mut context).await;
+
+    let revoke = revoke_ephemeral_key_ix(&program_id, &alice.pubkey(), &chat, [0u8; 32]);
+    let result = process(&mut context, &[revoke], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::InvalidPublicKey);
+
+    let revoke = revoke_ephemeral_key_ix(&program_id, &mallory.pubkey(), &chat, [7u8; 32]);
+    let result = process(&mut context, &[revoke], &[&mallory]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+}
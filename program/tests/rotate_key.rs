@@ -0,0 +1,142 @@
+mod common;
+
+use common::*;
+use solana_program::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, state::ChatConfig};
+
+#[tokio::test]
+async fn both_participants_can_rotate_their_key() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+
+    let rotate = rotate_key_ix(&program_id, &alice.pubkey(), &chat, [4u8; 32]);
+    process(&mut context, &[rotate], &[&alice]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant1_public_key, [4u8; 32]);
+    assert_eq!(state.participant2_public_key, [7u8; 32]);
+    assert_eq!(state.key_version, 1);
+
+    let rotate = rotate_key_ix(&program_id, &bob.pubkey(), &chat, [5u8; 32]);
+    process(&mut context, &[rotate], &[&bob]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant1_public_key, [4u8; 32]);
+    assert_eq!(state.participant2_public_key, [5u8; 32]);
+    assert_eq!(state.key_version, 2);
+}
+
+#[tokio::test]
+async fn rotation_rejects_zero_key_and_outsiders() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let mallory = funded_keypair(&mut context).await;
+
+    let rotate = rotate_key_ix(&program_id, &alice.pubkey(), &chat, [0u8; 32]);
+    let result = process(&mut context, &[rotate], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::InvalidPublicKey);
+
+    let rotate = rotate_key_ix(&program_id, &mallory.pubkey(), &chat, [4u8; 32]);
+    let result = process(&mut context, &[rotate], &[&mallory]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    assert_eq!(get_chat(&mut context, &chat).await.key_version, 0);
+}
+
+#[tokio::test]
+async fn rotations_must_be_spaced_out() {
+    let (mut context, program_id) = start().await;
+    let config = ChatConfig {
+        min_rotation_interval_secs: 3_600,
+        ..self_chat_config()
+    };
+    let (alice, chat) = create_chat_with_config(&mut context, &program_id, config).await;
+
+    set_unix_timestamp(&mut context, 10_000).await;
+    let rotate = rotate_key_ix(&program_id, &alice.pubkey(), &chat, [4u8; 32]);
+    process(&mut context, &[rotate], &[&alice]).await.unwrap();
+
+    set_unix_timestamp(&mut context, 13_599).await;
+    let rotate = rotate_key_ix(&program_id, &alice.pubkey(), &chat, [5u8; 32]);
+    let result = process(&mut context, &[rotate], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::RotationTooFrequent);
+
+    set_unix_timestamp(&mut context, 13_600).await;
+    let rotate = rotate_key_ix(&program_id, &alice.pubkey(), &chat, [5u8; 32]);
+    process(&mut context, &[rotate], &[&alice]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant1_public_key, [5u8; 32]);
+    assert_eq!(state.key_version, 2);
+    assert_eq!(state.last_rotation_at, 13_600);
+}
+
+#[tokio::test]
+async fn rekey_and_resend_tombstones_the_old_message() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    set_unix_timestamp(&mut context, 10_000).await;
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![2u8; 16], 90_000);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    set_unix_timestamp(&mut context, 20_000).await;
+    let message = message_pda(&program_id, &chat, 1);
+    let rekey = rekey_and_resend_ix(&program_id, &alice.pubkey(), &chat, &message, 2, [4u8; 32], vec![3u8; 32]);
+    process(&mut context, &[rekey], &[&alice]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant1_public_key, [4u8; 32]);
+    assert_eq!(state.key_version, 1);
+    assert_eq!(state.message_count, 3);
+
+    let old = get_message(&mut context, &message).await;
+    assert!(old.deleted);
+    assert!(old.encrypted_data.is_empty());
+
+    let resent = get_message(&mut context, &message_pda(&program_id, &chat, 2)).await;
+    assert!(resent.is_sent_by(&alice.pubkey()));
+    assert_eq!(resent.encrypted_data, vec![3u8; 32]);
+    assert_eq!(resent.ephemeral_public_key, [8u8; 32]);
+    assert_eq!(resent.expires_at, 90_000);
+    assert!(!resent.deleted);
+
+    // A tombstone has nothing left to resend
+    let again = rekey_and_resend_ix(&program_id, &alice.pubkey(), &chat, &message, 3, [5u8; 32], vec![3u8; 32]);
+    let result = process(&mut context, &[again], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+}
+
+#[tokio::test]
+async fn failed_resend_keeps_the_old_key() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+
+    // Bob is a participant but didn't send message 1
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    let message = message_pda(&program_id, &chat, 1);
+    let rekey = rekey_and_resend_ix(&program_id, &bob.pubkey(), &chat, &message, 2, [4u8; 32], vec![3u8; 16]);
+    let result = process(&mut context, &[rekey], &[&bob]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant2_public_key, [7u8; 32]);
+    assert_eq!(state.key_version, 0);
+    assert_eq!(state.message_count, 2);
+    let original = get_message(&mut context, &message).await;
+    assert!(!original.deleted);
+    assert_eq!(original.encrypted_data, vec![2u8; 16]);
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::*;
+use solana_program::{
+    instruction::{AccountMeta, InstructionError},
+    pubkey::Pubkey,
+    system_instruction,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::Account,
+    signature::{Keypair, Signer},
+};
+use whisperchain::{
+    error::WhisperChainError,
+    state::{BatchEntry, Chat, Message},
+};
+
+const FEE: u64 = 5_000_000;
+
+/// Initializes the config and sets `fee` payable to a fresh funded treasury
+async fn set_fee(context: &mut ProgramTestContext, program_id: &Pubkey, admin: &Keypair, fee: u64) -> Pubkey {
+    let init = init_config_ix(program_id, &admin.pubkey());
+    process(context, &[init], &[admin]).await.unwrap();
+
+    let treasury = funded_keypair(context).await.pubkey();
+    let set = set_fee_ix(program_id, &admin.pubkey(), fee, &treasury);
+    process(context, &[set], &[admin]).await.unwrap();
+    treasury
+}
+
+#[tokio::test]
+async fn fee_is_collected_into_the_treasury() {
+    let (mut context, program_id, admin) = start_with_upgrade_authority().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let treasury = set_fee(&mut context, &program_id, &admin, FEE).await;
+    let treasury_before = lamports(&mut context, &treasury).await;
+
+    let mut send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    send.accounts.push(AccountMeta::new(treasury, false));
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    assert_eq!(lamports(&mut context, &treasury).await, treasury_before + FEE);
+
+    let entries = (0..2)
+        .map(|byte| BatchEntry {
+            encrypted_data: vec![byte; 16],
+            ephemeral_public_key: [7u8; 32],
+            expires_at: 0,
+        })
+        .collect();
+    let mut batch = send_message_batch_ix(&program_id, &alice.pubkey(), &chat, 1, entries);
+    batch.accounts.push(AccountMeta::new(treasury, false));
+    process(&mut context, &[batch], &[&alice]).await.unwrap();
+    assert_eq!(lamports(&mut context, &treasury).await, treasury_before + 3 * FEE);
+
+    // The fee can't be skipped by leaving the treasury out
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 3, vec![1u8; 16], 0);
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::NotEnoughAccountKeys);
+}
+
+#[tokio::test]
+async fn zero_fee_needs_no_treasury() {
+    let (mut context, program_id, admin) = start_with_upgrade_authority().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let treasury = set_fee(&mut context, &program_id, &admin, 0).await;
+    let treasury_before = lamports(&mut context, &treasury).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 1);
+    assert_eq!(lamports(&mut context, &treasury).await, treasury_before);
+}
+
+#[tokio::test]
+async fn sender_must_cover_fee_and_rent() {
+    let (mut context, program_id, admin) = start_with_upgrade_authority().await;
+    let (_alice, chat) = create_chat(&mut context, &program_id).await;
+    let treasury = set_fee(&mut context, &program_id, &admin, FEE).await;
+
+    // Enough for the message's rent, but not for the fee on top
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let bob = Keypair::new();
+    let balance = rent.minimum_balance(Message::space(16, false, false)) + FEE - 1;
+    let fund = system_instruction::transfer(&context.payer.pubkey(), &bob.pubkey(), balance);
+    let payer = context.payer.insecure_clone();
+    process(&mut context, &[fund], &[&payer]).await.unwrap();
+
+    let mut send = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    send.accounts.push(AccountMeta::new(treasury, false));
+    let result = process(&mut context, &[send], &[&bob]).await;
+
+    assert_custom_error(result, WhisperChainError::InsufficientFunds);
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 0);
+}
+
+#[tokio::test]
+async fn fee_cant_be_skipped_by_leaving_out_the_config() {
+    let (mut context, program_id, admin) = start_with_upgrade_authority().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let treasury = set_fee(&mut context, &program_id, &admin, FEE).await;
+    let treasury_before = lamports(&mut context, &treasury).await;
+
+    let mut send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    send.accounts.pop();
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::NotEnoughAccountKeys);
+
+    let mut send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    send.accounts[5] = AccountMeta::new(treasury, false);
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::InvalidAccountData);
+
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 0);
+    assert_eq!(lamports(&mut context, &treasury).await, treasury_before);
+}
+
+#[tokio::test]
+async fn dead_lettered_send_pays_the_fee() {
+    let (mut context, program_id, admin) = start_with_upgrade_authority().await;
+    let treasury = set_fee(&mut context, &program_id, &admin, FEE).await;
+    let treasury_before = lamports(&mut context, &treasury).await;
+
+    let alice = funded_keypair(&mut context).await;
+    let chat = Pubkey::new_unique();
+    context.set_account(&chat, &Account::new(SOL, Chat::LEN, &program_id).into());
+    let dead_letter = dead_letter_pda(&program_id, &alice.pubkey(), &chat);
+
+    let mut send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    send.accounts.push(AccountMeta::new(dead_letter, false));
+    let result = process(&mut context, &[send.clone()], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::NotEnoughAccountKeys);
+
+    send.accounts.push(AccountMeta::new(treasury, false));
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    assert_eq!(lamports(&mut context, &treasury).await, treasury_before + FEE);
+    assert!(lamports(&mut context, &dead_letter).await > 0);
+}
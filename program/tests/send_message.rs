@@ -0,0 +1,518 @@
+mod common;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use common::*;
+use solana_sdk::{account::Account, signature::Signer};
+use solana_program::{instruction::InstructionError, pubkey::Pubkey, system_program};
+use whisperchain::{
+    error::WhisperChainError,
+    instruction::WhisperChainInstruction,
+    state::{ChatConfig, ChatInit, Message, SentMessage, MAX_EXPIRY_HORIZON_SECS, MAX_RECENT_NONCES},
+};
+
+#[tokio::test]
+async fn timestamps_come_from_the_clock() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    set_unix_timestamp(&mut context, 5_000).await;
+    let first = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    let second = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![2u8; 16], 6_000);
+    process(&mut context, &[first, second], &[&alice]).await.unwrap();
+
+    let first = get_message(&mut context, &message_pda(&program_id, &chat, 0)).await;
+    let second = get_message(&mut context, &message_pda(&program_id, &chat, 1)).await;
+    assert_eq!(first.timestamp, 5_000);
+    assert!(second.timestamp >= first.timestamp);
+    assert_eq!(second.expires_at, 6_000);
+
+    let chat = get_chat(&mut context, &chat).await;
+    assert_eq!(chat.message_count, 2);
+    assert_eq!(chat.last_message_at, second.timestamp);
+}
+
+#[tokio::test]
+async fn past_expiration_is_rejected() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    set_unix_timestamp(&mut context, 5_000).await;
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 5_000);
+    let result = process(&mut context, &[send], &[&alice]).await;
+
+    assert_custom_error(result, WhisperChainError::InvalidExpiration);
+}
+
+#[tokio::test]
+async fn expiration_beyond_the_horizon_is_rejected() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    set_unix_timestamp(&mut context, 5_000).await;
+    for expires_at in [5_001 + MAX_EXPIRY_HORIZON_SECS, i64::MAX, -1] {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], expires_at);
+        let result = process(&mut context, &[send], &[&alice]).await;
+        assert_custom_error(result, WhisperChainError::InvalidExpiration);
+    }
+
+    // Right at the horizon and anywhere before it is fine
+    for (index, expires_at) in [(0, 5_000 + MAX_EXPIRY_HORIZON_SECS), (1, 5_000 + 7 * 24 * 60 * 60)] {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![1u8; 16], expires_at);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+
+        let message = get_message(&mut context, &message_pda(&program_id, &chat, index)).await;
+        assert_eq!(message.expires_at, expires_at);
+    }
+}
+
+#[tokio::test]
+async fn default_ttl_applies_only_without_an_expiry() {
+    let (mut context, program_id) = start().await;
+    let config = ChatConfig {
+        default_ttl_secs: 3_600,
+        ..self_chat_config()
+    };
+    let (alice, chat) = create_chat_with_config(&mut context, &program_id, config).await;
+
+    set_unix_timestamp(&mut context, 5_000).await;
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    let message = get_message(&mut context, &message_pda(&program_id, &chat, 0)).await;
+    assert_eq!(message.expires_at, 8_600);
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![1u8; 16], 60_000);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+    let message = get_message(&mut context, &message_pda(&program_id, &chat, 1)).await;
+    assert_eq!(message.expires_at, 60_000);
+}
+
+#[tokio::test]
+async fn scheduled_message_is_hidden_until_visible_at() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    set_unix_timestamp(&mut context, 5_000).await;
+    let data = WhisperChainInstruction::SendMessage {
+        encrypted_data: vec![1u8; 16],
+        ephemeral_public_key: [7u8; 32],
+        expires_at: 0,
+        visible_at: 8_000,
+        client_timestamp: 0,
+        client_nonce: [0u8; 16],
+        reply_to: None,
+        show_sender: true,
+    };
+    let send = send_instruction(&program_id, &alice.pubkey(), &chat, 0, data);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message = get_message(&mut context, &message_pda(&program_id, &chat, 0)).await;
+    assert_eq!(message.visible_at, 8_000);
+    assert!(!message.is_visible(7_999));
+    assert!(message.is_visible(8_000));
+}
+
+#[tokio::test]
+async fn visible_at_before_send_time_is_rejected() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    set_unix_timestamp(&mut context, 5_000).await;
+    let data = WhisperChainInstruction::SendMessage {
+        encrypted_data: vec![1u8; 16],
+        ephemeral_public_key: [7u8; 32],
+        expires_at: 0,
+        visible_at: 4_999,
+        client_timestamp: 0,
+        client_nonce: [0u8; 16],
+        reply_to: None,
+        show_sender: true,
+    };
+    let send = send_instruction(&program_id, &alice.pubkey(), &chat, 0, data);
+    let result = process(&mut context, &[send], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+}
+
+#[tokio::test]
+async fn only_allowed_sizes_can_be_sent() {
+    let (mut context, program_id) = start().await;
+    let config = ChatConfig {
+        allowed_sizes: [128, 256, 0, 0],
+        ..self_chat_config()
+    };
+    let (alice, chat) = create_chat_with_config(&mut context, &program_id, config).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 256], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![1u8; 200], 0);
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::InvalidPadding);
+}
+
+#[tokio::test]
+async fn drifted_client_clock_is_flagged() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    set_unix_timestamp(&mut context, 10_000).await;
+    for (index, client_timestamp) in [(0, 9_990), (1, 2_000)] {
+        let data = WhisperChainInstruction::SendMessage {
+            encrypted_data: vec![1u8; 16],
+            ephemeral_public_key: [7u8; 32],
+            expires_at: 0,
+            visible_at: 0,
+            client_timestamp,
+            client_nonce: [0u8; 16],
+            reply_to: None,
+            show_sender: true,
+        };
+        let send = send_instruction(&program_id, &alice.pubkey(), &chat, index, data);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+
+    let close = get_message(&mut context, &message_pda(&program_id, &chat, 0)).await;
+    assert_eq!(close.client_timestamp, 9_990);
+    assert!(!close.clock_drift_flag);
+
+    let drifted = get_message(&mut context, &message_pda(&program_id, &chat, 1)).await;
+    assert_eq!(drifted.timestamp, 10_000);
+    assert!(drifted.clock_drift_flag);
+}
+
+#[tokio::test]
+async fn owner_cannot_send_alone_unless_self_chat_allowed() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) =
+        create_chat_with_config(&mut context, &program_id, ChatConfig::default()).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    // Once bob has joined, alice is no longer the only sender
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let (carol, notebook) = create_chat_with_config(&mut context, &program_id, self_chat_config()).await;
+    let send = send_message_ix(&program_id, &carol.pubkey(), &notebook, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&carol]).await.unwrap();
+
+    let state = get_chat(&mut context, &notebook).await;
+    assert_eq!(state.message_count, 1);
+    assert_eq!(state.participant2, Pubkey::default());
+}
+
+#[tokio::test]
+async fn quota_limits_one_participant_only() {
+    let (mut context, program_id) = start().await;
+    let config = ChatConfig {
+        participant2_quota: 2,
+        ..ChatConfig::default()
+    };
+    let (alice, chat) = create_chat_with_config(&mut context, &program_id, config).await;
+    let bob = funded_keypair(&mut context).await;
+
+    for index in 0..2 {
+        let send = send_message_ix(&program_id, &bob.pubkey(), &chat, index, vec![1u8; 16], 0);
+        process(&mut context, &[send], &[&bob]).await.unwrap();
+    }
+
+    let send = send_message_ix(&program_id, &bob.pubkey(), &chat, 2, vec![1u8; 16], 0);
+    let result = process(&mut context, &[send], &[&bob]).await;
+    assert_custom_error(result, WhisperChainError::QuotaExceeded);
+
+    // Alice has no quota and can keep going
+    for index in 2..5 {
+        let send = send_message_ix(&program_id, &alice.pubkey(), &chat, index, vec![1u8; 16], 0);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant1_sent_count, 3);
+    assert_eq!(state.participant2_sent_count, 2);
+}
+
+#[tokio::test]
+async fn existing_account_at_message_pda_is_reported() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    // Someone funded the PDA for index 0 before the send landed
+    let message = message_pda(&program_id, &chat, 0);
+    context.set_account(&message, &Account::new(SOL, 0, &system_program::id()).into());
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    let result = process(&mut context, &[send], &[&alice]).await;
+
+    assert_custom_error(result, WhisperChainError::AlreadyInitialized);
+}
+
+#[tokio::test]
+async fn replies_must_reference_an_existing_message() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let reply = |reply_to| WhisperChainInstruction::SendMessage {
+        encrypted_data: vec![2u8; 16],
+        ephemeral_public_key: [7u8; 32],
+        expires_at: 0,
+        visible_at: 0,
+        client_timestamp: 0,
+        client_nonce: [0u8; 16],
+        reply_to: Some(reply_to),
+        show_sender: true,
+    };
+
+    let send = send_instruction(&program_id, &alice.pubkey(), &chat, 1, reply(0));
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let send = send_instruction(&program_id, &alice.pubkey(), &chat, 2, reply(5));
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+
+    let top_level = get_message(&mut context, &message_pda(&program_id, &chat, 0)).await;
+    let threaded = get_message(&mut context, &message_pda(&program_id, &chat, 1)).await;
+    assert_eq!(top_level.reply_to, None);
+    assert_eq!(threaded.reply_to, Some(0));
+}
+
+#[tokio::test]
+async fn rate_limit_applies_per_sender() {
+    let (mut context, program_id) = start().await;
+    let config = ChatConfig {
+        min_interval_secs: 60,
+        ..self_chat_config()
+    };
+    let (alice, chat) = create_chat_with_config(&mut context, &program_id, config).await;
+    let bob = funded_keypair(&mut context).await;
+
+    set_unix_timestamp(&mut context, 10_000).await;
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    // Bob's first message isn't held back by alice's
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 1, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+
+    set_unix_timestamp(&mut context, 10_059).await;
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 2, vec![1u8; 16], 0);
+    let result = process(&mut context, &[send], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::RateLimited);
+
+    set_unix_timestamp(&mut context, 10_060).await;
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 2, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant1_last_sent_at, 10_060);
+    assert_eq!(state.participant2_last_sent_at, 10_000);
+}
+
+#[tokio::test]
+async fn hidden_sender_is_stored_as_a_commitment() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let data = WhisperChainInstruction::SendMessage {
+        encrypted_data: vec![2u8; 16],
+        ephemeral_public_key: [7u8; 32],
+        expires_at: 0,
+        visible_at: 0,
+        client_timestamp: 0,
+        client_nonce: [0u8; 16],
+        reply_to: None,
+        show_sender: false,
+    };
+    let send = send_instruction(&program_id, &alice.pubkey(), &chat, 1, data);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let attributed = get_message(&mut context, &message_pda(&program_id, &chat, 0)).await;
+    assert!(attributed.show_sender);
+    assert_eq!(attributed.sender, alice.pubkey());
+
+    let unattributed = get_message(&mut context, &message_pda(&program_id, &chat, 1)).await;
+    assert!(!unattributed.show_sender);
+    assert_eq!(unattributed.sender, Message::sender_commitment(&alice.pubkey(), &chat, 1));
+    assert!(unattributed.is_sent_by(&alice.pubkey()));
+
+    // The commitment still lets alice delete her own message
+    let delete = delete_message_ix(
+        &program_id,
+        &alice.pubkey(),
+        &message_pda(&program_id, &chat, 1),
+        &chat,
+        &alice.pubkey(),
+    );
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.live_message_count, 1);
+}
+
+#[tokio::test]
+async fn send_at_max_message_count_overflows_gracefully() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let mut account = context.banks_client.get_account(chat).await.unwrap().unwrap();
+    let mut state = get_chat(&mut context, &chat).await;
+    state.message_count = u64::MAX;
+    account.data = state.try_to_vec().unwrap();
+    context.set_account(&chat, &account.into());
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, u64::MAX, vec![1u8; 16], 0);
+    let result = process(&mut context, &[send], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::ArithmeticOverflow);
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, u64::MAX);
+}
+
+#[tokio::test]
+async fn send_at_max_sent_count_overflows_gracefully() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let mut account = context.banks_client.get_account(chat).await.unwrap().unwrap();
+    let mut state = get_chat(&mut context, &chat).await;
+    state.participant1_sent_count = u64::MAX;
+    account.data = state.try_to_vec().unwrap();
+    context.set_account(&chat, &account.into());
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    let result = process(&mut context, &[send], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::ArithmeticOverflow);
+}
+
+#[tokio::test]
+async fn copied_chat_account_at_wrong_address_is_rejected() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    // Program-owned and a valid chat, but not at alice's chat PDA
+    let account = context.banks_client.get_account(chat).await.unwrap().unwrap();
+    let copy = Pubkey::new_unique();
+    context.set_account(&copy, &account.into());
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &copy, 0, vec![1u8; 16], 0);
+    let result = process(&mut context, &[send], &[&alice]).await;
+
+    assert_instruction_error(result, InstructionError::InvalidSeeds);
+}
+
+#[tokio::test]
+async fn batch_created_chat_accepts_sends_from_both_participants() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let entry = ChatInit { counterpart: bob.pubkey(), public_key: [2u8; 32] };
+    let ix = initialize_chats_batch_ix(&program_id, &alice.pubkey(), vec![entry]);
+    process(&mut context, &[ix], &[&alice]).await.unwrap();
+    let chat = batch_chat_pda(&program_id, &alice.pubkey(), &bob.pubkey());
+
+    let send = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&bob]).await.unwrap();
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 2);
+}
+
+#[tokio::test]
+async fn send_returns_the_created_message_account() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    let return_data = simulated_return_data(&mut context, std::slice::from_ref(&send), &[&alice])
+        .await
+        .unwrap();
+    assert_eq!(return_data.program_id, program_id);
+    assert_eq!(return_data.data.len(), SentMessage::LEN);
+
+    let (expected, bump) = Pubkey::find_program_address(
+        &[b"message", chat.as_ref(), &1u64.to_le_bytes()],
+        &program_id,
+    );
+    assert_eq!(expected, message_pda(&program_id, &chat, 1));
+    assert_eq!(
+        SentMessage::try_from_slice(&return_data.data).unwrap(),
+        SentMessage {
+            message: expected,
+            index: 1,
+            bump,
+        }
+    );
+}
+
+#[tokio::test]
+async fn recent_nonce_is_rejected_as_duplicate() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send_with_nonce = |index, client_nonce| {
+        let data = WhisperChainInstruction::SendMessage {
+            encrypted_data: vec![1u8; 16],
+            ephemeral_public_key: [7u8; 32],
+            expires_at: 0,
+            visible_at: 0,
+            client_timestamp: 0,
+            client_nonce,
+            reply_to: None,
+            show_sender: true,
+        };
+        send_instruction(&program_id, &alice.pubkey(), &chat, index, data)
+    };
+
+    process(&mut context, &[send_with_nonce(0, [1u8; 16])], &[&alice]).await.unwrap();
+    let message = get_message(&mut context, &message_pda(&program_id, &chat, 0)).await;
+    assert_eq!(message.client_nonce, [1u8; 16]);
+
+    // A retry of the landed send is refused and takes no index
+    let result = process(&mut context, &[send_with_nonce(1, [1u8; 16])], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::DuplicateNonce);
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 1);
+
+    process(&mut context, &[send_with_nonce(1, [2u8; 16])], &[&alice]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 2);
+}
+
+#[tokio::test]
+async fn nonce_can_be_reused_once_it_leaves_the_window() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send_with_nonce = |index: u64, client_nonce| {
+        let data = WhisperChainInstruction::SendMessage {
+            encrypted_data: vec![1u8; 16],
+            ephemeral_public_key: [7u8; 32],
+            expires_at: 0,
+            visible_at: 0,
+            client_timestamp: 0,
+            client_nonce,
+            reply_to: None,
+            show_sender: true,
+        };
+        send_instruction(&program_id, &alice.pubkey(), &chat, index, data)
+    };
+
+    for index in 0..=MAX_RECENT_NONCES as u64 {
+        let send = send_with_nonce(index, [index as u8 + 1; 16]);
+        process(&mut context, &[send], &[&alice]).await.unwrap();
+    }
+
+    let index = MAX_RECENT_NONCES as u64 + 1;
+    process(&mut context, &[send_with_nonce(index, [1u8; 16])], &[&alice]).await.unwrap();
+}
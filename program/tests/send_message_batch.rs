@@ -0,0 +1,154 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::*;
+use solana_program::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use whisperchain::{
+    error::WhisperChainError,
+    state::{BatchEntry, BatchSent, ChatConfig, MAX_MESSAGES_PER_BATCH},
+};
+
+fn entry(byte: u8, expires_at: i64) -> BatchEntry {
+    BatchEntry {
+        encrypted_data: vec![byte; 16],
+        ephemeral_public_key: [7u8; 32],
+        expires_at,
+    }
+}
+
+#[tokio::test]
+async fn batch_creates_consecutive_messages() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let batch = send_message_batch_ix(
+        &program_id,
+        &alice.pubkey(),
+        &chat,
+        1,
+        vec![entry(2, 0), entry(3, 0), entry(4, 0)],
+    );
+    process(&mut context, &[batch], &[&alice]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.message_count, 4);
+    assert_eq!(state.live_message_count, 4);
+    assert_eq!(state.participant1_sent_count, 4);
+
+    for index in 1..4u64 {
+        let message = get_message(&mut context, &message_pda(&program_id, &chat, index)).await;
+        assert_eq!(message.index, index);
+        assert_eq!(message.chat, chat);
+        assert_eq!(message.sender, alice.pubkey());
+        assert_eq!(message.encrypted_data, vec![index as u8 + 1; 16]);
+    }
+}
+
+#[tokio::test]
+async fn batch_returns_the_next_index_and_count() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let batch = send_message_batch_ix(&program_id, &alice.pubkey(), &chat, 1, vec![entry(2, 0), entry(3, 0)]);
+    let return_data = simulated_return_data(&mut context, std::slice::from_ref(&batch), &[&alice])
+        .await
+        .unwrap();
+    assert_eq!(return_data.program_id, program_id);
+    assert_eq!(
+        BatchSent::try_from_slice(&return_data.data).unwrap(),
+        BatchSent {
+            next_index: 3,
+            count: 2,
+        }
+    );
+
+    process(&mut context, &[batch], &[&alice]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 3);
+}
+
+#[tokio::test]
+async fn batch_size_is_bounded() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    let empty = send_message_batch_ix(&program_id, &alice.pubkey(), &chat, 0, vec![]);
+    let result = process(&mut context, &[empty], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+
+    let oversized = send_message_batch_ix(
+        &program_id,
+        &alice.pubkey(),
+        &chat,
+        0,
+        vec![entry(1, 0); MAX_MESSAGES_PER_BATCH + 1],
+    );
+    let result = process(&mut context, &[oversized], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+}
+
+#[tokio::test]
+async fn invalid_entry_rejects_the_whole_batch() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    set_unix_timestamp(&mut context, 5_000).await;
+    let batch = send_message_batch_ix(
+        &program_id,
+        &alice.pubkey(),
+        &chat,
+        0,
+        vec![entry(1, 0), entry(2, 4_000)],
+    );
+    let result = process(&mut context, &[batch], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::InvalidExpiration);
+
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 0);
+    assert!(context
+        .banks_client
+        .get_account(message_pda(&program_id, &chat, 0))
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn batch_counts_once_against_the_rate_limit() {
+    let (mut context, program_id) = start().await;
+    let config = ChatConfig {
+        min_interval_secs: 60,
+        ..self_chat_config()
+    };
+    let (alice, chat) = create_chat_with_config(&mut context, &program_id, config).await;
+
+    set_unix_timestamp(&mut context, 10_000).await;
+    let batch = send_message_batch_ix(
+        &program_id,
+        &alice.pubkey(),
+        &chat,
+        0,
+        vec![entry(1, 0), entry(2, 0), entry(3, 0)],
+    );
+    process(&mut context, &[batch], &[&alice]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 3);
+
+    // The next batch still has to wait out the interval
+    set_unix_timestamp(&mut context, 10_059).await;
+    let batch = send_message_batch_ix(&program_id, &alice.pubkey(), &chat, 3, vec![entry(4, 0), entry(5, 0)]);
+    let result = process(&mut context, &[batch], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::RateLimited);
+
+    set_unix_timestamp(&mut context, 10_060).await;
+    let batch = send_message_batch_ix(&program_id, &alice.pubkey(), &chat, 3, vec![entry(4, 0), entry(5, 0)]);
+    process(&mut context, &[batch], &[&alice]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.message_count, 5);
+    assert_eq!(state.participant1_last_sent_at, 10_060);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::*;
+use solana_program::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use whisperchain::error::WhisperChainError;
+
+#[tokio::test]
+async fn blocked_participant_cannot_send_until_unblocked() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+
+    let block = set_blocked_ix(&program_id, &alice.pubkey(), &chat, &bob.pubkey(), true);
+    process(&mut context, &[block], &[&alice]).await.unwrap();
+    assert!(get_chat(&mut context, &chat).await.is_blocked(&bob.pubkey()));
+
+    let send = send_message_ix(&program_id, &bob.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    let result = process(&mut context, &[send], &[&bob]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    // Blocking is one-way: alice can still write to bob
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![3u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let unblock = set_blocked_ix(&program_id, &alice.pubkey(), &chat, &bob.pubkey(), false);
+    process(&mut context, &[unblock], &[&alice]).await.unwrap();
+
+    let send = send_message_ix(&program_id, &bob.pubkey(), &chat, 2, vec![2u8; 16], 0);
+    process(&mut context, &[send], &[&bob]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 3);
+}
+
+#[tokio::test]
+async fn only_the_other_participant_can_be_blocked() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+    let mallory = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+
+    let block = set_blocked_ix(&program_id, &alice.pubkey(), &chat, &mallory.pubkey(), true);
+    let result = process(&mut context, &[block], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+
+    let block = set_blocked_ix(&program_id, &mallory.pubkey(), &chat, &alice.pubkey(), true);
+    let result = process(&mut context, &[block], &[&mallory]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::*;
+use solana_program::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, state::Message};
+
+#[tokio::test]
+async fn soft_delete_keeps_position_and_wipes_ciphertext() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+
+    set_unix_timestamp(&mut context, 1_000).await;
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 0, vec![1u8; 256], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+
+    let message_key = message_pda(&program_id, &chat, 0);
+    let rent_before = lamports(&mut context, &message_key).await;
+
+    let delete = soft_delete_message_ix(&program_id, &alice.pubkey(), &message_key, &chat);
+    process(&mut context, &[delete], &[&alice]).await.unwrap();
+
+    let message = get_message(&mut context, &message_key).await;
+    assert!(message.deleted);
+    assert!(message.encrypted_data.is_empty());
+    assert_eq!(message.index, 0);
+    assert_eq!(message.timestamp, 1_000);
+
+    let account = context
+        .banks_client
+        .get_account(message_key)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), Message::space(0, false, false));
+    assert!(account.lamports < rent_before);
+
+    // The tombstone can't be edited back into a live message
+    let edit = edit_message_ix(&program_id, &alice.pubkey(), &message_key, &chat, vec![2u8; 16]);
+    let result = process(&mut context, &[edit], &[&alice]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+
+    // Later sends still land at the next index
+    let send = send_message_ix(&program_id, &alice.pubkey(), &chat, 1, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&alice]).await.unwrap();
+}
+
+#[tokio::test]
+async fn only_sender_can_soft_delete() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let send = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&bob]).await.unwrap();
+
+    let message_key = message_pda(&program_id, &chat, 0);
+    let delete = soft_delete_message_ix(&program_id, &alice.pubkey(), &message_key, &chat);
+    let result = process(&mut context, &[delete], &[&alice]).await;
+
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+    assert!(!get_message(&mut context, &message_key).await.deleted);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::*;
+use solana_program::{instruction::AccountMeta, pubkey::Pubkey};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::signature::Signer;
+use whisperchain::state::{ChatInit, Stats};
+
+async fn get_stats(context: &mut ProgramTestContext, program_id: &Pubkey) -> Stats {
+    let account = context
+        .banks_client
+        .get_account(stats_pda(program_id))
+        .await
+        .unwrap()
+        .expect("stats account exists");
+    Stats::unpack(&account.data).unwrap()
+}
+
+#[tokio::test]
+async fn counters_track_chats_and_messages() {
+    let (mut context, program_id) = start().await;
+    let stats = AccountMeta::new(stats_pda(&program_id), false);
+
+    let mut chats = Vec::new();
+    for _ in 0..2 {
+        let alice = funded_keypair(&mut context).await;
+        let mut init = initialize_chat_ix(&program_id, &alice.pubkey(), [1u8; 32]);
+        init.accounts.push(stats.clone());
+        process(&mut context, &[init], &[&alice]).await.unwrap();
+        chats.push(chat_pda(&program_id, &alice.pubkey()));
+    }
+
+    let counts = get_stats(&mut context, &program_id).await;
+    assert_eq!(counts.total_chats, 2);
+    assert_eq!(counts.total_messages, 0);
+
+    let bob = funded_keypair(&mut context).await;
+    for chat in &chats {
+        for index in 0..2 {
+            let mut send = send_message_ix(&program_id, &bob.pubkey(), chat, index, vec![1u8; 16], 0);
+            send.accounts.push(stats.clone());
+            process(&mut context, &[send], &[&bob]).await.unwrap();
+        }
+    }
+
+    let counts = get_stats(&mut context, &program_id).await;
+    assert_eq!(counts.total_chats, 2);
+    assert_eq!(counts.total_messages, 4);
+}
+
+#[tokio::test]
+async fn omitting_the_stats_account_still_works() {
+    let (mut context, program_id) = start().await;
+    let (_alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let send = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[send], &[&bob]).await.unwrap();
+    assert_eq!(get_chat(&mut context, &chat).await.message_count, 1);
+
+    let stats = stats_pda(&program_id);
+    assert!(context.banks_client.get_account(stats).await.unwrap().is_none());
+
+    // Counting starts whenever a client first passes the account
+    let mut send = send_message_ix(&program_id, &bob.pubkey(), &chat, 1, vec![1u8; 16], 0);
+    send.accounts.push(AccountMeta::new(stats, false));
+    process(&mut context, &[send], &[&bob]).await.unwrap();
+
+    let counts = get_stats(&mut context, &program_id).await;
+    assert_eq!(counts.total_chats, 0);
+    assert_eq!(counts.total_messages, 1);
+}
+
+#[tokio::test]
+async fn batch_counts_every_chat() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+
+    let entries = (0..3)
+        .map(|_| ChatInit {
+            counterpart: Pubkey::new_unique(),
+            public_key: [1u8; 32],
+        })
+        .collect();
+    let mut batch = initialize_chats_batch_ix(&program_id, &alice.pubkey(), entries);
+    batch.accounts.push(AccountMeta::new(stats_pda(&program_id), false));
+    process(&mut context, &[batch], &[&alice]).await.unwrap();
+
+    let counts = get_stats(&mut context, &program_id).await;
+    assert_eq!(counts.total_chats, 3);
+    assert_eq!(counts.total_messages, 0);
+}
+
+#[tokio::test]
+async fn pair_chat_is_counted() {
+    let (mut context, program_id) = start().await;
+    let alice = funded_keypair(&mut context).await;
+
+    let mut init = initialize_chat_pair_ix(&program_id, &alice.pubkey(), &Pubkey::new_unique(), [1u8; 32]);
+    init.accounts.push(AccountMeta::new(stats_pda(&program_id), false));
+    process(&mut context, &[init], &[&alice]).await.unwrap();
+
+    let counts = get_stats(&mut context, &program_id).await;
+    assert_eq!(counts.total_chats, 1);
+    assert_eq!(counts.total_messages, 0);
+}
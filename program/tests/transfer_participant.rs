@@ -0,0 +1,82 @@
+mod common;
+
+use common::*;
+use solana_program::{instruction::InstructionError, pubkey::Pubkey};
+use solana_sdk::signature::Signer;
+use whisperchain::{error::WhisperChainError, state::ChatInit};
+
+#[tokio::test]
+async fn participant2_moves_to_a_new_wallet() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+    let bob_new = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+
+    let transfer = transfer_participant_ix(&program_id, &bob.pubkey(), &chat, &bob_new.pubkey(), [9u8; 32]);
+    process(&mut context, &[transfer], &[&bob]).await.unwrap();
+
+    let state = get_chat(&mut context, &chat).await;
+    assert_eq!(state.participant1, alice.pubkey());
+    assert_eq!(state.participant2, bob_new.pubkey());
+    assert_eq!(state.participant2_public_key, [9u8; 32]);
+    assert_eq!(state.participant2_sent_count, 1);
+    assert_eq!(state.key_version, 1);
+
+    // The old wallet is out and the new one can carry on the conversation
+    let send = send_message_ix(&program_id, &bob.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    let result = process(&mut context, &[send], &[&bob]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    let send = send_message_ix(&program_id, &bob_new.pubkey(), &chat, 1, vec![2u8; 16], 0);
+    process(&mut context, &[send], &[&bob_new]).await.unwrap();
+}
+
+#[tokio::test]
+async fn transfer_cannot_collide_with_participant1() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let bob = funded_keypair(&mut context).await;
+
+    let join = send_message_ix(&program_id, &bob.pubkey(), &chat, 0, vec![1u8; 16], 0);
+    process(&mut context, &[join], &[&bob]).await.unwrap();
+
+    let transfer = transfer_participant_ix(&program_id, &bob.pubkey(), &chat, &alice.pubkey(), [9u8; 32]);
+    let result = process(&mut context, &[transfer], &[&bob]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    let transfer = transfer_participant_ix(&program_id, &bob.pubkey(), &chat, &Pubkey::default(), [9u8; 32]);
+    let result = process(&mut context, &[transfer], &[&bob]).await;
+    assert_instruction_error(result, InstructionError::InvalidArgument);
+
+    assert_eq!(get_chat(&mut context, &chat).await.participant2, bob.pubkey());
+}
+
+#[tokio::test]
+async fn participant1_and_batch_chats_cannot_transfer() {
+    let (mut context, program_id) = start().await;
+    let (alice, chat) = create_chat(&mut context, &program_id).await;
+    let carol = funded_keypair(&mut context).await;
+
+    let transfer = transfer_participant_ix(&program_id, &alice.pubkey(), &chat, &carol.pubkey(), [9u8; 32]);
+    let result = process(&mut context, &[transfer], &[&alice]).await;
+    assert_custom_error(result, WhisperChainError::NotAuthorized);
+
+    let bob = funded_keypair(&mut context).await;
+    let batch = initialize_chats_batch_ix(
+        &program_id,
+        &alice.pubkey(),
+        vec![ChatInit {
+            counterpart: bob.pubkey(),
+            public_key: [1u8; 32],
+        }],
+    );
+    process(&mut context, &[batch], &[&alice]).await.unwrap();
+
+    let batch_chat = batch_chat_pda(&program_id, &alice.pubkey(), &bob.pubkey());
+    let transfer = transfer_participant_ix(&program_id, &bob.pubkey(), &batch_chat, &carol.pubkey(), [9u8; 32]);
+    let result = process(&mut context, &[transfer], &[&bob]).await;
+    assert_instruction_error(result, InstructionError::InvalidSeeds);
+}